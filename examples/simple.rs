@@ -15,37 +15,37 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
 
     let leaf_current_dir = cv.add_leaf(Leaf::StringId(leaf::StringId {
         substr: LeafId::null(),
-        content: "D:\\test".to_string(),
-    }));
+        content: "D:\\test".into(),
+    }))?;
 
     let leaf_build_tool = cv.add_leaf(Leaf::StringId(leaf::StringId {
         substr: LeafId::null(),
-        content: "cl.exe".to_string(),
-    }));
+        content: "cl.exe".into(),
+    }))?;
 
     let leaf_source_file = cv.add_leaf(Leaf::StringId(leaf::StringId {
         substr: LeafId::null(),
-        content: "main.cpp".to_string(),
-    }));
+        content: "main.cpp".into(),
+    }))?;
 
     let leaf_program_database_file = cv.add_leaf(Leaf::StringId(leaf::StringId {
         substr: LeafId::null(),
-        content: "D:\\test\\vc140.pdb".to_string(),
-    }));
+        content: "D:\\test\\vc140.pdb".into(),
+    }))?;
 
     let leaf_command_args_sub = cv.add_leaf(Leaf::StringId(leaf::StringId {
         substr: LeafId::null(),
-        content: "-Z7".to_string(),
-    }));
+        content: "-Z7".into(),
+    }))?;
 
     let leaf_command_args_sub_list = cv.add_leaf(Leaf::SubstrList(leaf::SubstrList {
         strings: vec![leaf_command_args_sub],
-    }));
+    }))?;
 
     let leaf_command_args = cv.add_leaf(Leaf::StringId(leaf::StringId {
         substr: leaf_command_args_sub_list,
-        content: "-I\"a\" -I\"b\"".to_string(),
-    }));
+        content: "-I\"a\" -I\"b\"".into(),
+    }))?;
 
     let leaf_build_info = cv.add_leaf(Leaf::BuildInfo(leaf::BuildInfo::new(
         leaf_current_dir,
@@ -53,7 +53,7 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
         leaf_source_file,
         leaf_program_database_file,
         leaf_command_args,
-    )));
+    )))?;
 
     cv.add_subsection(Subsection::Symbols(vec![Symbol::BuildInfo(
         symbol::BuildInfo {
@@ -71,56 +71,50 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
             obj: RefMut<'a, object::write::Object>,
             section: object::write::SectionId,
         }
-        impl<'a> Writer<'a> {
-            fn add_reloc(
+        impl<'a> SectionWrite<String> for Writer<'a> {
+            type Error = object::write::Error;
+
+            fn write(&mut self, data: &[u8]) -> std::result::Result<(), Self::Error> {
+                self.obj.append_section_data(self.section, data, 1);
+                Ok(())
+            }
+
+            fn write_reloc(
                 &mut self,
-                reloc: &str,
-                kind: object::RelocationKind,
-                size: u8,
-            ) -> std::result::Result<(), object::write::Error> {
+                kind: RelocKind,
+                reloc: &String,
+                addend: i64,
+            ) -> std::result::Result<(), Self::Error> {
                 let symbol = self
                     .obj
                     .symbol_id(reloc.as_bytes())
                     .expect("Undefiend symbol");
+                let size = kind.size();
+                let offset = self
+                    .obj
+                    .append_section_data(self.section, &[0; 8][0..size], 1);
+                let kind = match kind {
+                    RelocKind::Addr32Nb => object::RelocationKind::ImageOffset,
+                    RelocKind::Section => object::RelocationKind::SectionIndex,
+                    RelocKind::SecRel => object::RelocationKind::SectionOffset,
+                    RelocKind::Addr32 | RelocKind::Addr64 => object::RelocationKind::Absolute,
+                    RelocKind::Token => object::RelocationKind::Coff(
+                        kind.coff_type(MACHINE_X64).expect("Unsupported relocation"),
+                    ),
+                };
                 self.obj.add_relocation(
                     self.section,
                     object::write::Relocation {
-                        offset: 0,
-                        size: size * 8,
+                        offset,
+                        size: size as u8 * 8,
                         kind,
                         encoding: object::RelocationEncoding::Generic,
                         symbol,
-                        addend: 0,
+                        addend,
                     },
                 )
             }
         }
-        impl<'a> SectionWrite<String> for Writer<'a> {
-            type Error = object::write::Error;
-
-            fn write(&mut self, data: &[u8]) -> std::result::Result<(), Self::Error> {
-                self.obj.append_section_data(self.section, data, 1);
-                Ok(())
-            }
-
-            fn write_rva(&mut self, reloc: &String) -> std::result::Result<(), Self::Error> {
-                self.add_reloc(reloc, object::RelocationKind::ImageOffset, 4)?;
-                self.obj.append_section_data(self.section, &[0; 4], 1);
-                Ok(())
-            }
-
-            fn write_section(&mut self, reloc: &String) -> std::result::Result<(), Self::Error> {
-                self.add_reloc(reloc, object::RelocationKind::SectionIndex, 2)?;
-                self.obj.append_section_data(self.section, &[0; 2], 1);
-                Ok(())
-            }
-
-            fn write_secrel(&mut self, reloc: &String) -> std::result::Result<(), Self::Error> {
-                self.add_reloc(reloc, object::RelocationKind::SectionOffset, 4)?;
-                self.obj.append_section_data(self.section, &[0; 4], 1);
-                Ok(())
-            }
-        }
 
         Writer { obj, section }
     })?;
@@ -1,9 +1,44 @@
-use crate::error::{wu, Error};
+use crate::encoding::StringPolicy;
+use crate::error::Error;
 use crate::leaf::{self, Leaf, LeafId};
 use crate::section_write::SectionWrite;
+use crate::string_table::{StringOffset, StringTable};
 use crate::subsection::Subsection;
-use crate::symbol;
-use std::convert::*;
+use crate::validate::{self, ValidationError};
+use crate::writer::{self, SymbolStream, TypeStream};
+
+/// Where the types of an object come from.
+#[derive(Debug, Clone, Default)]
+pub(crate) enum TypeOrigin {
+    /// All types are in `.debug$T`.
+    #[default]
+    Local,
+
+    /// Types are in an external type server.
+    TypeServer(leaf::TypeServer2),
+
+    /// Types continue from precompiled types in another object.
+    Precomp(leaf::Precomp),
+
+    /// Types are precompiled types shared with other objects, and are written to `.debug$P`.
+    EndPrecomp(leaf::EndPrecomp),
+}
+
+impl TypeOrigin {
+    pub(crate) fn first_leaf_index(&self) -> usize {
+        match self {
+            TypeOrigin::Precomp(precomp) => precomp.start as usize + precomp.count as usize,
+            _ => 0x1000,
+        }
+    }
+
+    pub(crate) fn section_name(&self) -> &'static str {
+        match self {
+            TypeOrigin::EndPrecomp(_) => ".debug$P",
+            _ => ".debug$T",
+        }
+    }
+}
 
 /// CodeView information for an object.
 ///
@@ -12,6 +47,10 @@ use std::convert::*;
 pub struct Codeview<Reloc> {
     leafs: Vec<Leaf>,
     subsections: Vec<Subsection<Reloc>>,
+    strings: StringTable,
+    types: TypeOrigin,
+    validate_on_write: bool,
+    string_policy: StringPolicy,
 }
 
 impl<Reloc> Codeview<Reloc> {
@@ -20,68 +59,459 @@ impl<Reloc> Codeview<Reloc> {
         Codeview {
             leafs: vec![],
             subsections: vec![],
+            strings: StringTable::default(),
+            types: TypeOrigin::Local,
+            validate_on_write: false,
+            string_policy: StringPolicy::NulTerminated,
+        }
+    }
+
+    /// Create a new `Codeview` instance whose types are in an external type server.
+    ///
+    /// `.debug$T` starts with the type server reference. Leaf indices refer to types in the
+    /// type server, so leaves can't be added to this instance.
+    pub fn with_type_server(type_server: leaf::TypeServer2) -> Codeview<Reloc> {
+        Codeview {
+            types: TypeOrigin::TypeServer(type_server),
+            ..Codeview::new()
+        }
+    }
+
+    /// Create a new `Codeview` instance that uses precompiled types from another object.
+    ///
+    /// `.debug$T` starts with the precompiled types reference, and added leaves are numbered
+    /// after the precompiled types.
+    pub fn with_precomp(precomp: leaf::Precomp) -> Codeview<Reloc> {
+        Codeview {
+            types: TypeOrigin::Precomp(precomp),
+            ..Codeview::new()
         }
     }
 
-    pub fn add_leaf(&mut self, leaf: Leaf) -> LeafId {
+    /// Create a new `Codeview` instance whose types are shared as precompiled types.
+    ///
+    /// Leaves are written to `.debug$P` instead of `.debug$T`, followed by the end of
+    /// precompiled types with the given signature.
+    pub fn with_end_precomp(signature: u32) -> Codeview<Reloc> {
+        Codeview {
+            types: TypeOrigin::EndPrecomp(leaf::EndPrecomp { signature }),
+            ..Codeview::new()
+        }
+    }
+
+    fn first_leaf_index(&self) -> usize {
+        self.types.first_leaf_index()
+    }
+
+    /// Add a leaf and return its leaf index.
+    ///
+    /// A `Leaf::FieldList` too large for a single record is split into multiple records chained
+    /// by `Field::Index`, and the index of the record containing the first members is returned.
+    ///
+    /// Fails with `Error::LeafIndexOverflow` if there are no leaf indices left, and with
+    /// `Error::TypeServerLeaf` if types are in a type server.
+    pub fn add_leaf(&mut self, leaf: Leaf) -> Result<LeafId, Error> {
+        writer::add_leaf::<Reloc, _>(leaf, |leaf| self.push_leaf(leaf))
+    }
+
+    fn push_leaf(&mut self, leaf: Leaf) -> Result<LeafId, Error> {
+        if let TypeOrigin::TypeServer(_) = self.types {
+            return Err(Error::TypeServerLeaf);
+        }
+        let id = writer::leaf_id(self.leafs.len() + self.first_leaf_index())?;
         self.leafs.push(leaf);
-        LeafId(
-            (self.leafs.len() - 1 + 0x1000)
-                .try_into()
-                .expect("Too many leaves"),
-        )
+        Ok(id)
+    }
+
+    /// Get a previously added leaf.
+    pub fn leaf(&self, id: LeafId) -> Option<&Leaf> {
+        (id.0 as usize)
+            .checked_sub(self.first_leaf_index())
+            .and_then(|index| self.leafs.get(index))
+    }
+
+    /// Get the added leaves, unless leaf indices refer to types elsewhere.
+    pub(crate) fn local_leafs(&self) -> Option<&[Leaf]> {
+        match self.types {
+            TypeOrigin::Local => Some(&self.leafs),
+            _ => None,
+        }
     }
 
     pub fn add_subsection(&mut self, subsection: Subsection<Reloc>) {
         self.subsections.push(subsection);
     }
 
+    /// Get the added subsections.
+    #[cfg(feature = "gimli")]
+    pub(crate) fn subsections(&self) -> &[Subsection<Reloc>] {
+        &self.subsections
+    }
+
+    /// Add a string to the string table subsection.
+    ///
+    /// Adding the same string multiple times returns the same offset.
+    pub fn add_string(&mut self, string: &str) -> Result<StringOffset, Error> {
+        Ok(self.strings.add(string)?)
+    }
+
+    /// Check that leaf indices in leaves and symbols refer to existing leaves of the expected
+    /// kind, that leaves only refer to earlier leaves, and that scope symbols are matched.
+    ///
+    /// Leaf indices referring to an external type server or precompiled types are not checked.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let leaves = validate::Leaves {
+            leafs: &self.leafs,
+            first_index: self.first_leaf_index() as u32,
+            external: matches!(self.types, TypeOrigin::TypeServer(_)),
+        };
+        validate::validate_leaves(&leaves)?;
+        validate::validate_subsections(&leaves, &self.subsections)
+    }
+
+    /// Set whether `write` calls `validate` before writing anything.
+    pub fn set_validate_on_write(&mut self, validate_on_write: bool) {
+        self.validate_on_write = validate_on_write;
+    }
+
+    /// Set how strings in records are encoded. Strings in the string table subsection are always
+    /// NUL-terminated. See `StringPolicy` for strings in a code page.
+    pub fn set_string_policy(&mut self, string_policy: StringPolicy) {
+        self.string_policy = string_policy;
+    }
+
     /// Write CodeView information to object sections.
     pub fn write<W, F>(&self, mut writer_factory: F) -> Result<(), Error<W::Error>>
     where
         W: SectionWrite<Reloc>,
         F: FnMut(&str) -> W,
     {
-        let mut type_section = writer_factory(".debug$T");
-        wu(type_section.write(&4u32.to_le_bytes()))?;
+        if self.validate_on_write {
+            self.validate()?;
+        }
 
+        let type_section = writer_factory(self.types.section_name());
+        let mut types = TypeStream::new(type_section, &self.types, self.string_policy)?;
         for leaf in &self.leafs {
-            leaf::write(&leaf, &mut type_section)?;
+            types.write_leaf(leaf)?;
         }
+        drop(types.finish()?);
 
-        drop(type_section);
+        let symbol_section = writer_factory(".debug$S");
+        let mut symbols = SymbolStream::new(symbol_section, self.string_policy)?;
+        for subsection in &self.subsections {
+            symbols.add_subsection(subsection)?;
+        }
+        symbols.finish(&self.strings)?;
 
-        let mut symbol_section = writer_factory(".debug$S");
-        wu(symbol_section.write(&4u32.to_le_bytes()))?;
+        Ok(())
+    }
+}
 
-        for subsection in &self.subsections {
-            match subsection {
-                Subsection::Symbols(symbols) => {
-                    let len = symbols.iter().map(symbol::size::<Reloc>).sum::<usize>();
-                    wu(write_subsection_header(
-                        &mut symbol_section,
-                        0xF1,
-                        len.try_into()?,
-                    ))?;
-                    for symbol in symbols {
-                        symbol::write(&symbol, &mut symbol_section)?;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file::FileId;
+    use crate::leaf::LeafKind;
+    use crate::line::{Block, Line, Lines};
+    use crate::reloc::{SecRel, SectionIndex};
+    use crate::section_write::SectionSink;
+    use crate::struct_macro::Writable;
+    use crate::symbol::{self, Symbol};
+    use crate::validate::Referrer;
+    use crate::writer::MAX_FIELD_LIST_SIZE;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn split_field_list() {
+        let mut codeview = Codeview::<()>::new();
+        let fields = (0..10000)
+            .map(|i| {
+                leaf::Field::Member(leaf::Member {
+                    attributes: 3,
+                    member_type: LeafId(0x74),
+                    offset: leaf::Numeric::Unsigned(i * 4),
+                    name: format!("member{}", i),
+                })
+            })
+            .collect();
+        let id = codeview
+            .add_leaf(Leaf::FieldList(leaf::FieldList { fields }))
+            .unwrap();
+        assert_eq!(id.0, 0x1003);
+
+        let mut members = 0;
+        let mut next = Some(id);
+        while let Some(id) = next.take() {
+            match codeview.leaf(id) {
+                Some(Leaf::FieldList(field_list)) => {
+                    assert!(Writable::<()>::size(field_list) <= MAX_FIELD_LIST_SIZE);
+                    for field in &field_list.fields {
+                        match field {
+                            leaf::Field::Member(_) => members += 1,
+                            leaf::Field::Index(index) => {
+                                assert!(index.continuation.0 < id.0);
+                                next = Some(index.continuation);
+                            }
+                            _ => panic!(),
+                        }
                     }
-                    wu(symbol_section.write(&[0; 3][0..(4 - len % 4) % 4]))?;
                 }
-                _ => unimplemented!(),
+                leaf => panic!("{:?}", leaf),
             }
         }
+        assert_eq!(members, 10000);
+    }
 
-        Ok(())
+    #[test]
+    fn validation() {
+        let string = |substr| {
+            Leaf::StringId(leaf::StringId {
+                substr,
+                content: "s".into(),
+            })
+        };
+        let pointer = |pointee| Leaf::Pointer(leaf::Pointer::new(pointee, 8));
+
+        let mut codeview = Codeview::<()>::new();
+        let id = codeview.add_leaf(string(LeafId::null())).unwrap();
+        codeview
+            .add_leaf(Leaf::BuildInfo(leaf::BuildInfo { args: vec![id] }))
+            .unwrap();
+        codeview.add_leaf(pointer(LeafId(0x74))).unwrap();
+        codeview
+            .add_leaf(Leaf::FuncId(leaf::FuncId {
+                scope: id,
+                function_type: LeafId::null(),
+                name: "f".to_string(),
+            }))
+            .unwrap();
+        codeview.add_subsection(Subsection::Symbols(vec![
+            Symbol::BuildInfo(symbol::BuildInfo {
+                leaf: LeafId(0x1001),
+            }),
+            Symbol::Callees(symbol::Callees {
+                functions: vec![LeafId(0x1003)],
+                invocations: vec![],
+            }),
+            Symbol::Block32(symbol::Block32 {
+                length: 0,
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                name: "b".to_string(),
+            }),
+            Symbol::End(symbol::End {}),
+        ]));
+        codeview.validate().unwrap();
+
+        let mut codeview = Codeview::<()>::new();
+        codeview.add_leaf(pointer(LeafId(0x1005))).unwrap();
+        assert_eq!(
+            codeview.validate(),
+            Err(ValidationError::DanglingIndex {
+                referrer: Referrer::Leaf(LeafId(0x1000)),
+                index: LeafId(0x1005),
+            })
+        );
+
+        let mut codeview = Codeview::<()>::new();
+        codeview.add_leaf(pointer(LeafId(0x1001))).unwrap();
+        codeview.add_leaf(pointer(LeafId(0x74))).unwrap();
+        assert_eq!(
+            codeview.validate(),
+            Err(ValidationError::ForwardReference {
+                referrer: LeafId(0x1000),
+                index: LeafId(0x1001),
+            })
+        );
+
+        let mut codeview = Codeview::<()>::new();
+        codeview.add_leaf(string(LeafId::null())).unwrap();
+        codeview.add_leaf(pointer(LeafId(0x1000))).unwrap();
+        codeview.add_leaf(string(LeafId(0x74))).unwrap();
+        assert_eq!(
+            codeview.validate(),
+            Err(ValidationError::KindMismatch {
+                referrer: Referrer::Leaf(LeafId(0x1001)),
+                index: LeafId(0x1000),
+                expected: LeafKind::Type,
+            })
+        );
+
+        let mut codeview = Codeview::<()>::new();
+        codeview.add_leaf(pointer(LeafId(0x74))).unwrap();
+        codeview.add_subsection(Subsection::Symbols(vec![Symbol::Callees(
+            symbol::Callees {
+                functions: vec![LeafId(0x1000)],
+                invocations: vec![],
+            },
+        )]));
+        assert_eq!(
+            codeview.validate(),
+            Err(ValidationError::KindMismatch {
+                referrer: Referrer::Symbol {
+                    subsection: 0,
+                    symbol: 0,
+                },
+                index: LeafId(0x1000),
+                expected: LeafKind::Id,
+            })
+        );
+
+        let mut codeview = Codeview::<()>::new();
+        codeview.add_subsection(Subsection::Symbols(vec![Symbol::Callers(
+            symbol::Callers {
+                functions: vec![LeafId::null()],
+                invocations: vec![1, 2],
+            },
+        )]));
+        assert_eq!(
+            codeview.validate(),
+            Err(ValidationError::InvocationCount {
+                subsection: 0,
+                symbol: 0,
+                functions: 1,
+                invocations: 2,
+            })
+        );
+
+        let mut codeview = Codeview::<()>::new();
+        codeview.add_subsection(Subsection::Symbols(vec![]));
+        codeview.add_subsection(Subsection::Symbols(vec![Symbol::End(symbol::End {})]));
+        assert_eq!(
+            codeview.validate(),
+            Err(ValidationError::UnmatchedScopeEnd {
+                subsection: 1,
+                symbol: 0,
+            })
+        );
+
+        let mut codeview = Codeview::<()>::new();
+        codeview.add_subsection(Subsection::Symbols(vec![
+            Symbol::Block32(symbol::Block32 {
+                length: 0,
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                name: "b".to_string(),
+            }),
+            Symbol::InlineSiteEnd(symbol::InlineSiteEnd {}),
+        ]));
+        assert_eq!(
+            codeview.validate(),
+            Err(ValidationError::MismatchedScopeEnd {
+                subsection: 0,
+                symbol: 1,
+                begin: 0,
+            })
+        );
+
+        let mut codeview = Codeview::<()>::with_type_server(leaf::TypeServer2 {
+            guid: [0; 16],
+            age: 1,
+            name: "a.pdb".into(),
+        });
+        codeview.add_subsection(Subsection::Symbols(vec![Symbol::BuildInfo(
+            symbol::BuildInfo {
+                leaf: LeafId(0x1234),
+            },
+        )]));
+        codeview.validate().unwrap();
+        codeview.set_validate_on_write(true);
+        codeview.add_subsection(Subsection::Symbols(vec![Symbol::Block32(
+            symbol::Block32 {
+                length: 0,
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                name: "b".to_string(),
+            },
+        )]));
+        assert!(matches!(
+            codeview.write(|_| SectionSink::new()),
+            Err(Error::ValidationError(ValidationError::UnclosedScope {
+                subsection: 1,
+                symbol: 0,
+            }))
+        ));
     }
-}
 
-fn write_subsection_header<Reloc, W: SectionWrite<Reloc>>(
-    writer: &mut W,
-    subsection_type: u32,
-    len: u32,
-) -> Result<(), W::Error> {
-    writer.write(&subsection_type.to_le_bytes())?;
-    writer.write(&len.to_le_bytes())?;
-    Ok(())
+    #[test]
+    fn errors() {
+        let mut codeview = Codeview::<()>::with_precomp(leaf::Precomp {
+            start: 0x1000,
+            count: 0x7FFF_F000,
+            signature: 0,
+            name: "a.obj".into(),
+        });
+        assert!(matches!(
+            codeview.add_leaf(Leaf::Pointer(leaf::Pointer::new(LeafId(0x74), 8))),
+            Err(Error::LeafIndexOverflow(0x8000_0000))
+        ));
+
+        let mut codeview = Codeview::<()>::with_type_server(leaf::TypeServer2 {
+            guid: [0; 16],
+            age: 1,
+            name: "a.pdb".into(),
+        });
+        assert!(matches!(
+            codeview.add_leaf(Leaf::Pointer(leaf::Pointer::new(LeafId(0x74), 8))),
+            Err(Error::TypeServerLeaf)
+        ));
+
+        let mut codeview = Codeview::<()>::new();
+        codeview.add_subsection(Subsection::Symbols(vec![Symbol::Phantom(
+            std::marker::PhantomData,
+        )]));
+        let error = codeview.write(|_| SectionSink::new()).unwrap_err();
+        assert!(matches!(error.root(), Error::PhantomRecord("Symbol")));
+        let context = error.context().unwrap();
+        assert_eq!(context.subsection(), Some(0));
+        assert_eq!(context.symbol(), Some(0));
+        assert_eq!(context.offset(), Some((".debug$S", 0x0C)));
+
+        let mut codeview = Codeview::<()>::new();
+        codeview.add_subsection(Subsection::Symbols(vec![]));
+        codeview.add_subsection(Subsection::Lines(Lines {
+            address: (),
+            code_length: 4,
+            blocks: vec![Block {
+                file: FileId::try_from(0).unwrap(),
+                lines: vec![Line {
+                    offset: 0,
+                    line_start: 1 << 24,
+                    line_delta: None,
+                    is_statement: true,
+                }],
+            }],
+        }));
+        let error = codeview.write(|_| SectionSink::new()).unwrap_err();
+        assert!(matches!(
+            error.root(),
+            Error::LineNumberOverflow(0x100_0000)
+        ));
+        let context = error.context().unwrap();
+        assert_eq!(context.subsection(), Some(1));
+        assert_eq!(context.offset(), Some((".debug$S", 0x0C)));
+
+        let mut codeview = Codeview::<()>::new();
+        for content in &["s", "a\0b"] {
+            codeview
+                .add_leaf(Leaf::StringId(leaf::StringId {
+                    substr: LeafId::null(),
+                    content: (*content).into(),
+                }))
+                .unwrap();
+        }
+        let error = codeview.write(|_| SectionSink::new()).unwrap_err();
+        assert!(matches!(error.root(), Error::StringError(_)));
+        let context = error.context().unwrap();
+        assert_eq!(context.record_kind(), Some("StringId"));
+        assert_eq!(context.leaf_index(), Some(LeafId(0x1001)));
+        assert_eq!(context.field(), Some("content"));
+        assert_eq!(context.offset(), Some((".debug$T", 0x0E)));
+        assert_eq!(
+            error.to_string(),
+            "Failed to encode string (record StringId, leaf 0x1001, field content, \
+             offset 0xe in .debug$T)"
+        );
+    }
 }
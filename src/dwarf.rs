@@ -0,0 +1,1787 @@
+//! Translation of DWARF debugging information into CodeView records.
+//!
+//! Types become leaves, functions become procedure symbols containing their variables, blocks,
+//! labels and inline sites, and line programs become `Lines` subsections. Constructs that have no
+//! translation are reported in `DwarfConversion::unsupported`. Inline sites only describe the
+//! code ranges of inlined calls, and line program rows in these ranges are attributed to the
+//! enclosing function. Column numbers are not translated.
+
+#![allow(non_upper_case_globals)]
+
+use crate::codeview::Codeview;
+use crate::error::Error;
+use crate::file::FileId;
+use crate::leaf::{self, Leaf, LeafId, Numeric, Primitive};
+use crate::line::{Block, Line, Lines};
+use crate::reloc::{SecRel, SectionIndex};
+use crate::section_write::{MACHINE_ARM64, MACHINE_X64, MACHINE_X86};
+use crate::symbol::{self, Symbol};
+use gimli::constants::*;
+use gimli::{AttributeValue, DebugInfoOffset, Operation, Reader, ReaderOffset, Unit, UnitOffset};
+use std::collections::HashMap;
+use std::convert::*;
+
+type Entry<'abbrev, 'unit, R> = gimli::DebuggingInformationEntry<'abbrev, 'unit, R>;
+
+/// A DWARF construct that has no CodeView translation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedDwarf {
+    /// Offset in `.debug_info` of the entry containing the construct, or of the unit for
+    /// constructs in its line program.
+    pub offset: usize,
+
+    /// Description of the construct, such as `"DW_TAG_variant_part"`.
+    pub construct: String,
+}
+
+/// CodeView records translated from DWARF by `convert_dwarf`.
+#[derive(Debug)]
+pub struct DwarfConversion<Reloc> {
+    /// Symbols of the translated functions, to be added as a `Subsection::Symbols`.
+    pub symbols: Vec<Symbol<Reloc>>,
+
+    /// Line numbers of each code sequence, each to be added as a `Subsection::Lines`.
+    ///
+    /// The lines refer to `files` through a `Subsection::FileChecksums` that must be added too.
+    pub lines: Vec<Lines<Reloc>>,
+
+    /// Paths of the source files referenced by `lines`.
+    ///
+    /// A `FileId` refers to the path at its index divided by 8, which is the offset of the file
+    /// in a file checksum subsection without checksums. Adding each path in order with
+    /// `ChecksumKind::None` to `FileChecksums` gives these file IDs.
+    pub files: Vec<String>,
+
+    /// Constructs that were not translated.
+    pub unsupported: Vec<UnsupportedDwarf>,
+}
+
+/// Translate the compilation units of `dwarf`, adding leaves to `codeview`.
+///
+/// `machine` is the COFF machine type (`MACHINE_*`), which determines how DWARF register numbers
+/// map to CodeView ones. `address` maps a code address to the relocation referring to it, and
+/// returning `None` fails the translation with `Error::UnknownAddress`.
+pub fn convert_dwarf<R, Reloc, F>(
+    dwarf: &gimli::Dwarf<R>,
+    machine: u16,
+    codeview: &mut Codeview<Reloc>,
+    address: F,
+) -> Result<DwarfConversion<Reloc>, Error>
+where
+    R: Reader,
+    Reloc: Clone,
+    F: FnMut(u64) -> Option<Reloc>,
+{
+    let mut converter = Converter {
+        dwarf,
+        machine,
+        codeview,
+        address,
+        types: HashMap::new(),
+        inlinees: HashMap::new(),
+        prefixes: HashMap::new(),
+        file_indices: HashMap::new(),
+        output: DwarfConversion {
+            symbols: vec![],
+            lines: vec![],
+            files: vec![],
+            unsupported: vec![],
+        },
+    };
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next()? {
+        let unit = dwarf.unit(header)?;
+        converter.convert_unit(&unit)?;
+    }
+    Ok(converter.output)
+}
+
+/// Member attributes (`CV_fldattr_t`) for each access level.
+pub(crate) const PRIVATE: u16 = 1;
+pub(crate) const PROTECTED: u16 = 2;
+pub(crate) const PUBLIC: u16 = 3;
+
+/// Type property for forward declarations.
+pub(crate) const FORWARD_REF: u16 = 0x80;
+
+/// Variable flags of `Symbol::Local`.
+pub(crate) const IS_PARAMETER: u16 = 0x01;
+pub(crate) const IS_OPTIMIZED_OUT: u16 = 0x100;
+
+/// Where the debugging information entries of a unit are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    Namespace,
+    Type,
+    Function,
+}
+
+/// Location of a variable.
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    Register(u16),
+    RegisterRelative(u16, i32),
+}
+
+/// The function containing the entries being translated.
+struct Function {
+    address: u64,
+    frame_base: Option<u16>,
+}
+
+struct Converter<'a, R: Reader, Reloc, F> {
+    dwarf: &'a gimli::Dwarf<R>,
+    machine: u16,
+    codeview: &'a mut Codeview<Reloc>,
+    address: F,
+    types: HashMap<DebugInfoOffset<R::Offset>, LeafId>,
+    inlinees: HashMap<DebugInfoOffset<R::Offset>, LeafId>,
+    prefixes: HashMap<DebugInfoOffset<R::Offset>, String>,
+    file_indices: HashMap<String, usize>,
+    output: DwarfConversion<Reloc>,
+}
+
+impl<'a, R, Reloc, F> Converter<'a, R, Reloc, F>
+where
+    R: Reader,
+    Reloc: Clone,
+    F: FnMut(u64) -> Option<Reloc>,
+{
+    fn convert_unit(&mut self, unit: &Unit<R>) -> Result<(), Error> {
+        let mut types = vec![];
+        let mut functions = vec![];
+        {
+            let mut tree = unit.entries_tree(None)?;
+            let root = tree.root()?;
+            self.walk(
+                unit,
+                root,
+                "",
+                Context::Namespace,
+                &mut types,
+                &mut functions,
+            )?;
+        }
+        for offset in types {
+            self.type_id(unit, offset)?;
+        }
+        for offset in functions {
+            self.function(unit, offset)?;
+        }
+        self.lines(unit)
+    }
+
+    /// Record the scope of entries, and collect the types and functions to translate.
+    ///
+    /// Other entries in types and functions are translated along with them.
+    fn walk(
+        &mut self,
+        unit: &Unit<R>,
+        node: gimli::EntriesTreeNode<R>,
+        prefix: &str,
+        context: Context,
+        types: &mut Vec<UnitOffset<R::Offset>>,
+        functions: &mut Vec<UnitOffset<R::Offset>>,
+    ) -> Result<(), Error> {
+        let entry = node.entry();
+        let tag = entry.tag();
+        if !prefix.is_empty() {
+            let key = self.key(unit, entry.offset());
+            self.prefixes.insert(key, prefix.to_string());
+        }
+
+        let (prefix, context) = match tag {
+            DW_TAG_compile_unit | DW_TAG_partial_unit => (prefix.to_string(), context),
+            DW_TAG_namespace if context == Context::Namespace => {
+                let name = self.own_name(unit, entry)?;
+                let name = name.as_deref().unwrap_or("`anonymous namespace'");
+                (format!("{}{}::", prefix, name), context)
+            }
+            DW_TAG_structure_type | DW_TAG_class_type | DW_TAG_union_type => {
+                types.push(entry.offset());
+                match self.own_name(unit, entry)? {
+                    Some(name) => (format!("{}{}::", prefix, name), Context::Type),
+                    None => (prefix.to_string(), Context::Type),
+                }
+            }
+            DW_TAG_subprogram => {
+                functions.push(entry.offset());
+                // Parameters and locals are not qualified by the enclosing namespace.
+                (String::new(), Context::Function)
+            }
+            tag if is_type(tag) => {
+                types.push(entry.offset());
+                return Ok(());
+            }
+            _ if context != Context::Namespace => (prefix.to_string(), context),
+            DW_TAG_variable => {
+                if entry.attr_value(DW_AT_location)?.is_some() {
+                    self.unsupported(unit, entry, "Global DW_TAG_variable");
+                }
+                return Ok(());
+            }
+            tag => {
+                self.unsupported(unit, entry, tag);
+                return Ok(());
+            }
+        };
+
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            self.walk(unit, child, &prefix, context, types, functions)?;
+        }
+        Ok(())
+    }
+
+    fn key(&self, unit: &Unit<R>, offset: UnitOffset<R::Offset>) -> DebugInfoOffset<R::Offset> {
+        // Units are only read from `.debug_info`, so the offset always converts.
+        offset
+            .to_debug_info_offset(&unit.header)
+            .unwrap_or(DebugInfoOffset(R::Offset::from_u8(0)))
+    }
+
+    fn unsupported(&mut self, unit: &Unit<R>, entry: &Entry<R>, construct: impl ToString) {
+        let offset = self.key(unit, entry.offset()).0.into_u64() as usize;
+        self.report(offset, construct);
+    }
+
+    fn report(&mut self, offset: usize, construct: impl ToString) {
+        self.output.unsupported.push(UnsupportedDwarf {
+            offset,
+            construct: construct.to_string(),
+        });
+    }
+
+    fn reloc(&mut self, address: u64) -> Result<Reloc, Error> {
+        (self.address)(address).ok_or(Error::UnknownAddress(address))
+    }
+
+    fn string(&self, unit: &Unit<R>, value: AttributeValue<R>) -> Result<String, Error> {
+        let string = self.dwarf.attr_string(unit, value)?;
+        Ok(string.to_string_lossy()?.into_owned())
+    }
+
+    fn own_name(&self, unit: &Unit<R>, entry: &Entry<R>) -> Result<Option<String>, Error> {
+        match entry.attr_value(DW_AT_name)? {
+            Some(value) => Ok(Some(self.string(unit, value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Qualified name of an entry, or of the entry it is a specification or instance of.
+    fn name(&self, unit: &Unit<R>, entry: &Entry<R>) -> Result<Option<String>, Error> {
+        let entry = match origin_with(unit, entry, DW_AT_name)? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let name = self.own_name(unit, &entry)?.unwrap_or_default();
+        match self.prefixes.get(&self.key(unit, entry.offset())) {
+            Some(prefix) => Ok(Some(format!("{}{}", prefix, name))),
+            None => Ok(Some(name)),
+        }
+    }
+
+    /// Leaf index of the type of an entry, which is `void` if the entry has no type.
+    fn type_of(&mut self, unit: &Unit<R>, entry: &Entry<R>) -> Result<LeafId, Error> {
+        match attr(unit, entry, DW_AT_type)? {
+            None => Ok(LeafId::primitive(Primitive::Void)),
+            Some(AttributeValue::UnitRef(offset)) => self.type_id(unit, offset),
+            Some(_) => {
+                self.unsupported(unit, entry, "DW_AT_type referring to another unit");
+                Ok(LeafId::null())
+            }
+        }
+    }
+
+    fn type_id(&mut self, unit: &Unit<R>, offset: UnitOffset<R::Offset>) -> Result<LeafId, Error> {
+        let key = self.key(unit, offset);
+        if let Some(id) = self.types.get(&key) {
+            return Ok(*id);
+        }
+
+        let entry = unit.entry(offset)?;
+        let id = match entry.tag() {
+            DW_TAG_base_type => self.base_type(unit, &entry)?,
+            tag @ (DW_TAG_pointer_type | DW_TAG_reference_type | DW_TAG_rvalue_reference_type) => {
+                let pointee = self.type_of(unit, &entry)?;
+                let size = match entry.attr_value(DW_AT_byte_size)? {
+                    Some(size) => u8::try_from(size.udata_value().unwrap_or(0))?,
+                    None => unit.encoding().address_size,
+                };
+                let mut pointer = leaf::Pointer::new(pointee, size);
+                match tag {
+                    DW_TAG_reference_type => pointer.attributes |= 1 << 5,
+                    DW_TAG_rvalue_reference_type => pointer.attributes |= 4 << 5,
+                    _ => (),
+                }
+                self.codeview.add_leaf(Leaf::Pointer(pointer))?
+            }
+            tag @ (DW_TAG_const_type | DW_TAG_volatile_type) => {
+                let modified_type = self.type_of(unit, &entry)?;
+                let modifiers = if tag == DW_TAG_const_type { 1 } else { 2 };
+                self.codeview.add_leaf(Leaf::Modifier(leaf::Modifier {
+                    modified_type,
+                    modifiers,
+                }))?
+            }
+            DW_TAG_typedef => {
+                self.unsupported(unit, &entry, "DW_TAG_typedef, replaced by the aliased type");
+                self.type_of(unit, &entry)?
+            }
+            DW_TAG_structure_type | DW_TAG_class_type | DW_TAG_union_type => {
+                self.structure(unit, &entry)?
+            }
+            DW_TAG_enumeration_type => self.enumeration(unit, &entry)?,
+            DW_TAG_array_type => self.array(unit, &entry)?,
+            DW_TAG_subroutine_type => self.procedure(unit, &entry)?,
+            tag => {
+                self.unsupported(unit, &entry, tag);
+                LeafId::null()
+            }
+        };
+        self.types.insert(key, id);
+        Ok(id)
+    }
+
+    fn base_type(&mut self, unit: &Unit<R>, entry: &Entry<R>) -> Result<LeafId, Error> {
+        let size = byte_size(entry)?;
+        let encoding = match entry.attr_value(DW_AT_encoding)? {
+            Some(AttributeValue::Encoding(encoding)) => encoding,
+            _ => DwAte(0),
+        };
+        let primitive = match (encoding, size) {
+            (DW_ATE_boolean, Some(1)) => Primitive::Bool,
+            (DW_ATE_signed_char, Some(1)) => Primitive::Char,
+            (DW_ATE_unsigned_char, Some(1)) | (DW_ATE_UTF, Some(1)) => Primitive::U8,
+            (DW_ATE_UTF, Some(2)) => Primitive::Char16,
+            (DW_ATE_UTF, Some(4)) => Primitive::Char32,
+            (DW_ATE_float, Some(4)) => Primitive::F32,
+            (DW_ATE_float, Some(8)) => Primitive::F64,
+            (DW_ATE_signed, Some(size)) | (DW_ATE_unsigned, Some(size)) => {
+                let signed = encoding == DW_ATE_signed;
+                match (size, signed) {
+                    (1, true) => Primitive::I8,
+                    (1, false) => Primitive::U8,
+                    (2, true) => Primitive::I16,
+                    (2, false) => Primitive::U16,
+                    (4, true) => Primitive::I32,
+                    (4, false) => Primitive::U32,
+                    (8, true) => Primitive::I64,
+                    (8, false) => Primitive::U64,
+                    (16, true) => Primitive::I128,
+                    (16, false) => Primitive::U128,
+                    _ => return self.unsupported_base_type(unit, entry, encoding, size),
+                }
+            }
+            _ => return self.unsupported_base_type(unit, entry, encoding, size.unwrap_or(0)),
+        };
+        Ok(LeafId::primitive(primitive))
+    }
+
+    fn unsupported_base_type(
+        &mut self,
+        unit: &Unit<R>,
+        entry: &Entry<R>,
+        encoding: DwAte,
+        size: u64,
+    ) -> Result<LeafId, Error> {
+        let construct = format!("DW_TAG_base_type with {} of {} bytes", encoding, size);
+        self.unsupported(unit, entry, construct);
+        Ok(LeafId::null())
+    }
+
+    fn structure(&mut self, unit: &Unit<R>, entry: &Entry<R>) -> Result<LeafId, Error> {
+        let name = self
+            .name(unit, entry)?
+            .unwrap_or_else(|| "<unnamed-tag>".to_string());
+        let is_union = entry.tag() == DW_TAG_union_type;
+        let aggregate = |member_count, properties, field_list, size: u64| {
+            if is_union {
+                Leaf::Union(leaf::Union {
+                    member_count,
+                    properties,
+                    field_list,
+                    size: size.into(),
+                    name: name.clone(),
+                    unique_name: None,
+                })
+            } else {
+                Leaf::Structure(leaf::Structure {
+                    member_count,
+                    properties,
+                    field_list,
+                    derived_from: LeafId::null(),
+                    vtable_shape: LeafId::null(),
+                    size: size.into(),
+                    name: name.clone(),
+                    unique_name: None,
+                })
+            }
+        };
+
+        // Members refer to the forward declaration, which breaks recursive type cycles.
+        let forward_ref = aggregate(0, FORWARD_REF, LeafId::null(), 0);
+        let forward_ref = self.codeview.add_leaf(forward_ref)?;
+        if is_declaration(entry)? {
+            return Ok(forward_ref);
+        }
+        self.types
+            .insert(self.key(unit, entry.offset()), forward_ref);
+
+        let default_access = if entry.tag() == DW_TAG_class_type {
+            PRIVATE
+        } else {
+            PUBLIC
+        };
+        let mut fields = vec![];
+        let mut tree = unit.entries_tree(Some(entry.offset()))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            let member = child.entry();
+            let attributes = match member.attr_value(DW_AT_accessibility)? {
+                Some(AttributeValue::Accessibility(DW_ACCESS_public)) => PUBLIC,
+                Some(AttributeValue::Accessibility(DW_ACCESS_protected)) => PROTECTED,
+                Some(AttributeValue::Accessibility(DW_ACCESS_private)) => PRIVATE,
+                _ => default_access,
+            };
+            let name = self.own_name(unit, member)?.unwrap_or_default();
+            match member.tag() {
+                DW_TAG_member if is_declaration(member)? => {
+                    fields.push(leaf::Field::StaticMember(leaf::StaticMember {
+                        attributes,
+                        member_type: self.type_of(unit, member)?,
+                        name,
+                    }));
+                }
+                DW_TAG_variable => {
+                    fields.push(leaf::Field::StaticMember(leaf::StaticMember {
+                        attributes,
+                        member_type: self.type_of(unit, member)?,
+                        name,
+                    }));
+                }
+                DW_TAG_member => {
+                    if member.attr_value(DW_AT_bit_size)?.is_some() {
+                        self.unsupported(unit, member, "Bit field DW_TAG_member");
+                        continue;
+                    }
+                    let offset = match member.attr_value(DW_AT_data_member_location)? {
+                        None => 0,
+                        Some(value) => match value.udata_value() {
+                            Some(offset) => offset,
+                            None => {
+                                let construct = "DW_AT_data_member_location expression";
+                                self.unsupported(unit, member, construct);
+                                continue;
+                            }
+                        },
+                    };
+                    fields.push(leaf::Field::Member(leaf::Member {
+                        attributes,
+                        member_type: self.type_of(unit, member)?,
+                        offset: offset.into(),
+                        name,
+                    }));
+                }
+                tag if is_type(tag) => (),
+                tag => self.unsupported(unit, member, tag),
+            }
+        }
+
+        let member_count = fields.len().try_into()?;
+        let field_list = self
+            .codeview
+            .add_leaf(Leaf::FieldList(leaf::FieldList { fields }))?;
+        let size = byte_size(entry)?.unwrap_or(0);
+        let leaf = aggregate(member_count, 0, field_list, size);
+        self.codeview.add_leaf(leaf)
+    }
+
+    fn enumeration(&mut self, unit: &Unit<R>, entry: &Entry<R>) -> Result<LeafId, Error> {
+        let name = self
+            .name(unit, entry)?
+            .unwrap_or_else(|| "<unnamed-enum>".to_string());
+        let underlying_type = if entry.attr_value(DW_AT_type)?.is_some() {
+            self.type_of(unit, entry)?
+        } else {
+            LeafId::primitive(match byte_size(entry)? {
+                Some(1) => Primitive::U8,
+                Some(2) => Primitive::U16,
+                Some(8) => Primitive::U64,
+                _ => Primitive::U32,
+            })
+        };
+        if is_declaration(entry)? {
+            return self.codeview.add_leaf(Leaf::Enum(leaf::Enum {
+                member_count: 0,
+                properties: FORWARD_REF,
+                underlying_type,
+                field_list: LeafId::null(),
+                name,
+                unique_name: None,
+            }));
+        }
+
+        let mut fields = vec![];
+        let mut tree = unit.entries_tree(Some(entry.offset()))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            let enumerator = child.entry();
+            if enumerator.tag() != DW_TAG_enumerator {
+                self.unsupported(unit, enumerator, enumerator.tag());
+                continue;
+            }
+            let value = match enumerator.attr_value(DW_AT_const_value)? {
+                Some(AttributeValue::Sdata(value)) => Numeric::Signed(value),
+                Some(value) => match value.udata_value() {
+                    Some(value) => Numeric::Unsigned(value),
+                    None => {
+                        self.unsupported(
+                            unit,
+                            enumerator,
+                            "DW_AT_const_value of DW_TAG_enumerator",
+                        );
+                        continue;
+                    }
+                },
+                None => Numeric::Unsigned(0),
+            };
+            fields.push(leaf::Field::Enumerate(leaf::Enumerate {
+                attributes: PUBLIC,
+                value,
+                name: self.own_name(unit, enumerator)?.unwrap_or_default(),
+            }));
+        }
+
+        let member_count = fields.len().try_into()?;
+        let field_list = self
+            .codeview
+            .add_leaf(Leaf::FieldList(leaf::FieldList { fields }))?;
+        self.codeview.add_leaf(Leaf::Enum(leaf::Enum {
+            member_count,
+            properties: 0,
+            underlying_type,
+            field_list,
+            name,
+            unique_name: None,
+        }))
+    }
+
+    fn array(&mut self, unit: &Unit<R>, entry: &Entry<R>) -> Result<LeafId, Error> {
+        let mut counts = vec![];
+        let mut tree = unit.entries_tree(Some(entry.offset()))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            let subrange = child.entry();
+            if subrange.tag() != DW_TAG_subrange_type {
+                self.unsupported(unit, subrange, subrange.tag());
+                continue;
+            }
+            let dynamic = "DW_TAG_subrange_type with dynamic bounds";
+            let count = match subrange.attr_value(DW_AT_count)? {
+                Some(count) => count.udata_value().ok_or(dynamic),
+                None => match subrange.attr_value(DW_AT_upper_bound)? {
+                    Some(upper_bound) => {
+                        let lower_bound = subrange.attr_value(DW_AT_lower_bound)?;
+                        let lower_bound = lower_bound.and_then(|value| value.udata_value());
+                        match upper_bound.udata_value() {
+                            Some(upper_bound) => (u128::from(upper_bound) + 1)
+                                .checked_sub(u128::from(lower_bound.unwrap_or(0)))
+                                .and_then(|count| u64::try_from(count).ok())
+                                .ok_or("DW_TAG_subrange_type with out of range bounds"),
+                            None => Err(dynamic),
+                        }
+                    }
+                    None => Ok(0),
+                },
+            };
+            match count {
+                Ok(count) => counts.push(count),
+                Err(construct) => {
+                    self.unsupported(unit, subrange, construct);
+                    counts.push(0);
+                }
+            }
+        }
+
+        let mut size = match attr(unit, entry, DW_AT_type)? {
+            Some(AttributeValue::UnitRef(offset)) => type_size(unit, offset)?,
+            _ => None,
+        };
+        if size.is_none() {
+            self.unsupported(unit, entry, "DW_TAG_array_type of unknown element size");
+        }
+        let index_type = if unit.encoding().address_size == 8 {
+            Primitive::U64
+        } else {
+            Primitive::U32
+        };
+        let mut id = self.type_of(unit, entry)?;
+        for count in counts.into_iter().rev() {
+            size = match size.map(|size| size.checked_mul(count)) {
+                Some(None) => {
+                    self.unsupported(unit, entry, "DW_TAG_array_type of out of range size");
+                    None
+                }
+                size => size.flatten(),
+            };
+            id = self.codeview.add_leaf(Leaf::Array(leaf::Array {
+                element_type: id,
+                index_type: LeafId::primitive(index_type),
+                size: size.unwrap_or(0).into(),
+                name: "".to_string(),
+            }))?;
+        }
+        Ok(id)
+    }
+
+    /// Procedure type of a function or function type, from its type and parameter entries.
+    fn procedure(&mut self, unit: &Unit<R>, entry: &Entry<R>) -> Result<LeafId, Error> {
+        let return_type = self.type_of(unit, entry)?;
+        let mut arguments = vec![];
+        let mut tree = unit.entries_tree(Some(entry.offset()))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            match child.entry().tag() {
+                DW_TAG_formal_parameter => arguments.push(self.type_of(unit, child.entry())?),
+                DW_TAG_unspecified_parameters => arguments.push(LeafId::null()),
+                _ => (),
+            }
+        }
+        let parameter_count = arguments.len().try_into()?;
+        let argument_list = self
+            .codeview
+            .add_leaf(Leaf::ArgList(leaf::ArgList { arguments }))?;
+        self.codeview.add_leaf(Leaf::Procedure(leaf::Procedure {
+            return_type,
+            calling_convention: 0,
+            function_attributes: 0,
+            parameter_count,
+            argument_list,
+        }))
+    }
+
+    fn ranges(&self, unit: &Unit<R>, entry: &Entry<R>) -> Result<Vec<(u64, u64)>, Error> {
+        let mut ranges = vec![];
+        let mut iter = self.dwarf.die_ranges(unit, entry)?;
+        while let Some(range) = iter.next()? {
+            if range.begin < range.end {
+                ranges.push((range.begin, range.end));
+            }
+        }
+        ranges.sort_unstable();
+        Ok(ranges)
+    }
+
+    fn function(&mut self, unit: &Unit<R>, offset: UnitOffset<R::Offset>) -> Result<(), Error> {
+        let entry = unit.entry(offset)?;
+        let (begin, end) = match self.ranges(unit, &entry)?[..] {
+            // Declarations and abstract instances of inlined functions have no code.
+            [] => return Ok(()),
+            [range] => range,
+            _ => {
+                self.unsupported(unit, &entry, "DW_TAG_subprogram with multiple ranges");
+                return Ok(());
+            }
+        };
+
+        let frame_base = match entry.attr_value(DW_AT_frame_base)? {
+            Some(AttributeValue::Exprloc(expression)) => {
+                let mut operations = expression.operations(unit.encoding());
+                match (operations.next()?, operations.next()?) {
+                    (Some(Operation::Register { register }), None) => self.register(register.0),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let reloc = self.reloc(begin)?;
+        let length = u32::try_from(end - begin)?;
+        let function_type = self.procedure(unit, &entry)?;
+        let name = self.name(unit, &entry)?.unwrap_or_default();
+        let symbol = if let Some(AttributeValue::Flag(true)) = attr(unit, &entry, DW_AT_external)? {
+            Symbol::GlobalProc32(symbol::GlobalProc32 {
+                length,
+                debug_start: 0,
+                debug_end: length,
+                function_type,
+                offset: SecRel(reloc.clone()),
+                section: SectionIndex(reloc),
+                flags: 0,
+                name,
+            })
+        } else {
+            Symbol::LocalProc32(symbol::LocalProc32 {
+                length,
+                debug_start: 0,
+                debug_end: length,
+                function_type,
+                offset: SecRel(reloc.clone()),
+                section: SectionIndex(reloc),
+                flags: 0,
+                name,
+            })
+        };
+        self.output.symbols.push(symbol);
+        let function = Function {
+            address: begin,
+            frame_base,
+        };
+        self.scope(unit, offset, &function)?;
+        self.output.symbols.push(Symbol::End(symbol::End {}));
+        Ok(())
+    }
+
+    /// Translate the variables and nested scopes of a function, block or inline site.
+    fn scope(
+        &mut self,
+        unit: &Unit<R>,
+        offset: UnitOffset<R::Offset>,
+        function: &Function,
+    ) -> Result<(), Error> {
+        let mut tree = unit.entries_tree(Some(offset))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                DW_TAG_formal_parameter | DW_TAG_variable => {
+                    self.variable(unit, entry, function)?
+                }
+                DW_TAG_lexical_block => self.block(unit, entry, function)?,
+                DW_TAG_inlined_subroutine => self.inline_site(unit, entry, function)?,
+                DW_TAG_label => self.label(unit, entry)?,
+                // Nested functions and types are translated on their own, and unspecified
+                // parameters are part of the function type.
+                DW_TAG_subprogram | DW_TAG_unspecified_parameters => (),
+                tag if is_type(tag) => (),
+                tag => self.unsupported(unit, entry, tag),
+            }
+        }
+        Ok(())
+    }
+
+    fn block(
+        &mut self,
+        unit: &Unit<R>,
+        entry: &Entry<R>,
+        function: &Function,
+    ) -> Result<(), Error> {
+        let (begin, end) = match self.ranges(unit, entry)?[..] {
+            [range] => range,
+            _ => {
+                // The contents are still translated, in the enclosing scope.
+                self.unsupported(unit, entry, "DW_TAG_lexical_block without a single range");
+                return self.scope(unit, entry.offset(), function);
+            }
+        };
+        let reloc = self.reloc(begin)?;
+        self.output.symbols.push(Symbol::Block32(symbol::Block32 {
+            length: u32::try_from(end - begin)?,
+            offset: SecRel(reloc.clone()),
+            section: SectionIndex(reloc),
+            name: "".to_string(),
+        }));
+        self.scope(unit, entry.offset(), function)?;
+        self.output.symbols.push(Symbol::End(symbol::End {}));
+        Ok(())
+    }
+
+    fn inline_site(
+        &mut self,
+        unit: &Unit<R>,
+        entry: &Entry<R>,
+        function: &Function,
+    ) -> Result<(), Error> {
+        let mut annotations = vec![];
+        let mut code_offset = 0;
+        for (begin, end) in self.ranges(unit, entry)? {
+            let start = begin
+                .checked_sub(function.address)
+                .filter(|start| *start >= code_offset);
+            let start = match start {
+                Some(start) => start,
+                None => {
+                    let construct = "DW_TAG_inlined_subroutine outside of its function";
+                    self.unsupported(unit, entry, construct);
+                    return self.scope(unit, entry.offset(), function);
+                }
+            };
+            annotations.push(symbol::BinaryAnnotation::ChangeCodeOffset(u32::try_from(
+                start - code_offset,
+            )?));
+            annotations.push(symbol::BinaryAnnotation::ChangeCodeLength(u32::try_from(
+                end - begin,
+            )?));
+            code_offset = end - function.address;
+        }
+        if annotations.is_empty() {
+            return self.scope(unit, entry.offset(), function);
+        }
+
+        let origin = match entry.attr_value(DW_AT_abstract_origin)? {
+            Some(AttributeValue::UnitRef(origin)) => origin,
+            _ => entry.offset(),
+        };
+        let key = self.key(unit, origin);
+        let inlinee = match self.inlinees.get(&key) {
+            Some(inlinee) => *inlinee,
+            None => {
+                let function_type = self.procedure(unit, entry)?;
+                let name = self.name(unit, entry)?.unwrap_or_default();
+                let inlinee = self.codeview.add_leaf(Leaf::FuncId(leaf::FuncId {
+                    scope: LeafId::null(),
+                    function_type,
+                    name,
+                }))?;
+                self.inlinees.insert(key, inlinee);
+                inlinee
+            }
+        };
+        self.output
+            .symbols
+            .push(Symbol::InlineSite(symbol::InlineSite {
+                inlinee,
+                annotations,
+            }));
+        self.scope(unit, entry.offset(), function)?;
+        self.output
+            .symbols
+            .push(Symbol::InlineSiteEnd(symbol::InlineSiteEnd {}));
+        Ok(())
+    }
+
+    fn label(&mut self, unit: &Unit<R>, entry: &Entry<R>) -> Result<(), Error> {
+        let address = match entry.attr_value(DW_AT_low_pc)? {
+            Some(value) => self.dwarf.attr_address(unit, value)?,
+            None => None,
+        };
+        let address = match address {
+            Some(address) => address,
+            None => return Ok(()),
+        };
+        let reloc = self.reloc(address)?;
+        let name = self.name(unit, entry)?.unwrap_or_default();
+        self.output.symbols.push(Symbol::Label32(symbol::Label32 {
+            offset: SecRel(reloc.clone()),
+            section: SectionIndex(reloc),
+            flags: 0,
+            name,
+        }));
+        Ok(())
+    }
+
+    fn variable(
+        &mut self,
+        unit: &Unit<R>,
+        entry: &Entry<R>,
+        function: &Function,
+    ) -> Result<(), Error> {
+        let name = self.name(unit, entry)?.unwrap_or_default();
+        let variable_type = self.type_of(unit, entry)?;
+        let parameter = if entry.tag() == DW_TAG_formal_parameter {
+            IS_PARAMETER
+        } else {
+            0
+        };
+        let local = |flags| {
+            Symbol::Local(symbol::Local {
+                variable_type,
+                flags: parameter | flags,
+                name: name.clone(),
+            })
+        };
+
+        let value = match entry.attr_value(DW_AT_location)? {
+            Some(value) => value,
+            None => {
+                let symbol = match attr(unit, entry, DW_AT_const_value)? {
+                    Some(AttributeValue::Sdata(value)) => {
+                        constant(variable_type, value.into(), &name)
+                    }
+                    Some(value) => match value.udata_value() {
+                        Some(value) => constant(variable_type, value.into(), &name),
+                        None => {
+                            self.unsupported(unit, entry, "DW_AT_const_value of a variable");
+                            local(IS_OPTIMIZED_OUT)
+                        }
+                    },
+                    None => local(IS_OPTIMIZED_OUT),
+                };
+                self.output.symbols.push(symbol);
+                return Ok(());
+            }
+        };
+
+        if let AttributeValue::Exprloc(expression) = value {
+            let symbol = match self.location(unit, expression, function)? {
+                Some(Location::Register(register)) => Symbol::Register(symbol::Register {
+                    variable_type,
+                    register,
+                    name: name.clone(),
+                }),
+                Some(Location::RegisterRelative(register, offset)) => {
+                    Symbol::RegRel32(symbol::RegRel32 {
+                        offset,
+                        variable_type,
+                        register,
+                        name: name.clone(),
+                    })
+                }
+                None => {
+                    self.unsupported(unit, entry, "DW_AT_location expression");
+                    local(IS_OPTIMIZED_OUT)
+                }
+            };
+            self.output.symbols.push(symbol);
+            return Ok(());
+        }
+
+        let mut locations = match self.dwarf.attr_locations(unit, value)? {
+            Some(locations) => locations,
+            None => {
+                self.unsupported(unit, entry, "DW_AT_location");
+                self.output.symbols.push(local(IS_OPTIMIZED_OUT));
+                return Ok(());
+            }
+        };
+        self.output.symbols.push(local(0));
+        while let Some(location) = locations.next()? {
+            let kind = match self.location(unit, location.data, function)? {
+                Some(kind) => kind,
+                None => {
+                    self.unsupported(unit, entry, "DW_AT_location list entry");
+                    continue;
+                }
+            };
+            // Ranges of a location record are limited to 16-bit lengths.
+            let mut begin = location.range.begin;
+            while begin < location.range.end {
+                let length = (location.range.end - begin).min(0xFFFF);
+                let reloc = self.reloc(begin)?;
+                let range = symbol::AddressRange {
+                    offset: SecRel(reloc.clone()),
+                    section: SectionIndex(reloc),
+                    length: length as u16,
+                };
+                let symbol = match kind {
+                    Location::Register(register) => {
+                        Symbol::DefRangeRegister(symbol::DefRangeRegister {
+                            register,
+                            may_have_no_name: 0,
+                            range,
+                            gaps: vec![],
+                        })
+                    }
+                    Location::RegisterRelative(base_register, base_offset) => {
+                        Symbol::DefRangeRegisterRel(symbol::DefRangeRegisterRel {
+                            base_register,
+                            flags: 0,
+                            base_offset,
+                            range,
+                            gaps: vec![],
+                        })
+                    }
+                };
+                self.output.symbols.push(symbol);
+                begin += length;
+            }
+        }
+        Ok(())
+    }
+
+    /// Translate a location expression consisting of a single register operation.
+    fn location(
+        &self,
+        unit: &Unit<R>,
+        expression: gimli::Expression<R>,
+        function: &Function,
+    ) -> Result<Option<Location>, Error> {
+        let mut operations = expression.operations(unit.encoding());
+        let operation = match (operations.next()?, operations.next()?) {
+            (Some(operation), None) => operation,
+            _ => return Ok(None),
+        };
+        let location = match operation {
+            Operation::Register { register } => self.register(register.0).map(Location::Register),
+            Operation::RegisterOffset {
+                register,
+                offset,
+                base_type,
+            } if base_type.0.into_u64() == 0 => {
+                let offset = i32::try_from(offset).ok();
+                let register = self.register(register.0);
+                register
+                    .zip(offset)
+                    .map(|(register, offset)| Location::RegisterRelative(register, offset))
+            }
+            Operation::FrameOffset { offset } => {
+                let offset = i32::try_from(offset).ok();
+                let register = function.frame_base;
+                register
+                    .zip(offset)
+                    .map(|(register, offset)| Location::RegisterRelative(register, offset))
+            }
+            _ => None,
+        };
+        Ok(location)
+    }
+
+    fn register(&self, register: u16) -> Option<u16> {
+        codeview_register(self.machine, register)
+    }
+
+    fn file_id(
+        &mut self,
+        unit: &Unit<R>,
+        header: &gimli::LineProgramHeader<R>,
+        index: u64,
+    ) -> Result<Option<FileId>, Error> {
+        let file = match header.file(index) {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let mut path = self.string(unit, file.path_name())?;
+        if let Some(directory) = file.directory(header) {
+            if !is_absolute(&path) {
+                path = join(&self.string(unit, directory)?, &path);
+            }
+        }
+        if let Some(comp_dir) = &unit.comp_dir {
+            if !is_absolute(&path) {
+                path = join(&comp_dir.to_string_lossy()?, &path);
+            }
+        }
+        let index = match self.file_indices.get(&path) {
+            Some(index) => *index,
+            None => {
+                let index = self.output.files.len();
+                self.output.files.push(path.clone());
+                self.file_indices.insert(path, index);
+                index
+            }
+        };
+        Ok(Some(FileId(u32::try_from(index * 8)?)))
+    }
+
+    fn lines(&mut self, unit: &Unit<R>) -> Result<(), Error> {
+        let program = match &unit.line_program {
+            Some(program) => program.clone(),
+            None => return Ok(()),
+        };
+        let mut rows = program.rows();
+        let mut sequence: Option<(u64, Reloc, Vec<Block>)> = None;
+        while let Some((header, row)) = rows.next_row()? {
+            if row.end_sequence() {
+                if let Some((start, address, blocks)) = sequence.take() {
+                    self.output.lines.push(Lines {
+                        address,
+                        code_length: u32::try_from(row.address() - start)?,
+                        blocks,
+                    });
+                }
+                continue;
+            }
+            let line_start = match row.line() {
+                Some(line) => u32::try_from(line.get())?,
+                None => continue,
+            };
+            let file = match self.file_id(unit, header, row.file_index())? {
+                Some(file) => file,
+                None => {
+                    let offset = unit.header.offset().as_debug_info_offset();
+                    let offset = offset.map_or(0, |offset| offset.0.into_u64() as usize);
+                    self.report(offset, format!("Line program file {}", row.file_index()));
+                    continue;
+                }
+            };
+            if sequence.is_none() {
+                let reloc = self.reloc(row.address())?;
+                sequence = Some((row.address(), reloc, vec![]));
+            }
+            let (start, _, blocks) = sequence.as_mut().unwrap();
+            let line = Line {
+                offset: u32::try_from(row.address() - *start)?,
+                line_start,
+                line_delta: None,
+                is_statement: row.is_stmt(),
+            };
+            match blocks.last_mut() {
+                Some(block) if block.file == file => block.lines.push(line),
+                _ => blocks.push(Block {
+                    file,
+                    lines: vec![line],
+                }),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// CodeView register number (`CV_HREG_e`) of a DWARF register number.
+pub(crate) fn codeview_register(machine: u16, register: u16) -> Option<u16> {
+    match (machine, register) {
+        (MACHINE_X64, 0) => Some(328),
+        (MACHINE_X64, 1) => Some(331),
+        (MACHINE_X64, 2) => Some(330),
+        (MACHINE_X64, 3) => Some(329),
+        (MACHINE_X64, 4..=15) => Some(register + 328),
+        (MACHINE_X64, 17..=24) => Some(register - 17 + 154),
+        (MACHINE_X64, 25..=32) => Some(register - 25 + 252),
+        (MACHINE_X86, 0) => Some(17),
+        (MACHINE_X86, 1) => Some(18),
+        (MACHINE_X86, 2..=7) => Some(register + 17),
+        (MACHINE_ARM64, 0..=31) => Some(register + 50),
+        _ => None,
+    }
+}
+
+fn constant<Reloc>(constant_type: LeafId, value: Numeric, name: &str) -> Symbol<Reloc> {
+    Symbol::Constant(symbol::Constant {
+        constant_type,
+        value,
+        name: name.to_string(),
+    })
+}
+
+/// Whether the tag is of an entry describing a type.
+fn is_type(tag: DwTag) -> bool {
+    matches!(
+        tag,
+        DW_TAG_base_type
+            | DW_TAG_pointer_type
+            | DW_TAG_reference_type
+            | DW_TAG_rvalue_reference_type
+            | DW_TAG_const_type
+            | DW_TAG_volatile_type
+            | DW_TAG_restrict_type
+            | DW_TAG_atomic_type
+            | DW_TAG_typedef
+            | DW_TAG_structure_type
+            | DW_TAG_class_type
+            | DW_TAG_union_type
+            | DW_TAG_enumeration_type
+            | DW_TAG_array_type
+            | DW_TAG_subroutine_type
+            | DW_TAG_ptr_to_member_type
+            | DW_TAG_unspecified_type
+            | DW_TAG_string_type
+            | DW_TAG_set_type
+            | DW_TAG_subrange_type
+    )
+}
+
+fn is_declaration<R: Reader>(entry: &Entry<R>) -> Result<bool, Error> {
+    Ok(matches!(
+        entry.attr_value(DW_AT_declaration)?,
+        Some(AttributeValue::Flag(true))
+    ))
+}
+
+fn byte_size<R: Reader>(entry: &Entry<R>) -> Result<Option<u64>, Error> {
+    Ok(entry
+        .attr_value(DW_AT_byte_size)?
+        .and_then(|value| value.udata_value()))
+}
+
+/// The entry that has an attribute, following `DW_AT_abstract_origin` and `DW_AT_specification`.
+fn origin_with<'abbrev, 'unit, R: Reader>(
+    unit: &'unit Unit<R>,
+    entry: &Entry<'abbrev, 'unit, R>,
+    name: DwAt,
+) -> Result<Option<Entry<'abbrev, 'unit, R>>, Error>
+where
+    'unit: 'abbrev,
+{
+    let mut entry = entry.clone();
+    // Chains are short in practice, and the limit guards against cycles.
+    for _ in 0..8 {
+        if entry.attr_value(name)?.is_some() {
+            return Ok(Some(entry));
+        }
+        let origin = match entry.attr_value(DW_AT_abstract_origin)? {
+            Some(origin) => Some(origin),
+            None => entry.attr_value(DW_AT_specification)?,
+        };
+        entry = match origin {
+            Some(AttributeValue::UnitRef(offset)) => unit.entry(offset)?,
+            _ => return Ok(None),
+        };
+    }
+    Ok(None)
+}
+
+/// An attribute of an entry, or of the entry it is a specification or instance of.
+fn attr<R: Reader>(
+    unit: &Unit<R>,
+    entry: &Entry<R>,
+    name: DwAt,
+) -> Result<Option<AttributeValue<R>>, Error> {
+    match origin_with(unit, entry, name)? {
+        Some(entry) => Ok(entry.attr_value(name)?),
+        None => Ok(None),
+    }
+}
+
+/// Size in bytes of the type at the given offset, if known.
+fn type_size<R: Reader>(
+    unit: &Unit<R>,
+    offset: UnitOffset<R::Offset>,
+) -> Result<Option<u64>, Error> {
+    let mut offset = offset;
+    // Modifier chains are short in practice, and the limit guards against cycles.
+    for _ in 0..16 {
+        let entry = unit.entry(offset)?;
+        if let Some(size) = byte_size(&entry)? {
+            return Ok(Some(size));
+        }
+        match entry.tag() {
+            DW_TAG_pointer_type | DW_TAG_reference_type | DW_TAG_rvalue_reference_type => {
+                return Ok(Some(u64::from(unit.encoding().address_size)));
+            }
+            DW_TAG_typedef | DW_TAG_const_type | DW_TAG_volatile_type => {
+                match entry.attr_value(DW_AT_type)? {
+                    Some(AttributeValue::UnitRef(next)) => offset = next,
+                    _ => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+fn is_absolute(path: &str) -> bool {
+    path.starts_with('/') || path.starts_with('\\') || path.get(1..2) == Some(":")
+}
+
+fn join(directory: &str, path: &str) -> String {
+    if directory.ends_with('/') || directory.ends_with('\\') {
+        format!("{}{}", directory, path)
+    } else if directory.contains('\\') && !directory.contains('/') {
+        format!("{}\\{}", directory, path)
+    } else {
+        format!("{}/{}", directory, path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file::{ChecksumKind, FileChecksum, FileChecksums};
+    use crate::section_write::SectionSink;
+    use crate::subsection::Subsection;
+    use gimli::write::{
+        Address, AttributeValue as Value, DwarfUnit, EndianVec, Expression, LineProgram,
+        LineString, Location as Loc, LocationList, Sections, UnitEntryId,
+    };
+    use gimli::{Encoding, EndianSlice, Format, LittleEndian, Register};
+
+    fn add(
+        unit: &mut gimli::write::Unit,
+        parent: UnitEntryId,
+        tag: DwTag,
+        name: &str,
+        attributes: Vec<(DwAt, Value)>,
+    ) -> UnitEntryId {
+        let id = unit.add(parent, tag);
+        if !name.is_empty() {
+            unit.get_mut(id)
+                .set(DW_AT_name, Value::String(name.as_bytes().to_vec()));
+        }
+        for (name, value) in attributes {
+            unit.get_mut(id).set(name, value);
+        }
+        id
+    }
+
+    fn expression(build: impl FnOnce(&mut Expression)) -> Expression {
+        let mut expression = Expression::new();
+        build(&mut expression);
+        expression
+    }
+
+    fn location(begin: u64, end: u64, data: Expression) -> Loc {
+        Loc::StartEnd {
+            begin: Address::Constant(begin),
+            end: Address::Constant(end),
+            data,
+        }
+    }
+
+    fn build_dwarf() -> Sections<EndianVec<LittleEndian>> {
+        let encoding = Encoding {
+            format: Format::Dwarf32,
+            version: 5,
+            address_size: 8,
+        };
+        let mut dwarf = DwarfUnit::new(encoding);
+        let string = |s: &str| LineString::String(s.as_bytes().to_vec());
+        let mut program = LineProgram::new(
+            encoding,
+            Default::default(),
+            string("/src"),
+            string("main.c"),
+            None,
+        );
+        let directory = program.default_directory();
+        let main_c = program.add_file(string("main.c"), directory, None);
+        let util_h = program.add_file(string("include/util.h"), directory, None);
+        program.begin_sequence(Some(Address::Constant(0x1000)));
+        let rows = [
+            (0, main_c, 1),
+            (8, main_c, 2),
+            (0x20, util_h, 10),
+            (0x28, main_c, 3),
+        ];
+        for (offset, file, line) in rows {
+            program.row().address_offset = offset;
+            program.row().file = file;
+            program.row().line = line;
+            program.generate_row();
+        }
+        program.end_sequence(0x40);
+        dwarf.unit.line_program = program;
+
+        let unit = &mut dwarf.unit;
+        let root = unit.root();
+        unit.get_mut(root)
+            .set(DW_AT_comp_dir, Value::String(b"/src".to_vec()));
+
+        let int = add(
+            unit,
+            root,
+            DW_TAG_base_type,
+            "int",
+            vec![
+                (DW_AT_encoding, Value::Encoding(DW_ATE_signed)),
+                (DW_AT_byte_size, Value::Udata(4)),
+            ],
+        );
+        let node = add(
+            unit,
+            root,
+            DW_TAG_structure_type,
+            "node",
+            vec![(DW_AT_byte_size, Value::Udata(16))],
+        );
+        let pointer = add(
+            unit,
+            root,
+            DW_TAG_pointer_type,
+            "",
+            vec![(DW_AT_type, Value::UnitRef(node))],
+        );
+        let members = [
+            ("value", int, Some(0)),
+            ("next", pointer, Some(8)),
+            ("flags", int, None),
+        ];
+        for (name, member_type, offset) in members {
+            let location = match offset {
+                Some(offset) => (DW_AT_data_member_location, Value::Udata(offset)),
+                None => (DW_AT_bit_size, Value::Udata(3)),
+            };
+            let attributes = vec![(DW_AT_type, Value::UnitRef(member_type)), location];
+            add(unit, node, DW_TAG_member, name, attributes);
+        }
+
+        let namespace = add(unit, root, DW_TAG_namespace, "ns", vec![]);
+        let color = add(
+            unit,
+            namespace,
+            DW_TAG_enumeration_type,
+            "color",
+            vec![(DW_AT_type, Value::UnitRef(int))],
+        );
+        let enumerators = [("red", Value::Sdata(-1)), ("green", Value::Udata(1))];
+        for (name, value) in enumerators {
+            let attributes = vec![(DW_AT_const_value, value)];
+            add(unit, color, DW_TAG_enumerator, name, attributes);
+        }
+        let grid = add(
+            unit,
+            root,
+            DW_TAG_array_type,
+            "",
+            vec![(DW_AT_type, Value::UnitRef(int))],
+        );
+        let count = vec![(DW_AT_count, Value::Udata(3))];
+        add(unit, grid, DW_TAG_subrange_type, "", count);
+        let upper_bound = vec![(DW_AT_upper_bound, Value::Udata(3))];
+        add(unit, grid, DW_TAG_subrange_type, "", upper_bound);
+        let bounds = [
+            vec![(DW_AT_upper_bound, Value::Udata(u64::MAX))],
+            vec![
+                (DW_AT_lower_bound, Value::Udata(5)),
+                (DW_AT_upper_bound, Value::Udata(3)),
+            ],
+            vec![(DW_AT_count, Value::Udata(u64::MAX))],
+        ];
+        for attributes in bounds {
+            let element_type = vec![(DW_AT_type, Value::UnitRef(int))];
+            let array = add(unit, root, DW_TAG_array_type, "", element_type);
+            add(unit, array, DW_TAG_subrange_type, "", attributes);
+        }
+
+        let square = add(
+            unit,
+            namespace,
+            DW_TAG_subprogram,
+            "square",
+            vec![
+                (DW_AT_type, Value::UnitRef(int)),
+                (DW_AT_inline, Value::Inline(DW_INL_inlined)),
+            ],
+        );
+        let square_x = add(
+            unit,
+            square,
+            DW_TAG_formal_parameter,
+            "x",
+            vec![(DW_AT_type, Value::UnitRef(int))],
+        );
+
+        let main = add(
+            unit,
+            root,
+            DW_TAG_subprogram,
+            "main",
+            vec![
+                (DW_AT_external, Value::Flag(true)),
+                (DW_AT_type, Value::UnitRef(int)),
+                (DW_AT_low_pc, Value::Address(Address::Constant(0x1000))),
+                (DW_AT_high_pc, Value::Udata(0x40)),
+                (
+                    DW_AT_frame_base,
+                    Value::Exprloc(expression(|e| e.op_reg(Register(6)))),
+                ),
+            ],
+        );
+        add(
+            unit,
+            main,
+            DW_TAG_formal_parameter,
+            "argc",
+            vec![
+                (DW_AT_type, Value::UnitRef(int)),
+                (
+                    DW_AT_location,
+                    Value::Exprloc(expression(|e| e.op_fbreg(-20))),
+                ),
+            ],
+        );
+        let list = unit.locations.add(LocationList(vec![
+            location(0x1000, 0x1010, expression(|e| e.op_breg(Register(7), 8))),
+            location(0x1010, 0x1030, expression(|e| e.op_reg(Register(3)))),
+            location(0x1030, 0x1040, expression(|e| e.op_constu(0))),
+        ]));
+        add(
+            unit,
+            main,
+            DW_TAG_variable,
+            "list",
+            vec![
+                (DW_AT_type, Value::UnitRef(pointer)),
+                (DW_AT_location, Value::LocationListRef(list)),
+            ],
+        );
+        add(
+            unit,
+            main,
+            DW_TAG_variable,
+            "grid",
+            vec![
+                (DW_AT_type, Value::UnitRef(grid)),
+                (
+                    DW_AT_location,
+                    Value::Exprloc(expression(|e| e.op_fbreg(-48))),
+                ),
+            ],
+        );
+        let block = add(
+            unit,
+            main,
+            DW_TAG_lexical_block,
+            "",
+            vec![
+                (DW_AT_low_pc, Value::Address(Address::Constant(0x1010))),
+                (DW_AT_high_pc, Value::Udata(0x10)),
+            ],
+        );
+        add(
+            unit,
+            block,
+            DW_TAG_variable,
+            "i",
+            vec![
+                (DW_AT_type, Value::UnitRef(int)),
+                (
+                    DW_AT_location,
+                    Value::Exprloc(expression(|e| e.op_reg(Register(0)))),
+                ),
+            ],
+        );
+        let inlined = add(
+            unit,
+            main,
+            DW_TAG_inlined_subroutine,
+            "",
+            vec![
+                (DW_AT_abstract_origin, Value::UnitRef(square)),
+                (DW_AT_low_pc, Value::Address(Address::Constant(0x1020))),
+                (DW_AT_high_pc, Value::Udata(8)),
+            ],
+        );
+        add(
+            unit,
+            inlined,
+            DW_TAG_formal_parameter,
+            "",
+            vec![
+                (DW_AT_abstract_origin, Value::UnitRef(square_x)),
+                (
+                    DW_AT_location,
+                    Value::Exprloc(expression(|e| e.op_reg(Register(1)))),
+                ),
+            ],
+        );
+        add(
+            unit,
+            main,
+            DW_TAG_variable,
+            "c",
+            vec![(DW_AT_type, Value::UnitRef(color))],
+        );
+        add(
+            unit,
+            main,
+            DW_TAG_label,
+            "retry",
+            vec![(DW_AT_low_pc, Value::Address(Address::Constant(0x1030)))],
+        );
+        add(unit, main, DW_TAG_call_site, "", vec![]);
+        add(
+            unit,
+            root,
+            DW_TAG_variable,
+            "counter",
+            vec![
+                (DW_AT_type, Value::UnitRef(int)),
+                (
+                    DW_AT_location,
+                    Value::Exprloc(expression(|e| e.op_addr(Address::Constant(0x3000)))),
+                ),
+            ],
+        );
+
+        let mut sections = Sections::new(EndianVec::new(LittleEndian));
+        dwarf.write(&mut sections).unwrap();
+        sections
+    }
+
+    #[test]
+    fn conversion() {
+        let sections = build_dwarf();
+        let dwarf = gimli::Dwarf::load(|id| -> gimli::Result<_> {
+            let data = sections.get(id).map_or(&[][..], |section| section.slice());
+            Ok(EndianSlice::new(data, LittleEndian))
+        })
+        .unwrap();
+        let mut codeview = Codeview::<u64>::new();
+        let conversion = convert_dwarf(&dwarf, MACHINE_X64, &mut codeview, Some).unwrap();
+
+        let structure = |id| match codeview.leaf(id) {
+            Some(Leaf::Structure(structure)) => structure.clone(),
+            leaf => panic!("{:?}", leaf),
+        };
+        let procedure = match &conversion.symbols[0] {
+            Symbol::GlobalProc32(procedure) => procedure,
+            symbol => panic!("{:?}", symbol),
+        };
+        assert_eq!(procedure.name, "main");
+        assert_eq!(procedure.length, 0x40);
+        assert_eq!(procedure.offset.0, 0x1000);
+
+        let mut locals = HashMap::new();
+        for symbol in &conversion.symbols {
+            match symbol {
+                Symbol::RegRel32(symbol) => {
+                    locals.insert(symbol.name.clone(), symbol.variable_type);
+                }
+                Symbol::Local(symbol) => {
+                    locals.insert(symbol.name.clone(), symbol.variable_type);
+                }
+                _ => (),
+            }
+        }
+        let node = match codeview.leaf(locals["list"]) {
+            Some(Leaf::Pointer(pointer)) => structure(pointer.pointee),
+            leaf => panic!("{:?}", leaf),
+        };
+        assert_eq!(node.name, "node");
+        assert_eq!(node.properties, FORWARD_REF);
+        let node = structure(LeafId(node_definition(&codeview)));
+        assert_eq!(node.member_count, 2);
+        assert_eq!(node.size, Numeric::Unsigned(16));
+        match codeview.leaf(locals["grid"]) {
+            Some(Leaf::Array(array)) => {
+                assert_eq!(array.size, Numeric::Unsigned(48));
+                match codeview.leaf(array.element_type) {
+                    Some(Leaf::Array(array)) => {
+                        assert_eq!(array.size, Numeric::Unsigned(16));
+                        assert_eq!(array.element_type, LeafId::primitive(Primitive::I32));
+                    }
+                    leaf => panic!("{:?}", leaf),
+                }
+            }
+            leaf => panic!("{:?}", leaf),
+        }
+        match codeview.leaf(locals["c"]) {
+            Some(Leaf::Enum(e)) => {
+                assert_eq!(e.name, "ns::color");
+                assert_eq!(e.member_count, 2);
+            }
+            leaf => panic!("{:?}", leaf),
+        }
+
+        let kinds: Vec<String> = conversion
+            .symbols
+            .iter()
+            .map(|symbol| match symbol {
+                Symbol::GlobalProc32(symbol) => format!("proc {}", symbol.name),
+                Symbol::RegRel32(symbol) => {
+                    format!(
+                        "regrel {} {} {}",
+                        symbol.name, symbol.register, symbol.offset
+                    )
+                }
+                Symbol::Register(symbol) => format!("reg {} {}", symbol.name, symbol.register),
+                Symbol::Local(symbol) => format!("local {} {:#x}", symbol.name, symbol.flags),
+                Symbol::DefRangeRegister(symbol) => format!(
+                    "range {} {:#x}+{:#x}",
+                    symbol.register, symbol.range.offset.0, symbol.range.length
+                ),
+                Symbol::DefRangeRegisterRel(symbol) => format!(
+                    "range {}{:+} {:#x}+{:#x}",
+                    symbol.base_register,
+                    symbol.base_offset,
+                    symbol.range.offset.0,
+                    symbol.range.length
+                ),
+                Symbol::Block32(symbol) => {
+                    format!("block {:#x}+{:#x}", symbol.offset.0, symbol.length)
+                }
+                Symbol::InlineSite(symbol) => match codeview.leaf(symbol.inlinee) {
+                    Some(Leaf::FuncId(inlinee)) => {
+                        format!("inline {} {:?}", inlinee.name, symbol.annotations)
+                    }
+                    leaf => panic!("{:?}", leaf),
+                },
+                Symbol::Label32(symbol) => format!("label {} {:#x}", symbol.name, symbol.offset.0),
+                Symbol::End(_) | Symbol::InlineSiteEnd(_) => "end".to_string(),
+                symbol => panic!("{:?}", symbol),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "proc main",
+                "regrel argc 334 -20",
+                "local list 0x0",
+                "range 335+8 0x1000+0x10",
+                "range 329 0x1010+0x20",
+                "regrel grid 334 -48",
+                "block 0x1010+0x10",
+                "reg i 328",
+                "end",
+                "inline ns::square [ChangeCodeOffset(32), ChangeCodeLength(8)]",
+                "reg x 331",
+                "end",
+                "local c 0x100",
+                "label retry 0x1030",
+                "end",
+            ]
+        );
+
+        assert_eq!(conversion.files, ["/src/main.c", "/src/include/util.h"]);
+        assert_eq!(conversion.lines.len(), 1);
+        let lines = &conversion.lines[0];
+        assert_eq!(lines.address, 0x1000);
+        assert_eq!(lines.code_length, 0x40);
+        let blocks: Vec<_> = lines
+            .blocks
+            .iter()
+            .map(|block| {
+                let lines: Vec<_> = block
+                    .lines
+                    .iter()
+                    .map(|line| (line.offset, line.line_start))
+                    .collect();
+                (block.file.index(), lines)
+            })
+            .collect();
+        assert_eq!(
+            blocks,
+            [
+                (0, vec![(0, 1), (8, 2)]),
+                (8, vec![(0x20, 10)]),
+                (0, vec![(0x28, 3)]),
+            ]
+        );
+
+        let mut unsupported: Vec<_> = conversion
+            .unsupported
+            .iter()
+            .map(|unsupported| unsupported.construct.as_str())
+            .collect();
+        unsupported.sort_unstable();
+        assert_eq!(
+            unsupported,
+            [
+                "Bit field DW_TAG_member",
+                "DW_AT_location list entry",
+                "DW_TAG_array_type of out of range size",
+                "DW_TAG_call_site",
+                "DW_TAG_subrange_type with out of range bounds",
+                "DW_TAG_subrange_type with out of range bounds",
+                "Global DW_TAG_variable",
+            ]
+        );
+
+        codeview.add_subsection(Subsection::Symbols(conversion.symbols));
+        let mut checksums = FileChecksums::default();
+        for path in &conversion.files {
+            let id = checksums
+                .add(FileChecksum {
+                    name: codeview.add_string(path).unwrap(),
+                    kind: ChecksumKind::None,
+                    checksum: vec![],
+                })
+                .unwrap();
+            assert!(conversion.lines[0]
+                .blocks
+                .iter()
+                .any(|block| block.file == id));
+        }
+        codeview.add_subsection(Subsection::FileChecksums(checksums));
+        for lines in conversion.lines {
+            codeview.add_subsection(Subsection::Lines(lines));
+        }
+        codeview.validate().unwrap();
+        codeview.write(|_| SectionSink::new()).unwrap();
+
+        let error =
+            convert_dwarf(&dwarf, MACHINE_X64, &mut Codeview::<u64>::new(), |_| None).unwrap_err();
+        assert!(matches!(error, Error::UnknownAddress(0x1000)));
+    }
+
+    /// Leaf index of the definition of `node`, which follows its field list.
+    fn node_definition(codeview: &Codeview<u64>) -> u32 {
+        (0x1000..)
+            .find(|&index| match codeview.leaf(LeafId(index)) {
+                Some(Leaf::Structure(structure)) => structure.properties == 0,
+                _ => false,
+            })
+            .unwrap()
+    }
+}
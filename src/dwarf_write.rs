@@ -0,0 +1,1593 @@
+//! Translation of CodeView records into DWARF debugging information.
+//!
+//! Type leaves become type entries, procedures become subprograms containing their variables,
+//! blocks, labels and inlined subroutines, and `Lines` subsections become line program sequences.
+//! Qualified names are kept as they are instead of being split into namespaces, and forward
+//! references are resolved to the definition with the same name when there is one. Records and
+//! fields that have no translation are reported in `CodeviewConversion::unsupported`. End line
+//! deltas of lines and source lines of inline sites are not translated.
+
+use crate::codeview::Codeview;
+use crate::dwarf::{
+    codeview_register, FORWARD_REF, IS_OPTIMIZED_OUT, IS_PARAMETER, PRIVATE, PROTECTED, PUBLIC,
+};
+use crate::file::FileChecksums;
+use crate::leaf::{self, Field, Leaf, LeafId, Numeric, Primitive};
+use crate::line::Lines;
+use crate::section_write::MACHINE_X86;
+use crate::subsection::Subsection;
+use crate::symbol::{self, AddressGap, AddressRange, BinaryAnnotation, SourceLanguage, Symbol};
+use gimli::constants::*;
+use gimli::write::{
+    Address, AttributeValue as Value, DwarfUnit, Expression, LineProgram, LineString, Location,
+    LocationList, Range, RangeList, UnitEntryId,
+};
+use gimli::{Encoding, Format, LineEncoding, Register};
+use std::collections::HashMap;
+
+/// A CodeView record, or part of one, that has no DWARF translation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedCodeview {
+    /// Record containing the construct.
+    pub record: CodeviewRecord,
+
+    /// Description of the construct, such as `"Symbol::Thunk32"`.
+    pub construct: String,
+}
+
+/// Position of a record in a `Codeview`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeviewRecord {
+    /// A leaf.
+    Leaf(LeafId),
+
+    /// A symbol in a `Subsection::Symbols`, given by the indices of the subsection and the symbol.
+    Symbol { subsection: usize, index: usize },
+
+    /// A subsection, such as a `Subsection::Lines`.
+    Subsection(usize),
+
+    /// The reference to a type server or precompiled types that leaf indices refer to.
+    Types,
+}
+
+/// DWARF translated from CodeView by `convert_codeview`.
+#[derive(Debug)]
+pub struct CodeviewConversion {
+    /// The compilation unit, to be written with `DwarfUnit::write`.
+    pub dwarf: DwarfUnit,
+
+    /// Constructs that were not translated.
+    pub unsupported: Vec<UnsupportedCodeview>,
+}
+
+/// Translate the leaves and subsections of `codeview` into a DWARF compilation unit.
+///
+/// `machine` is the COFF machine type (`MACHINE_*`), which determines the address size and how
+/// CodeView register numbers map to DWARF ones. `files` are the paths of the source files
+/// referenced by `Lines` subsections, in the order of the `Subsection::FileChecksums` entries.
+/// Without that subsection, a `FileId` refers to the path at its index divided by 8, as in
+/// `DwarfConversion::files`. The first path is the name of the compilation unit.
+/// `address` maps a relocation to the address it refers to.
+pub fn convert_codeview<Reloc, F>(
+    codeview: &Codeview<Reloc>,
+    machine: u16,
+    files: &[String],
+    address: F,
+) -> CodeviewConversion
+where
+    F: FnMut(&Reloc) -> Address,
+{
+    let encoding = Encoding {
+        format: Format::Dwarf32,
+        version: 4,
+        address_size: if machine == MACHINE_X86 { 4 } else { 8 },
+    };
+    let mut dwarf = DwarfUnit::new(encoding);
+    if let Some(path) = files.first() {
+        let (directory, _) = split_path(path);
+        let directory = directory.unwrap_or(".");
+        let root = dwarf.unit.root();
+        let root = dwarf.unit.get_mut(root);
+        root.set(DW_AT_name, Value::String(path.as_bytes().to_vec()));
+        root.set(DW_AT_comp_dir, Value::String(directory.as_bytes().to_vec()));
+        dwarf.unit.line_program = LineProgram::new(
+            encoding,
+            LineEncoding::default(),
+            LineString::String(directory.as_bytes().to_vec()),
+            LineString::String(path.as_bytes().to_vec()),
+            None,
+        );
+    }
+
+    let mut converter = Converter {
+        codeview,
+        machine,
+        files,
+        address,
+        dwarf,
+        types: HashMap::new(),
+        definitions: HashMap::new(),
+        inlinees: HashMap::new(),
+        file_ids: HashMap::new(),
+        checksums: None,
+        procedures: vec![],
+        record: CodeviewRecord::Subsection(0),
+        unsupported: vec![],
+    };
+    converter.convert();
+    CodeviewConversion {
+        dwarf: converter.dwarf,
+        unsupported: converter.unsupported,
+    }
+}
+
+/// Key of a structure, union or enumeration, shared by its forward reference and definition.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum TypeName {
+    Structure(String),
+    Union(String),
+    Enum(String),
+}
+
+/// Location of a variable in a code range.
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    Register(u16),
+    RegisterRelative(u16, i32),
+}
+
+struct Converter<'a, Reloc, F> {
+    codeview: &'a Codeview<Reloc>,
+    machine: u16,
+    files: &'a [String],
+    address: F,
+    dwarf: DwarfUnit,
+    types: HashMap<LeafId, Option<UnitEntryId>>,
+    definitions: HashMap<TypeName, LeafId>,
+    inlinees: HashMap<LeafId, Option<UnitEntryId>>,
+    file_ids: HashMap<u32, gimli::write::FileId>,
+    checksums: Option<&'a FileChecksums>,
+    procedures: Vec<(Address, u64)>,
+    /// Record being translated, which unsupported constructs are reported in.
+    record: CodeviewRecord,
+    unsupported: Vec<UnsupportedCodeview>,
+}
+
+impl<'a, Reloc, F> Converter<'a, Reloc, F>
+where
+    F: FnMut(&Reloc) -> Address,
+{
+    fn convert(&mut self) {
+        let leafs = match self.codeview.local_leafs() {
+            Some(leafs) => leafs,
+            None => {
+                self.record = CodeviewRecord::Types;
+                self.report("Types in a type server or precompiled types");
+                &[]
+            }
+        };
+        for (index, leaf) in (0x1000..).zip(leafs) {
+            if let Some((name, properties)) = type_name(leaf) {
+                if properties & FORWARD_REF == 0 {
+                    self.definitions.entry(name).or_insert(LeafId(index));
+                }
+            }
+        }
+        // Named types are translated even if no symbol refers to them.
+        for (index, leaf) in (0x1000..).zip(leafs) {
+            if matches!(type_name(leaf), Some((_, properties)) if properties & FORWARD_REF == 0) {
+                self.type_entry(LeafId(index));
+            }
+        }
+
+        let subsections = self.codeview.subsections();
+        for (index, subsection) in subsections.iter().enumerate() {
+            self.record = CodeviewRecord::Subsection(index);
+            match subsection {
+                Subsection::Symbols(symbols) => self.symbols(index, symbols),
+                Subsection::Lines(_) => (),
+                Subsection::FileChecksums(checksums) => self.checksums = Some(checksums),
+                Subsection::FrameData(_) => self.report("Subsection::FrameData"),
+            }
+        }
+        // File IDs come from the file checksum subsection, which may follow the lines.
+        for (index, subsection) in subsections.iter().enumerate() {
+            if let Subsection::Lines(lines) = subsection {
+                self.record = CodeviewRecord::Subsection(index);
+                self.lines(lines);
+            }
+        }
+
+        if !self.procedures.is_empty() {
+            let ranges = self
+                .procedures
+                .iter()
+                .map(|&(begin, length)| Range::StartLength { begin, length })
+                .collect();
+            let ranges = self.dwarf.unit.ranges.add(RangeList(ranges));
+            let root = self.dwarf.unit.root();
+            let root = self.dwarf.unit.get_mut(root);
+            // Range and location lists are relative to the base address of the unit.
+            root.set(DW_AT_low_pc, Value::Address(Address::Constant(0)));
+            root.set(DW_AT_ranges, Value::RangeListRef(ranges));
+        }
+    }
+
+    fn report(&mut self, construct: impl ToString) {
+        self.unsupported.push(UnsupportedCodeview {
+            record: self.record,
+            construct: construct.to_string(),
+        });
+    }
+
+    fn add(&mut self, parent: UnitEntryId, tag: DwTag, name: &str) -> UnitEntryId {
+        let id = self.dwarf.unit.add(parent, tag);
+        if !name.is_empty() {
+            self.set(id, DW_AT_name, Value::String(name.as_bytes().to_vec()));
+        }
+        id
+    }
+
+    fn set(&mut self, id: UnitEntryId, name: DwAt, value: Value) {
+        self.dwarf.unit.get_mut(id).set(name, value);
+    }
+
+    /// Set `DW_AT_type` to the entry of a leaf index, leaving it out for `void`.
+    fn set_type(&mut self, id: UnitEntryId, type_id: LeafId) {
+        if let Some(entry) = self.type_entry(type_id) {
+            self.set(id, DW_AT_type, Value::UnitRef(entry));
+        }
+    }
+
+    /// Entry of the type with a leaf index, or `None` for `void` and untranslated types.
+    fn type_entry(&mut self, id: LeafId) -> Option<UnitEntryId> {
+        if id == LeafId::null() {
+            return None;
+        }
+        if let Some(entry) = self.types.get(&id) {
+            return *entry;
+        }
+        let record = std::mem::replace(&mut self.record, CodeviewRecord::Leaf(id));
+        let codeview = self.codeview;
+        let entry = match codeview.leaf(id) {
+            _ if id.is_primitive() => self.primitive(id),
+            Some(leaf) => self.leaf(id, leaf),
+            None => {
+                self.report(format!("Leaf index {} outside this object", id));
+                None
+            }
+        };
+        self.types.insert(id, entry);
+        self.record = record;
+        entry
+    }
+
+    fn primitive(&mut self, id: LeafId) -> Option<UnitEntryId> {
+        let root = self.dwarf.unit.root();
+        let primitive = match Primitive::from_index(id.index() & 0xFF) {
+            Some(primitive) => primitive,
+            None => {
+                self.report(format!("Primitive type {}", id));
+                return None;
+            }
+        };
+        match id.index() >> 8 {
+            0 if primitive == Primitive::Void => None,
+            0 => {
+                let (name, encoding) = base_type(primitive);
+                let entry = self.add(root, DW_TAG_base_type, name);
+                self.set(entry, DW_AT_encoding, Value::Encoding(encoding));
+                let size = primitive.size().unwrap_or(0);
+                self.set(entry, DW_AT_byte_size, Value::Udata(size));
+                Some(entry)
+            }
+            mode @ (4 | 6) => {
+                let entry = self.add(root, DW_TAG_pointer_type, "");
+                let size = if mode == 6 { 8 } else { 4 };
+                self.set(entry, DW_AT_byte_size, Value::Udata(size));
+                self.set_type(entry, LeafId::primitive(primitive));
+                Some(entry)
+            }
+            mode => {
+                self.report(format!("Primitive pointer mode {:#x}", mode));
+                None
+            }
+        }
+    }
+
+    fn leaf(&mut self, id: LeafId, leaf: &'a Leaf) -> Option<UnitEntryId> {
+        let root = self.dwarf.unit.root();
+        match leaf {
+            Leaf::Pointer(pointer) => {
+                let tag = match (pointer.attributes >> 5) & 7 {
+                    0 => DW_TAG_pointer_type,
+                    1 => DW_TAG_reference_type,
+                    4 => DW_TAG_rvalue_reference_type,
+                    mode => {
+                        self.report(format!("Pointer mode {}", mode));
+                        return None;
+                    }
+                };
+                let entry = self.add(root, tag, "");
+                let size = (pointer.attributes >> 13) & 0x3F;
+                self.set(entry, DW_AT_byte_size, Value::Udata(size.into()));
+                self.set_type(entry, pointer.pointee);
+                Some(entry)
+            }
+            Leaf::Modifier(modifier) => {
+                let mut entry = self.type_entry(modifier.modified_type);
+                if modifier.modifiers & 4 != 0 {
+                    self.report("Unaligned modifier");
+                }
+                for (bit, tag) in [(2, DW_TAG_volatile_type), (1, DW_TAG_const_type)] {
+                    if modifier.modifiers & bit != 0 {
+                        let qualified = self.add(root, tag, "");
+                        if let Some(entry) = entry {
+                            self.set(qualified, DW_AT_type, Value::UnitRef(entry));
+                        }
+                        entry = Some(qualified);
+                    }
+                }
+                entry
+            }
+            Leaf::Structure(structure) => {
+                if structure.derived_from != LeafId::null() {
+                    self.report("Derivation list of Leaf::Structure");
+                }
+                if structure.vtable_shape != LeafId::null() {
+                    self.report("Virtual function table shape of Leaf::Structure");
+                }
+                self.aggregate(id, leaf, DW_TAG_structure_type, &structure.size)
+            }
+            Leaf::Union(union) => self.aggregate(id, leaf, DW_TAG_union_type, &union.size),
+            Leaf::Enum(e) => self.enumeration(id, leaf, e),
+            Leaf::Array(array) => {
+                let entry = self.add(root, DW_TAG_array_type, &array.name);
+                let size = numeric(&array.size);
+                self.set(entry, DW_AT_byte_size, Value::Udata(size));
+                self.set_type(entry, array.element_type);
+                let subrange = self.add(entry, DW_TAG_subrange_type, "");
+                match self.type_size(array.element_type) {
+                    Some(element_size) if element_size > 0 => {
+                        let count = Value::Udata(size / element_size);
+                        self.set(subrange, DW_AT_count, count);
+                    }
+                    _ => self.report("Array of elements of unknown size"),
+                }
+                Some(entry)
+            }
+            Leaf::Procedure(procedure) => {
+                let entry = self.add(root, DW_TAG_subroutine_type, "");
+                self.set(entry, DW_AT_prototyped, Value::Flag(true));
+                self.set_type(entry, procedure.return_type);
+                let codeview = self.codeview;
+                let arguments = match codeview.leaf(procedure.argument_list) {
+                    Some(Leaf::ArgList(list)) => &list.arguments[..],
+                    _ => {
+                        self.report("Argument list that isn't a Leaf::ArgList");
+                        &[]
+                    }
+                };
+                for &argument in arguments {
+                    // A trailing `T_NOTYPE` marks variadic functions.
+                    if argument == LeafId::null() {
+                        self.add(entry, DW_TAG_unspecified_parameters, "");
+                    } else {
+                        let parameter = self.add(entry, DW_TAG_formal_parameter, "");
+                        self.set_type(parameter, argument);
+                    }
+                }
+                Some(entry)
+            }
+            leaf => {
+                self.report(format!("Leaf::{} as a type", leaf::kind_name(leaf)));
+                None
+            }
+        }
+    }
+
+    /// Entry of a forward reference, which is the definition with the same name if there is one.
+    fn forward_ref(&mut self, leaf: &Leaf, tag: DwTag) -> Option<UnitEntryId> {
+        let (name, _) = type_name(leaf)?;
+        if let Some(&definition) = self.definitions.get(&name) {
+            return self.type_entry(definition);
+        }
+        let name = match name {
+            TypeName::Structure(name) | TypeName::Union(name) | TypeName::Enum(name) => name,
+        };
+        let root = self.dwarf.unit.root();
+        let entry = self.add(root, tag, &name);
+        self.set(entry, DW_AT_declaration, Value::Flag(true));
+        Some(entry)
+    }
+
+    fn aggregate(
+        &mut self,
+        id: LeafId,
+        leaf: &'a Leaf,
+        tag: DwTag,
+        size: &Numeric,
+    ) -> Option<UnitEntryId> {
+        let (properties, field_list, name) = match leaf {
+            Leaf::Structure(s) => (s.properties, s.field_list, &s.name),
+            Leaf::Union(u) => (u.properties, u.field_list, &u.name),
+            _ => return None,
+        };
+        if properties & FORWARD_REF != 0 {
+            return self.forward_ref(leaf, tag);
+        }
+        let root = self.dwarf.unit.root();
+        let entry = self.add(root, tag, name);
+        // Members may refer back to the aggregate.
+        self.types.insert(id, Some(entry));
+        self.set(entry, DW_AT_byte_size, Value::Udata(numeric(size)));
+        for field in self.fields(field_list) {
+            let (attributes, member_type, name) = match field {
+                Field::Member(member) => (member.attributes, member.member_type, &member.name),
+                Field::StaticMember(member) => {
+                    (member.attributes, member.member_type, &member.name)
+                }
+                Field::VFuncTab(_) => {
+                    self.report("Field::VFuncTab");
+                    continue;
+                }
+                Field::Enumerate(_) => {
+                    self.report("Field::Enumerate of an aggregate");
+                    continue;
+                }
+                Field::Index(_) => continue,
+            };
+            let member = self.add(entry, DW_TAG_member, name);
+            self.set_type(member, member_type);
+            match field {
+                Field::Member(field) => {
+                    let offset = Value::Udata(numeric(&field.offset));
+                    self.set(member, DW_AT_data_member_location, offset);
+                }
+                _ => {
+                    self.set(member, DW_AT_external, Value::Flag(true));
+                    self.set(member, DW_AT_declaration, Value::Flag(true));
+                }
+            }
+            let accessibility = match attributes & 3 {
+                PRIVATE => DW_ACCESS_private,
+                PROTECTED => DW_ACCESS_protected,
+                PUBLIC => DW_ACCESS_public,
+                _ => continue,
+            };
+            self.set(
+                member,
+                DW_AT_accessibility,
+                Value::Accessibility(accessibility),
+            );
+        }
+        Some(entry)
+    }
+
+    fn enumeration(
+        &mut self,
+        id: LeafId,
+        leaf: &'a Leaf,
+        e: &'a leaf::Enum,
+    ) -> Option<UnitEntryId> {
+        if e.properties & FORWARD_REF != 0 {
+            return self.forward_ref(leaf, DW_TAG_enumeration_type);
+        }
+        let root = self.dwarf.unit.root();
+        let entry = self.add(root, DW_TAG_enumeration_type, &e.name);
+        self.types.insert(id, Some(entry));
+        self.set_type(entry, e.underlying_type);
+        if let Some(size) = self.type_size(e.underlying_type) {
+            self.set(entry, DW_AT_byte_size, Value::Udata(size));
+        }
+        for field in self.fields(e.field_list) {
+            match field {
+                Field::Enumerate(enumerate) => {
+                    let enumerator = self.add(entry, DW_TAG_enumerator, &enumerate.name);
+                    let value = match enumerate.value {
+                        Numeric::Signed(value) => Value::Sdata(value),
+                        Numeric::Unsigned(value) => Value::Udata(value),
+                    };
+                    self.set(enumerator, DW_AT_const_value, value);
+                }
+                Field::Index(_) => (),
+                _ => self.report("Field of an enumeration that isn't a Field::Enumerate"),
+            }
+        }
+        Some(entry)
+    }
+
+    /// Members of a field list, following its continuations.
+    fn fields(&mut self, mut field_list: LeafId) -> Vec<&'a Field> {
+        let codeview = self.codeview;
+        let mut fields = vec![];
+        while field_list != LeafId::null() {
+            let list = match codeview.leaf(field_list) {
+                Some(Leaf::FieldList(list)) => list,
+                _ => {
+                    self.report(format!(
+                        "Field list {} that isn't a Leaf::FieldList",
+                        field_list
+                    ));
+                    break;
+                }
+            };
+            let previous = field_list;
+            field_list = LeafId::null();
+            for field in &list.fields {
+                if let Field::Index(index) = field {
+                    field_list = index.continuation;
+                }
+                fields.push(field);
+            }
+            // Continuations are written first, so a later one can only come from a cycle.
+            if field_list >= previous {
+                self.report(format!(
+                    "Field list {} continued by {}",
+                    previous, field_list
+                ));
+                break;
+            }
+        }
+        fields
+    }
+
+    /// Size of a type in bytes, if it is known.
+    fn type_size(&self, id: LeafId) -> Option<u64> {
+        if id.is_primitive() {
+            return match id.index() >> 8 {
+                0 => Primitive::from_index(id.index())?.size(),
+                4 => Some(4),
+                6 => Some(8),
+                _ => None,
+            };
+        }
+        let leaf = self.codeview.leaf(id)?;
+        let definition = match type_name(leaf) {
+            Some((name, properties)) if properties & FORWARD_REF != 0 => {
+                return self.type_size(*self.definitions.get(&name)?);
+            }
+            _ => leaf,
+        };
+        match definition {
+            Leaf::Pointer(pointer) => Some(((pointer.attributes >> 13) & 0x3F).into()),
+            Leaf::Modifier(modifier) => self.type_size(modifier.modified_type),
+            Leaf::Structure(structure) => Some(numeric(&structure.size)),
+            Leaf::Union(union) => Some(numeric(&union.size)),
+            Leaf::Enum(e) => self.type_size(e.underlying_type),
+            Leaf::Array(array) => Some(numeric(&array.size)),
+            _ => None,
+        }
+    }
+
+    /// Entry of the return type of a function type.
+    fn return_type(&mut self, function_type: LeafId) -> Option<UnitEntryId> {
+        let codeview = self.codeview;
+        match codeview.leaf(function_type) {
+            Some(Leaf::Procedure(procedure)) => self.type_entry(procedure.return_type),
+            _ if function_type == LeafId::null() => None,
+            _ => {
+                let construct = format!(
+                    "Function type {} that isn't a Leaf::Procedure",
+                    function_type
+                );
+                self.report(construct);
+                None
+            }
+        }
+    }
+
+    /// DWARF register number of a CodeView register number.
+    fn register(&mut self, register: u16) -> Option<Register> {
+        let dwarf =
+            (0..=32).find(|&dwarf| codeview_register(self.machine, dwarf) == Some(register));
+        if dwarf.is_none() {
+            self.report(format!("Register {}", register));
+        }
+        dwarf.map(Register)
+    }
+
+    fn expression(&mut self, place: Place) -> Option<Expression> {
+        let mut expression = Expression::new();
+        match place {
+            Place::Register(register) => expression.op_reg(self.register(register)?),
+            Place::RegisterRelative(register, offset) => {
+                expression.op_breg(self.register(register)?, offset.into())
+            }
+        }
+        Some(expression)
+    }
+
+    fn symbols(&mut self, subsection: usize, symbols: &'a [Symbol<Reloc>]) {
+        let root = self.dwarf.unit.root();
+        let mut scopes = vec![root];
+        // Scope and start of the innermost procedure, which inline site code offsets are
+        // relative to.
+        let mut procedure = None;
+        // The `Symbol::Local` that `DefRange*` symbols add locations to.
+        let mut local: Option<(UnitEntryId, Vec<Location>)> = None;
+        for (index, symbol) in symbols.iter().enumerate() {
+            self.record = CodeviewRecord::Symbol { subsection, index };
+            let parent = scopes[scopes.len() - 1];
+            let (range, gaps, place) = match symbol {
+                Symbol::DefRangeRegister(symbol) => (
+                    &symbol.range,
+                    &symbol.gaps,
+                    Place::Register(symbol.register),
+                ),
+                Symbol::DefRangeRegisterRel(symbol) => {
+                    if symbol.flags & 1 != 0 {
+                        self.report("Spilled member of Symbol::DefRangeRegisterRel");
+                        continue;
+                    }
+                    let place = Place::RegisterRelative(symbol.base_register, symbol.base_offset);
+                    (&symbol.range, &symbol.gaps, place)
+                }
+                _ => {
+                    self.finish_local(local.take());
+                    self.symbol(symbol, parent, &mut scopes, &mut procedure, &mut local);
+                    continue;
+                }
+            };
+            if local.is_none() {
+                let kind = symbol::kind_name(symbol);
+                self.report(format!("Symbol::{} without a Symbol::Local", kind));
+                continue;
+            }
+            if let Some(data) = self.expression(place) {
+                for (begin, length) in self.address_ranges(range, gaps) {
+                    let data = data.clone();
+                    let location = Location::StartLength {
+                        begin,
+                        length,
+                        data,
+                    };
+                    if let Some((_, locations)) = &mut local {
+                        locations.push(location);
+                    }
+                }
+            }
+        }
+        self.finish_local(local);
+        if scopes.len() > 1 {
+            self.record = CodeviewRecord::Subsection(subsection);
+            self.report("Scope without Symbol::End");
+        }
+    }
+
+    fn symbol(
+        &mut self,
+        symbol: &'a Symbol<Reloc>,
+        parent: UnitEntryId,
+        scopes: &mut Vec<UnitEntryId>,
+        procedure: &mut Option<(UnitEntryId, Address)>,
+        local: &mut Option<(UnitEntryId, Vec<Location>)>,
+    ) {
+        match symbol {
+            Symbol::GlobalProc32(symbol) => {
+                let address = (self.address)(&symbol.offset.0);
+                let entry = self.add(parent, DW_TAG_subprogram, &symbol.name);
+                self.set(entry, DW_AT_external, Value::Flag(true));
+                self.procedure(entry, address, symbol.length, symbol.function_type);
+                *procedure = Some((entry, address));
+                scopes.push(entry);
+            }
+            Symbol::LocalProc32(symbol) => {
+                let address = (self.address)(&symbol.offset.0);
+                let entry = self.add(parent, DW_TAG_subprogram, &symbol.name);
+                self.procedure(entry, address, symbol.length, symbol.function_type);
+                *procedure = Some((entry, address));
+                scopes.push(entry);
+            }
+            Symbol::Block32(symbol) => {
+                let address = (self.address)(&symbol.offset.0);
+                let entry = self.add(parent, DW_TAG_lexical_block, &symbol.name);
+                self.set(entry, DW_AT_low_pc, Value::Address(address));
+                self.set(entry, DW_AT_high_pc, Value::Udata(symbol.length.into()));
+                scopes.push(entry);
+            }
+            Symbol::InlineSite(symbol) => {
+                let entry = self.add(parent, DW_TAG_inlined_subroutine, "");
+                if let Some(origin) = self.inlinee(symbol.inlinee) {
+                    self.set(entry, DW_AT_abstract_origin, Value::UnitRef(origin));
+                }
+                let address = procedure.map(|(_, address)| address);
+                self.inline_ranges(entry, address, &symbol.annotations);
+                scopes.push(entry);
+            }
+            Symbol::End(_) | Symbol::InlineSiteEnd(_) if scopes.len() > 1 => {
+                let scope = scopes.pop();
+                if matches!(procedure, Some((entry, _)) if Some(*entry) == scope) {
+                    *procedure = None;
+                }
+            }
+            Symbol::Label32(symbol) => {
+                let entry = self.add(parent, DW_TAG_label, &symbol.name);
+                let address = (self.address)(&symbol.offset.0);
+                self.set(entry, DW_AT_low_pc, Value::Address(address));
+            }
+            Symbol::Register(symbol) => {
+                let entry = self.add(parent, DW_TAG_variable, &symbol.name);
+                self.set_type(entry, symbol.variable_type);
+                if let Some(expression) = self.expression(Place::Register(symbol.register)) {
+                    self.set(entry, DW_AT_location, Value::Exprloc(expression));
+                }
+            }
+            Symbol::RegRel32(symbol) => {
+                let entry = self.add(parent, DW_TAG_variable, &symbol.name);
+                self.set_type(entry, symbol.variable_type);
+                let place = Place::RegisterRelative(symbol.register, symbol.offset);
+                if let Some(expression) = self.expression(place) {
+                    self.set(entry, DW_AT_location, Value::Exprloc(expression));
+                }
+            }
+            Symbol::Local(symbol) => {
+                let tag = if symbol.flags & IS_PARAMETER != 0 {
+                    DW_TAG_formal_parameter
+                } else {
+                    DW_TAG_variable
+                };
+                let entry = self.add(parent, tag, &symbol.name);
+                self.set_type(entry, symbol.variable_type);
+                if symbol.flags & IS_OPTIMIZED_OUT == 0 {
+                    *local = Some((entry, vec![]));
+                }
+            }
+            Symbol::Constant(symbol) => {
+                let entry = self.add(parent, DW_TAG_variable, &symbol.name);
+                self.set_type(entry, symbol.constant_type);
+                let value = match symbol.value {
+                    Numeric::Signed(value) => Value::Sdata(value),
+                    Numeric::Unsigned(value) => Value::Udata(value),
+                };
+                self.set(entry, DW_AT_const_value, value);
+            }
+            Symbol::Compile3(symbol) => {
+                let root = self.dwarf.unit.root();
+                let producer = Value::String(symbol.version.as_bytes().to_vec());
+                self.set(root, DW_AT_producer, producer);
+                match language(symbol.language) {
+                    Some(language) => self.set(root, DW_AT_language, Value::Language(language)),
+                    None => self.report(format!("Source language {:?}", symbol.language)),
+                }
+            }
+            symbol => self.report(format!("Symbol::{}", symbol::kind_name(symbol))),
+        }
+    }
+
+    fn procedure(
+        &mut self,
+        entry: UnitEntryId,
+        address: Address,
+        length: u32,
+        function_type: LeafId,
+    ) {
+        self.set(entry, DW_AT_low_pc, Value::Address(address));
+        self.set(entry, DW_AT_high_pc, Value::Udata(length.into()));
+        self.set(entry, DW_AT_prototyped, Value::Flag(true));
+        if let Some(return_type) = self.return_type(function_type) {
+            self.set(entry, DW_AT_type, Value::UnitRef(return_type));
+        }
+        self.procedures.push((address, length.into()));
+    }
+
+    /// Set the location list of a `Symbol::Local` from its `DefRange*` symbols.
+    fn finish_local(&mut self, local: Option<(UnitEntryId, Vec<Location>)>) {
+        if let Some((entry, locations)) = local {
+            if !locations.is_empty() {
+                let list = self.dwarf.unit.locations.add(LocationList(locations));
+                self.set(entry, DW_AT_location, Value::LocationListRef(list));
+            }
+        }
+    }
+
+    /// Start and length of the parts of an address range outside its gaps.
+    fn address_ranges(
+        &mut self,
+        range: &AddressRange<Reloc>,
+        gaps: &[AddressGap],
+    ) -> Vec<(Address, u64)> {
+        let begin = (self.address)(&range.offset.0);
+        let end = u64::from(range.length);
+        let mut gaps = gaps.to_vec();
+        gaps.sort_by_key(|gap| gap.offset);
+        let mut ranges = vec![];
+        let mut start = 0;
+        for gap in gaps {
+            let gap_start = u64::from(gap.offset).min(end);
+            if gap_start > start {
+                ranges.push((advance(begin, start), gap_start - start));
+            }
+            start = start.max(u64::from(gap.offset) + u64::from(gap.length));
+        }
+        if end > start {
+            ranges.push((advance(begin, start), end - start));
+        }
+        ranges
+    }
+
+    /// Entry of the abstract subprogram for the inlinee of inline sites.
+    fn inlinee(&mut self, id: LeafId) -> Option<UnitEntryId> {
+        if let Some(entry) = self.inlinees.get(&id) {
+            return *entry;
+        }
+        let codeview = self.codeview;
+        let entry = match codeview.leaf(id) {
+            Some(Leaf::FuncId(function)) => {
+                let name = match codeview.leaf(function.scope) {
+                    Some(Leaf::StringId(scope)) => {
+                        format!(
+                            "{}::{}",
+                            String::from_utf8_lossy(&scope.content.0),
+                            function.name
+                        )
+                    }
+                    _ => function.name.clone(),
+                };
+                let root = self.dwarf.unit.root();
+                let entry = self.add(root, DW_TAG_subprogram, &name);
+                self.set(entry, DW_AT_inline, Value::Inline(DW_INL_inlined));
+                self.set(entry, DW_AT_prototyped, Value::Flag(true));
+                if let Some(return_type) = self.return_type(function.function_type) {
+                    self.set(entry, DW_AT_type, Value::UnitRef(return_type));
+                }
+                Some(entry)
+            }
+            _ => {
+                self.report(format!("Inlinee {} that isn't a Leaf::FuncId", id));
+                None
+            }
+        };
+        self.inlinees.insert(id, entry);
+        entry
+    }
+
+    /// Set the code ranges of an inlined subroutine from the annotations of its inline site.
+    fn inline_ranges(
+        &mut self,
+        entry: UnitEntryId,
+        procedure: Option<Address>,
+        annotations: &[BinaryAnnotation],
+    ) {
+        let procedure = match procedure {
+            Some(procedure) => procedure,
+            None => return self.report("Symbol::InlineSite outside a procedure"),
+        };
+        let mut offset = 0;
+        let mut ranges = vec![];
+        let mut has_lines = false;
+        for annotation in annotations {
+            match *annotation {
+                BinaryAnnotation::CodeOffset(value) => offset = u64::from(value),
+                BinaryAnnotation::ChangeCodeOffset(delta) => offset += u64::from(delta),
+                BinaryAnnotation::ChangeCodeLength(length) => {
+                    ranges.push((offset, u64::from(length)));
+                    offset += u64::from(length);
+                }
+                BinaryAnnotation::ChangeCodeLengthAndCodeOffset {
+                    length,
+                    code_offset,
+                } => {
+                    ranges.push((offset, u64::from(length)));
+                    offset += u64::from(length) + u64::from(code_offset);
+                }
+                BinaryAnnotation::ChangeCodeOffsetBase(_) => {
+                    self.report("BinaryAnnotation::ChangeCodeOffsetBase")
+                }
+                _ => has_lines = true,
+            }
+        }
+        if has_lines {
+            self.report("Source lines of Symbol::InlineSite");
+        }
+        match ranges[..] {
+            [] => (),
+            [(offset, length)] => {
+                let begin = advance(procedure, offset);
+                self.set(entry, DW_AT_low_pc, Value::Address(begin));
+                self.set(entry, DW_AT_high_pc, Value::Udata(length));
+            }
+            _ => {
+                let ranges = ranges
+                    .into_iter()
+                    .map(|(offset, length)| Range::StartLength {
+                        begin: advance(procedure, offset),
+                        length,
+                    })
+                    .collect();
+                let ranges = self.dwarf.unit.ranges.add(RangeList(ranges));
+                self.set(entry, DW_AT_ranges, Value::RangeListRef(ranges));
+            }
+        }
+    }
+
+    /// Line program file of a `FileId`.
+    fn file(&mut self, file: crate::file::FileId) -> Option<gimli::write::FileId> {
+        if let Some(id) = self.file_ids.get(&file.index()) {
+            return Some(*id);
+        }
+        let index = match self.checksums {
+            Some(checksums) => checksums.position(file)?,
+            None => file.index() as usize / 8,
+        };
+        let path = self.files.get(index)?;
+        let program = &mut self.dwarf.unit.line_program;
+        let (directory, name) = split_path(path);
+        let directory = match directory {
+            Some(directory) => {
+                program.add_directory(LineString::String(directory.as_bytes().to_vec()))
+            }
+            None => program.default_directory(),
+        };
+        let id = program.add_file(
+            LineString::String(name.as_bytes().to_vec()),
+            directory,
+            None,
+        );
+        self.file_ids.insert(file.index(), id);
+        Some(id)
+    }
+
+    fn lines(&mut self, lines: &Lines<Reloc>) {
+        let address = (self.address)(&lines.address);
+        let mut rows = vec![];
+        for block in &lines.blocks {
+            let file = match self.file(block.file) {
+                Some(file) => file,
+                None => {
+                    self.report(format!("File {:#x} without a path", block.file.index()));
+                    continue;
+                }
+            };
+            for line in &block.lines {
+                rows.push((
+                    u64::from(line.offset),
+                    file,
+                    line.line_start,
+                    line.is_statement,
+                ));
+            }
+        }
+        // Rows of a sequence must have increasing addresses.
+        rows.sort_by_key(|row| row.0);
+        let last = match rows.last() {
+            Some(row) => row.0,
+            None => return,
+        };
+        let length = u64::from(lines.code_length).max(last);
+        let program = &mut self.dwarf.unit.line_program;
+        program.begin_sequence(Some(address));
+        for (offset, file, line, is_statement) in rows {
+            let row = program.row();
+            row.address_offset = offset;
+            row.file = file;
+            row.line = line.into();
+            row.is_statement = is_statement;
+            program.generate_row();
+        }
+        program.end_sequence(length);
+    }
+}
+
+/// Key and properties of a structure, union or enumeration.
+fn type_name(leaf: &Leaf) -> Option<(TypeName, u16)> {
+    let name = |name: &String, unique_name: &Option<String>| {
+        unique_name.clone().unwrap_or_else(|| name.clone())
+    };
+    match leaf {
+        Leaf::Structure(s) => Some((
+            TypeName::Structure(name(&s.name, &s.unique_name)),
+            s.properties,
+        )),
+        Leaf::Union(u) => Some((TypeName::Union(name(&u.name, &u.unique_name)), u.properties)),
+        Leaf::Enum(e) => Some((TypeName::Enum(name(&e.name, &e.unique_name)), e.properties)),
+        _ => None,
+    }
+}
+
+/// Name and encoding of a primitive type. `void` has no encoding and no base type entry.
+fn base_type(primitive: Primitive) -> (&'static str, DwAte) {
+    match primitive {
+        Primitive::Void => ("void", DwAte(0)),
+        Primitive::Bool => ("bool", DW_ATE_boolean),
+        Primitive::Char => ("char", DW_ATE_signed_char),
+        Primitive::WideChar => ("wchar_t", DW_ATE_UTF),
+        Primitive::Char16 => ("char16_t", DW_ATE_UTF),
+        Primitive::Char32 => ("char32_t", DW_ATE_UTF),
+        Primitive::I8 => ("int8_t", DW_ATE_signed),
+        Primitive::U8 => ("uint8_t", DW_ATE_unsigned),
+        Primitive::I16 => ("int16_t", DW_ATE_signed),
+        Primitive::U16 => ("uint16_t", DW_ATE_unsigned),
+        Primitive::I32 => ("int32_t", DW_ATE_signed),
+        Primitive::U32 => ("uint32_t", DW_ATE_unsigned),
+        Primitive::I64 => ("int64_t", DW_ATE_signed),
+        Primitive::U64 => ("uint64_t", DW_ATE_unsigned),
+        Primitive::I128 => ("int128_t", DW_ATE_signed),
+        Primitive::U128 => ("uint128_t", DW_ATE_unsigned),
+        Primitive::F32 => ("float", DW_ATE_float),
+        Primitive::F64 => ("double", DW_ATE_float),
+    }
+}
+
+fn language(language: SourceLanguage) -> Option<DwLang> {
+    match language {
+        SourceLanguage::C => Some(DW_LANG_C),
+        SourceLanguage::Cpp => Some(DW_LANG_C_plus_plus),
+        SourceLanguage::Fortran => Some(DW_LANG_Fortran95),
+        SourceLanguage::Pascal => Some(DW_LANG_Pascal83),
+        SourceLanguage::Cobol => Some(DW_LANG_Cobol85),
+        SourceLanguage::Java => Some(DW_LANG_Java),
+        SourceLanguage::ObjC => Some(DW_LANG_ObjC),
+        SourceLanguage::ObjCpp => Some(DW_LANG_ObjC_plus_plus),
+        SourceLanguage::Swift => Some(DW_LANG_Swift),
+        SourceLanguage::Rust => Some(DW_LANG_Rust),
+        SourceLanguage::Go => Some(DW_LANG_Go),
+        _ => None,
+    }
+}
+
+/// Value of a size or offset, where negative values wrap around.
+fn numeric(value: &Numeric) -> u64 {
+    match *value {
+        Numeric::Signed(value) => value as u64,
+        Numeric::Unsigned(value) => value,
+    }
+}
+
+fn advance(address: Address, offset: u64) -> Address {
+    match address {
+        Address::Constant(address) => Address::Constant(address.wrapping_add(offset)),
+        Address::Symbol { symbol, addend } => Address::Symbol {
+            symbol,
+            addend: addend.wrapping_add(offset as i64),
+        },
+    }
+}
+
+/// Directory and file name of a path with either kind of separator.
+fn split_path(path: &str) -> (Option<&str>, &str) {
+    match path.rfind(['/', '\\']) {
+        Some(0) => (Some(&path[..1]), &path[1..]),
+        Some(index) => (Some(&path[..index]), &path[index + 1..]),
+        None => (None, path),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dwarf::convert_dwarf;
+    use crate::file::FileId;
+    use crate::leaf::{
+        ArgList, Array, Enum, Enumerate, FieldList, FuncId, Member, Modifier, Pointer, Procedure,
+        StaticMember, StringId, Structure, VTShape,
+    };
+    use crate::line::{Block, Line};
+    use crate::reloc::{SecRel, SectionIndex};
+    use crate::section_write::MACHINE_X64;
+    use crate::symbol::{
+        Block32, Compile3, Constant, DefRangeRegister, DefRangeRegisterRel, End, GlobalProc32,
+        InlineSite, InlineSiteEnd, Label32, Local, RegRel32,
+    };
+    use gimli::write::{EndianVec, Sections};
+    use gimli::{AttributeValue, EndianSlice, LittleEndian};
+    use std::convert::*;
+
+    type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+    fn build_codeview() -> Codeview<u64> {
+        let mut codeview = Codeview::new();
+        let mut add = |leaf| codeview.add_leaf(leaf).unwrap();
+        let int = LeafId::primitive(Primitive::I32);
+        let structure = |properties, field_list, size: u64| {
+            Leaf::Structure(Structure {
+                member_count: 3,
+                properties,
+                field_list,
+                derived_from: LeafId::null(),
+                vtable_shape: LeafId::null(),
+                size: size.into(),
+                name: "node".to_string(),
+                unique_name: None,
+            })
+        };
+        let arguments = add(Leaf::ArgList(ArgList {
+            arguments: vec![int, LeafId::null()],
+        }));
+        let procedure = add(Leaf::Procedure(Procedure {
+            return_type: int,
+            calling_convention: 0,
+            function_attributes: 0,
+            parameter_count: 2,
+            argument_list: arguments,
+        }));
+        let forward = add(structure(FORWARD_REF, LeafId::null(), 0));
+        let pointer = add(Leaf::Pointer(Pointer::new(forward, 8)));
+        let member = |attributes, member_type, offset: u64, name: &str| {
+            Field::Member(Member {
+                attributes,
+                member_type,
+                offset: offset.into(),
+                name: name.to_string(),
+            })
+        };
+        let fields = add(Leaf::FieldList(FieldList {
+            fields: vec![
+                member(PUBLIC, int, 0, "value"),
+                member(PUBLIC, pointer, 8, "next"),
+                Field::StaticMember(StaticMember {
+                    attributes: PRIVATE,
+                    member_type: LeafId::primitive(Primitive::U32),
+                    name: "count".to_string(),
+                }),
+            ],
+        }));
+        let node = add(structure(0, fields, 16));
+        let enumerate = |value: i64, name: &str| {
+            Field::Enumerate(Enumerate {
+                attributes: PUBLIC,
+                value: value.into(),
+                name: name.to_string(),
+            })
+        };
+        let enumerators = add(Leaf::FieldList(FieldList {
+            fields: vec![enumerate(-1, "red"), enumerate(1, "green")],
+        }));
+        let color = add(Leaf::Enum(Enum {
+            member_count: 2,
+            properties: 0,
+            underlying_type: int,
+            field_list: enumerators,
+            name: "ns::color".to_string(),
+            unique_name: None,
+        }));
+        let array = add(Leaf::Array(Array {
+            element_type: int,
+            index_type: LeafId::primitive(Primitive::U64),
+            size: 12u64.into(),
+            name: String::new(),
+        }));
+        let modified = add(Leaf::Modifier(Modifier {
+            modified_type: node,
+            modifiers: 3,
+        }));
+        let scope = add(Leaf::StringId(StringId {
+            substr: LeafId::null(),
+            content: "ns".into(),
+        }));
+        let square = add(Leaf::FuncId(FuncId {
+            scope,
+            function_type: procedure,
+            name: "square".to_string(),
+        }));
+        let shape = add(Leaf::VTShape(VTShape { slots: vec![] }));
+        let callback = add(Leaf::Pointer(Pointer::new(procedure, 8)));
+
+        let range = |offset, length| AddressRange {
+            offset: SecRel(offset),
+            section: SectionIndex(offset),
+            length,
+        };
+        let register = |variable_type, register, name: &str| {
+            Symbol::Register(symbol::Register {
+                variable_type,
+                register,
+                name: name.to_string(),
+            })
+        };
+        let rbp = |offset, variable_type, name: &str| {
+            Symbol::RegRel32(RegRel32 {
+                offset,
+                variable_type,
+                register: 334,
+                name: name.to_string(),
+            })
+        };
+        let local = |variable_type, flags, name: &str| {
+            Symbol::Local(Local {
+                variable_type,
+                flags,
+                name: name.to_string(),
+            })
+        };
+        codeview.add_subsection(Subsection::Symbols(vec![
+            Symbol::Compile3(Compile3::rust(MACHINE_X64, [1, 80, 0], "rustc".to_string())),
+            Symbol::GlobalProc32(GlobalProc32 {
+                length: 0x40,
+                debug_start: 0,
+                debug_end: 0,
+                function_type: procedure,
+                offset: SecRel(0x1000),
+                section: SectionIndex(0x1000),
+                flags: 0,
+                name: "main".to_string(),
+            }),
+            rbp(-20, int, "argc"),
+            local(pointer, 0, "list"),
+            Symbol::DefRangeRegisterRel(DefRangeRegisterRel {
+                base_register: 335,
+                flags: 0,
+                base_offset: 8,
+                range: range(0x1000, 0x10),
+                gaps: vec![],
+            }),
+            Symbol::DefRangeRegister(DefRangeRegister {
+                register: 329,
+                may_have_no_name: 0,
+                range: range(0x1010, 0x20),
+                gaps: vec![AddressGap {
+                    offset: 8,
+                    length: 4,
+                }],
+            }),
+            rbp(-48, array, "grid"),
+            rbp(-56, callback, "callback"),
+            register(modified, 332, "q"),
+            Symbol::Block32(Block32 {
+                length: 0x10,
+                offset: SecRel(0x1010),
+                section: SectionIndex(0x1010),
+                name: String::new(),
+            }),
+            register(int, 328, "i"),
+            Symbol::End(End {}),
+            Symbol::InlineSite(InlineSite {
+                inlinee: square,
+                annotations: vec![
+                    BinaryAnnotation::ChangeCodeOffset(0x20),
+                    BinaryAnnotation::ChangeCodeLength(8),
+                    BinaryAnnotation::ChangeLineOffset(1),
+                ],
+            }),
+            register(int, 331, "x"),
+            Symbol::InlineSiteEnd(InlineSiteEnd {}),
+            local(color, IS_OPTIMIZED_OUT, "c"),
+            Symbol::Constant(Constant {
+                constant_type: int,
+                value: Numeric::Signed(-5),
+                name: "limit".to_string(),
+            }),
+            Symbol::Label32(Label32 {
+                offset: SecRel(0x1030),
+                section: SectionIndex(0x1030),
+                flags: 0,
+                name: "retry".to_string(),
+            }),
+            register(shape, 9999, "bogus"),
+            Symbol::BuildInfo(symbol::BuildInfo {
+                leaf: LeafId::null(),
+            }),
+            Symbol::End(End {}),
+        ]));
+
+        let line = |offset, line_start| Line {
+            offset,
+            line_start,
+            line_delta: None,
+            is_statement: true,
+        };
+        let block = |file, lines| Block {
+            file: FileId::try_from(file).unwrap(),
+            lines,
+        };
+        codeview.add_subsection(Subsection::Lines(Lines {
+            address: 0x1000,
+            code_length: 0x40,
+            blocks: vec![
+                block(0, vec![line(0, 1), line(8, 2)]),
+                block(8, vec![line(0x20, 10)]),
+                block(0, vec![line(0x28, 3)]),
+            ],
+        }));
+        codeview.add_subsection(Subsection::Lines(Lines {
+            address: 0x2000,
+            code_length: 4,
+            blocks: vec![block(16, vec![line(0, 1)])],
+        }));
+        codeview
+    }
+
+    /// One line per entry, indented by depth, with the attributes that the translation sets.
+    fn dump(dwarf: &gimli::Dwarf<Reader>, unit: &gimli::Unit<Reader>) -> Vec<String> {
+        let label = |offset| {
+            let entry = unit.entry(offset).unwrap();
+            match entry.attr_value(DW_AT_name).unwrap() {
+                Some(name) => dwarf
+                    .attr_string(unit, name)
+                    .unwrap()
+                    .to_string_lossy()
+                    .into(),
+                None => entry.tag().static_string().unwrap().to_string(),
+            }
+        };
+        let mut lines = vec![];
+        let mut depth = 0;
+        let mut entries = unit.entries();
+        while let Some((delta, entry)) = entries.next_dfs().unwrap() {
+            depth += delta;
+            let mut line = format!("{}{}", "  ".repeat(depth as usize), entry.tag());
+            let mut attrs = entry.attrs();
+            while let Some(attr) = attrs.next().unwrap() {
+                let value = match attr.value() {
+                    AttributeValue::UnitRef(offset) => label(offset),
+                    AttributeValue::Exprloc(expression) => format!("{:?}", expression.0.slice()),
+                    AttributeValue::Addr(address) => format!("{:#x}", address),
+                    AttributeValue::Udata(value) => format!("{:#x}", value),
+                    AttributeValue::Data1(value) => format!("{:#x}", value),
+                    AttributeValue::Flag(value) => value.to_string(),
+                    AttributeValue::Sdata(value) => value.to_string(),
+                    AttributeValue::Encoding(value) => value.to_string(),
+                    AttributeValue::Accessibility(value) => value.to_string(),
+                    AttributeValue::Inline(value) => value.to_string(),
+                    AttributeValue::Language(value) => value.to_string(),
+                    AttributeValue::LocationListsRef(_) => "list".to_string(),
+                    AttributeValue::RangeListsRef(_) => "ranges".to_string(),
+                    AttributeValue::DebugLineRef(_) => continue,
+                    value => dwarf
+                        .attr_string(unit, value)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into(),
+                };
+                line += &format!(" {}={}", attr.name().static_string().unwrap(), value);
+            }
+            lines.push(line.replace("DW_AT_", "").replace("DW_TAG_", ""));
+        }
+        lines
+    }
+
+    #[test]
+    fn conversion() {
+        let codeview = build_codeview();
+        let files = ["/src/main.c".to_string(), "/src/include/util.h".to_string()];
+        let mut conversion = convert_codeview(&codeview, MACHINE_X64, &files, |&address| {
+            Address::Constant(address)
+        });
+        let mut sections = Sections::new(EndianVec::new(LittleEndian));
+        conversion.dwarf.write(&mut sections).unwrap();
+        let dwarf = gimli::Dwarf::load(|id| -> gimli::Result<_> {
+            let data = sections.get(id).map_or(&[][..], |section| section.slice());
+            Ok(EndianSlice::new(data, LittleEndian))
+        })
+        .unwrap();
+        let header = dwarf.units().next().unwrap().unwrap();
+        let unit = dwarf.unit(header).unwrap();
+        assert_eq!(
+            dump(&dwarf, &unit),
+            [
+                "compile_unit name=/src/main.c comp_dir=/src producer=rustc language=DW_LANG_Rust \
+                 low_pc=0x0 ranges=ranges",
+                "  base_type name=int32_t encoding=DW_ATE_signed byte_size=0x4",
+                "  base_type name=uint32_t encoding=DW_ATE_unsigned byte_size=0x4",
+                "  structure_type name=node byte_size=0x10",
+                "    member name=value type=int32_t data_member_location=0x0 \
+                 accessibility=DW_ACCESS_public",
+                "    member name=next type=pointer_type data_member_location=0x8 \
+                 accessibility=DW_ACCESS_public",
+                "    member name=count type=uint32_t external=true declaration=true \
+                 accessibility=DW_ACCESS_private",
+                "  pointer_type byte_size=0x8 type=node",
+                "  enumeration_type name=ns::color type=int32_t byte_size=0x4",
+                "    enumerator name=red const_value=-1",
+                "    enumerator name=green const_value=1",
+                "  subprogram name=main external=true low_pc=0x1000 high_pc=0x40 prototyped=true \
+                 type=int32_t",
+                "    variable name=argc type=int32_t location=[118, 108]",
+                "    variable name=list type=pointer_type location=list",
+                "    variable name=grid type=array_type location=[118, 80]",
+                "    variable name=callback type=pointer_type location=[118, 72]",
+                "    variable name=q type=const_type location=[84]",
+                "    lexical_block low_pc=0x1010 high_pc=0x10",
+                "      variable name=i type=int32_t location=[80]",
+                "    inlined_subroutine abstract_origin=ns::square low_pc=0x1020 high_pc=0x8",
+                "      variable name=x type=int32_t location=[81]",
+                "    variable name=c type=ns::color",
+                "    variable name=limit type=int32_t const_value=-5",
+                "    label name=retry low_pc=0x1030",
+                "    variable name=bogus",
+                "  array_type byte_size=0xc type=int32_t",
+                "    subrange_type count=0x3",
+                "  pointer_type byte_size=0x8 type=subroutine_type",
+                "  subroutine_type prototyped=true type=int32_t",
+                "    formal_parameter type=int32_t",
+                "    unspecified_parameters",
+                "  volatile_type type=node",
+                "  const_type type=volatile_type",
+                "  subprogram name=ns::square inline=DW_INL_inlined prototyped=true type=int32_t",
+            ]
+        );
+
+        let mut rows = vec![];
+        let program = unit.line_program.clone().unwrap();
+        let (program, sequences) = program.sequences().unwrap();
+        for sequence in sequences {
+            let mut sequence_rows = program.resume_from(&sequence);
+            while let Some((header, row)) = sequence_rows.next_row().unwrap() {
+                let file = row.file(header).unwrap();
+                let directory = file.directory(header).unwrap();
+                let directory = dwarf.attr_string(&unit, directory).unwrap();
+                let name = dwarf.attr_string(&unit, file.path_name()).unwrap();
+                let path = format!("{}/{}", directory.to_string_lossy(), name.to_string_lossy());
+                let line = row.line().map_or(0, |line| line.get());
+                rows.push((row.address(), path, line));
+            }
+        }
+        let row = |address, path: &str, line| (address, path.to_string(), line);
+        assert_eq!(
+            rows,
+            [
+                row(0x1000, "/src/main.c", 1),
+                row(0x1008, "/src/main.c", 2),
+                row(0x1020, "/src/include/util.h", 10),
+                row(0x1028, "/src/main.c", 3),
+                row(0x1040, "/src/main.c", 3),
+            ]
+        );
+
+        let unsupported = |record, construct: &str| UnsupportedCodeview {
+            record,
+            construct: construct.to_string(),
+        };
+        let symbol = |index| CodeviewRecord::Symbol {
+            subsection: 0,
+            index,
+        };
+        assert_eq!(
+            conversion.unsupported,
+            [
+                unsupported(symbol(12), "Source lines of Symbol::InlineSite"),
+                unsupported(
+                    CodeviewRecord::Leaf(LeafId(0x100C)),
+                    "Leaf::VTShape as a type"
+                ),
+                unsupported(symbol(18), "Register 9999"),
+                unsupported(symbol(19), "Symbol::BuildInfo"),
+                unsupported(CodeviewRecord::Subsection(2), "File 0x10 without a path"),
+            ]
+        );
+
+        // Translating back gives the same symbols, except for the gap in the range of `list`.
+        let mut roundtrip = Codeview::<u64>::new();
+        let conversion = convert_dwarf(&dwarf, MACHINE_X64, &mut roundtrip, Some).unwrap();
+        assert!(conversion.unsupported.is_empty());
+        let symbols: Vec<_> = conversion
+            .symbols
+            .iter()
+            .map(|symbol| match symbol {
+                Symbol::RegRel32(symbol) => {
+                    format!("{} {}{:+}", symbol.name, symbol.register, symbol.offset)
+                }
+                Symbol::Register(symbol) => format!("{} {}", symbol.name, symbol.register),
+                Symbol::DefRangeRegister(symbol) => format!(
+                    "{:#x}+{:#x} {}",
+                    symbol.range.offset.0, symbol.range.length, symbol.register
+                ),
+                Symbol::DefRangeRegisterRel(symbol) => format!(
+                    "{:#x}+{:#x} {}{:+}",
+                    symbol.range.offset.0,
+                    symbol.range.length,
+                    symbol.base_register,
+                    symbol.base_offset
+                ),
+                symbol => symbol::kind_name(symbol).to_string(),
+            })
+            .collect();
+        assert_eq!(
+            symbols,
+            [
+                "GlobalProc32",
+                "argc 334-20",
+                "Local",
+                "0x1000+0x10 335+8",
+                "0x1010+0x8 329",
+                "0x101c+0x14 329",
+                "grid 334-48",
+                "callback 334-56",
+                "q 332",
+                "Block32",
+                "i 328",
+                "End",
+                "InlineSite",
+                "x 331",
+                "InlineSiteEnd",
+                "Local",
+                "Constant",
+                "Label32",
+                "Local",
+                "End",
+            ]
+        );
+    }
+    #[test]
+    fn outside_procedures() {
+        let codeview = Codeview::<u64>::with_type_server(leaf::TypeServer2 {
+            guid: [0; 16],
+            age: 1,
+            name: "a.pdb".into(),
+        });
+        let conversion = convert_codeview(&codeview, MACHINE_X64, &[], |&address| {
+            Address::Constant(address)
+        });
+        assert_eq!(
+            conversion.unsupported,
+            [UnsupportedCodeview {
+                record: CodeviewRecord::Types,
+                construct: "Types in a type server or precompiled types".to_string(),
+            }]
+        );
+
+        let mut codeview = Codeview::<u64>::new();
+        codeview.add_subsection(Subsection::Symbols(vec![
+            Symbol::GlobalProc32(GlobalProc32 {
+                length: 0x10,
+                debug_start: 0,
+                debug_end: 0,
+                function_type: LeafId::null(),
+                offset: SecRel(0x1000),
+                section: SectionIndex(0x1000),
+                flags: 0,
+                name: "f".to_string(),
+            }),
+            Symbol::End(End {}),
+            Symbol::InlineSite(InlineSite {
+                inlinee: LeafId::null(),
+                annotations: vec![BinaryAnnotation::ChangeCodeLength(4)],
+            }),
+            Symbol::InlineSiteEnd(InlineSiteEnd {}),
+        ]));
+        let conversion = convert_codeview(&codeview, MACHINE_X64, &[], |&address| {
+            Address::Constant(address)
+        });
+        assert!(conversion.unsupported.contains(&UnsupportedCodeview {
+            record: CodeviewRecord::Symbol {
+                subsection: 0,
+                index: 2,
+            },
+            construct: "Symbol::InlineSite outside a procedure".to_string(),
+        }));
+    }
+
+    #[test]
+    fn field_list_cycle() {
+        let mut codeview = Codeview::<u64>::new();
+        let fields = codeview
+            .add_leaf(Leaf::FieldList(FieldList {
+                fields: vec![
+                    Field::Member(Member {
+                        attributes: PUBLIC,
+                        member_type: LeafId::primitive(Primitive::I32),
+                        offset: 0u64.into(),
+                        name: "value".to_string(),
+                    }),
+                    Field::Index(leaf::Index {
+                        continuation: LeafId(0x1000),
+                    }),
+                ],
+            }))
+            .unwrap();
+        assert_eq!(fields, LeafId(0x1000));
+        codeview
+            .add_leaf(Leaf::Structure(Structure {
+                member_count: 1,
+                properties: 0,
+                field_list: fields,
+                derived_from: LeafId::null(),
+                vtable_shape: LeafId::null(),
+                size: 4u64.into(),
+                name: "node".to_string(),
+                unique_name: None,
+            }))
+            .unwrap();
+        let conversion = convert_codeview(&codeview, MACHINE_X64, &[], |&address| {
+            Address::Constant(address)
+        });
+        assert_eq!(
+            conversion.unsupported,
+            [UnsupportedCodeview {
+                record: CodeviewRecord::Leaf(LeafId(0x1001)),
+                construct: "Field list 0x1000 continued by 0x1000".to_string(),
+            }]
+        );
+    }
+}
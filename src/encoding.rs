@@ -0,0 +1,161 @@
+use crate::error::{wu, Error};
+use crate::struct_macro::*;
+use std::convert::*;
+use std::ffi::OsStr;
+use std::path::Path;
+
+/// How strings in records are encoded.
+///
+/// `String` fields are always written as UTF-8. There is no code page policy: strings for tools
+/// that expect a code page must be encoded by the caller and given as `ByteString` fields, whose
+/// bytes are written unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StringPolicy {
+    /// Strings are NUL-terminated, and strings containing NUL are rejected.
+    #[default]
+    NulTerminated,
+
+    /// Strings are NUL-terminated, and NUL characters in strings are replaced by `?`.
+    NulTerminatedLossy,
+
+    /// Strings are prefixed by a one-byte length, as in CodeView 4 and 5.
+    ///
+    /// Records that have a legacy form are written with the legacy (`_ST`) record kind. Records
+    /// that don't have one still use NUL-terminated strings. Strings longer than 255 bytes are
+    /// rejected.
+    LengthPrefixed,
+}
+
+/// A string of bytes with no particular encoding, such as a path that isn't valid UTF-8 or a
+/// string already encoded in a code page.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct ByteString(pub Vec<u8>);
+
+impl From<Vec<u8>> for ByteString {
+    fn from(bytes: Vec<u8>) -> ByteString {
+        ByteString(bytes)
+    }
+}
+
+impl From<&[u8]> for ByteString {
+    fn from(bytes: &[u8]) -> ByteString {
+        ByteString(bytes.to_vec())
+    }
+}
+
+impl From<String> for ByteString {
+    fn from(string: String) -> ByteString {
+        ByteString(string.into_bytes())
+    }
+}
+
+impl From<&str> for ByteString {
+    fn from(string: &str) -> ByteString {
+        ByteString(string.as_bytes().to_vec())
+    }
+}
+
+/// Uses the platform encoding of `OsStr`, which is the raw bytes on Unix and WTF-8 on Windows.
+impl From<&OsStr> for ByteString {
+    fn from(string: &OsStr) -> ByteString {
+        ByteString(string.as_encoded_bytes().to_vec())
+    }
+}
+
+impl From<&Path> for ByteString {
+    fn from(path: &Path) -> ByteString {
+        ByteString::from(path.as_os_str())
+    }
+}
+
+fn write_string<Reloc, W: RecordWrite<Reloc>>(
+    bytes: &[u8],
+    writer: &mut W,
+) -> Result<(), Error<W::Error>> {
+    let error = || Error::StringError(String::from_utf8_lossy(bytes).into_owned());
+    match writer.string_policy() {
+        StringPolicy::NulTerminated => {
+            if bytes.contains(&0) {
+                return Err(error());
+            }
+            wu(writer.write(bytes))?;
+            wu(writer.write(&[0]))
+        }
+        StringPolicy::NulTerminatedLossy => {
+            let bytes: Vec<u8> = bytes
+                .iter()
+                .map(|&byte| if byte == 0 { b'?' } else { byte })
+                .collect();
+            wu(writer.write(&bytes))?;
+            wu(writer.write(&[0]))
+        }
+        StringPolicy::LengthPrefixed => {
+            let len = u8::try_from(bytes.len()).map_err(|_| error())?;
+            wu(writer.write(&[len]))?;
+            wu(writer.write(bytes))
+        }
+    }
+}
+
+impl<Reloc> Writable<Reloc> for String {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        // It is uncertain which encoding CodeView uses.
+        // Maybe it is locale-dependent.
+        // However, on my en-US Windows system, Chinese characters are encoded in UTF-8
+        // in Visual Studio-generated CodeView data, so UTF-8 is a good bet here.
+        write_string(self.as_bytes(), writer)
+    }
+
+    fn size(&self) -> usize {
+        // Both the terminator and the length prefix take one byte.
+        self.len() + 1
+    }
+}
+
+impl<Reloc> Writable<Reloc> for ByteString {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        write_string(&self.0, writer)
+    }
+
+    fn size(&self) -> usize {
+        self.0.len() + 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::section_write::SectionSink;
+
+    fn test_string(string: &str, policy: StringPolicy, data: Option<&[u8]>) {
+        let mut sink = SectionSink::<()>::new();
+        let result = Writable::<()>::write(
+            &string.to_string(),
+            &mut PolicyWriter {
+                inner: &mut sink,
+                policy,
+            },
+        );
+        match data {
+            Some(data) => {
+                result.unwrap();
+                assert_eq!(sink.data, data);
+                assert_eq!(Writable::<()>::size(&string.to_string()), data.len());
+            }
+            None => assert!(matches!(result, Err(Error::StringError(_)))),
+        }
+    }
+
+    #[test]
+    fn strings() {
+        test_string("ab", StringPolicy::NulTerminated, Some(b"ab\0"));
+        test_string("a\0b", StringPolicy::NulTerminated, None);
+        test_string("a\0b", StringPolicy::NulTerminatedLossy, Some(b"a?b\0"));
+        test_string("ab", StringPolicy::LengthPrefixed, Some(b"\x02ab"));
+        test_string(&"a".repeat(256), StringPolicy::LengthPrefixed, None);
+
+        let mut sink = SectionSink::<()>::new();
+        Writable::<()>::write(&ByteString(vec![0xFF, 0xFE]), &mut sink).unwrap();
+        assert_eq!(sink.data, &[0xFF, 0xFE, 0]);
+    }
+}
@@ -1,9 +1,17 @@
+use crate::leaf::LeafId;
+use crate::section_write::RelocKind;
+use crate::validate::ValidationError;
+use std::convert::Infallible;
+use std::fmt;
 use std::num::TryFromIntError;
 use thiserror::Error;
 
 /// An error that occurred when generating CodeView data
+///
+/// `WriteErrorType` is the error type of the `SectionWrite` implementation, and is `Infallible`
+/// for errors that occur before anything is written.
 #[derive(Error, Debug)]
-pub enum Error<WriteErrorType: std::error::Error + 'static> {
+pub enum Error<WriteErrorType: std::error::Error + 'static = Infallible> {
     #[error("SectionWrite reported error")]
     WriteError(WriteErrorType),
 
@@ -12,6 +20,209 @@ pub enum Error<WriteErrorType: std::error::Error + 'static> {
 
     #[error("Failed to encode string")]
     StringError(String),
+
+    #[error("Size of type {0} is unknown")]
+    UnknownSize(String),
+
+    #[error("Size of array with {0} elements is out of range")]
+    ArraySizeOverflow(u64),
+
+    #[error("CodeView information is inconsistent")]
+    ValidationError(#[from] ValidationError),
+
+    #[error("The placeholder variant of {0} can't be written")]
+    PhantomRecord(&'static str),
+
+    #[error("Leaf index {0:#x} is out of range")]
+    LeafIndexOverflow(usize),
+
+    #[error("Line number {0} is out of range")]
+    LineNumberOverflow(u32),
+
+    #[error("Line delta {0} is out of range")]
+    LineDeltaOverflow(u32),
+
+    #[error("Binary annotation operand {0:#x} is out of range")]
+    AnnotationOverflow(u32),
+
+    #[error("Relocation target is unknown")]
+    UnresolvedRelocation,
+
+    #[error("{0:?} relocation can't be resolved")]
+    UnsupportedRelocation(RelocKind),
+
+    #[error("Leaves can't be added when types are in a type server")]
+    TypeServerLeaf,
+
+    #[error("Types in a type server or precompiled types can't be written to a PDB")]
+    ExternalTypes,
+
+    #[error("ID leaves can't be written to a PDB")]
+    UnsupportedIdLeaf,
+
+    #[error("Function {0} is not in a section")]
+    FunctionOutsideSections(String),
+
+    #[error("PDB is too large")]
+    PdbTooLarge,
+
+    #[cfg(feature = "gimli")]
+    #[error("Failed to read DWARF")]
+    DwarfError(#[from] gimli::Error),
+
+    #[error("Address {0:#x} is not in a known section")]
+    UnknownAddress(u64),
+
+    #[error("{} ({context})", .error.to_string())]
+    Context {
+        context: ErrorContext,
+        error: Box<Error<WriteErrorType>>,
+    },
+}
+
+impl<W: std::error::Error + 'static> Error<W> {
+    /// Where the error occurred, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// The error without its context.
+    pub fn root(&self) -> &Error<W> {
+        match self {
+            Error::Context { error, .. } => error.root(),
+            error => error,
+        }
+    }
+
+    fn with_context(self, update: impl FnOnce(&mut ErrorContext)) -> Error<W> {
+        let (mut context, error) = match self {
+            Error::Context { context, error } => (context, error),
+            error => (ErrorContext::default(), Box::new(error)),
+        };
+        update(&mut context);
+        Error::Context { context, error }
+    }
+
+    /// Set the record kind, unless a more specific one is already known.
+    pub(crate) fn in_record(self, kind: &'static str) -> Error<W> {
+        self.with_context(|context| {
+            context.record_kind.get_or_insert(kind);
+        })
+    }
+
+    /// Set the field name, unless a more specific one is already known.
+    pub(crate) fn in_field(self, field: &'static str) -> Error<W> {
+        self.with_context(|context| {
+            context.field.get_or_insert(field);
+        })
+    }
+
+    pub(crate) fn in_leaf(self, leaf: LeafId) -> Error<W> {
+        self.with_context(|context| context.leaf = Some(leaf))
+    }
+
+    pub(crate) fn in_subsection(self, subsection: usize) -> Error<W> {
+        self.with_context(|context| context.subsection = Some(subsection))
+    }
+
+    pub(crate) fn in_symbol(self, subsection: usize, symbol: usize) -> Error<W> {
+        self.with_context(|context| {
+            context.subsection = Some(subsection);
+            context.symbol = Some(symbol);
+        })
+    }
+
+    pub(crate) fn in_relocation(self, index: usize, kind: RelocKind) -> Error<W> {
+        self.with_context(|context| context.relocation = Some((index, kind)))
+    }
+
+    /// Set the position in the section, unless a more specific one is already known.
+    pub(crate) fn at_offset(self, section: &'static str, offset: usize) -> Error<W> {
+        self.with_context(|context| {
+            context.offset.get_or_insert((section, offset));
+        })
+    }
+}
+
+/// Where an error occurred.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    record_kind: Option<&'static str>,
+    leaf: Option<LeafId>,
+    subsection: Option<usize>,
+    symbol: Option<usize>,
+    field: Option<&'static str>,
+    relocation: Option<(usize, RelocKind)>,
+    offset: Option<(&'static str, usize)>,
+}
+
+impl ErrorContext {
+    /// Name of the record being written, such as `"Structure"` or `"Member"`.
+    pub fn record_kind(&self) -> Option<&'static str> {
+        self.record_kind
+    }
+
+    /// Index of the leaf being written.
+    pub fn leaf_index(&self) -> Option<LeafId> {
+        self.leaf
+    }
+
+    /// Index of the subsection being written, in the order added to `Codeview`.
+    pub fn subsection(&self) -> Option<usize> {
+        self.subsection
+    }
+
+    /// Index of the symbol being written within its subsection.
+    pub fn symbol(&self) -> Option<usize> {
+        self.symbol
+    }
+
+    /// Name of the record field being written.
+    pub fn field(&self) -> Option<&'static str> {
+        self.field
+    }
+
+    /// Index and kind of the relocation being resolved by `SectionSink::resolve`.
+    pub fn relocation(&self) -> Option<(usize, RelocKind)> {
+        self.relocation
+    }
+
+    /// Name of the section and offset in it of the record being written.
+    pub fn offset(&self) -> Option<(&'static str, usize)> {
+        self.offset
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(kind) = self.record_kind {
+            parts.push(format!("record {}", kind));
+        }
+        if let Some(leaf) = self.leaf {
+            parts.push(format!("leaf {}", leaf));
+        }
+        match (self.subsection, self.symbol) {
+            (Some(subsection), Some(symbol)) => {
+                parts.push(format!("symbol {} in subsection {}", symbol, subsection))
+            }
+            (Some(subsection), None) => parts.push(format!("subsection {}", subsection)),
+            _ => (),
+        }
+        if let Some(field) = self.field {
+            parts.push(format!("field {}", field));
+        }
+        if let Some((index, kind)) = self.relocation {
+            parts.push(format!("{:?} relocation {}", kind, index));
+        }
+        if let Some((section, offset)) = self.offset {
+            parts.push(format!("offset {:#x} in {}", offset, section));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
 }
 
 pub(crate) fn wu<T, W: std::error::Error + 'static>(result: Result<T, W>) -> Result<T, Error<W>> {
@@ -1,3 +1,118 @@
+use crate::error::{wu, Error};
+use crate::string_table::StringOffset;
+use crate::struct_macro::*;
+use std::convert::*;
+use thiserror::Error;
+
 /// An identifier for a source code file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(pub(crate) u32);
+
+impl FileId {
+    /// Raw offset of the file entry in the file checksum subsection.
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+writable_transparent!(FileId);
+
+/// An error converting a raw offset that is not aligned to a file checksum entry.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Invalid file index {0:#x}")]
+pub struct InvalidFileId(pub u32);
+
+impl TryFrom<u32> for FileId {
+    type Error = InvalidFileId;
+
+    /// File checksum entries are 4-byte aligned.
+    fn try_from(index: u32) -> Result<FileId, InvalidFileId> {
+        if !index.is_multiple_of(4) {
+            return Err(InvalidFileId(index));
+        }
+        Ok(FileId(index))
+    }
+}
+
+/// A subsection that lists the source files referred to by `FileId`.
+#[derive(Debug, Clone, Default)]
+pub struct FileChecksums {
+    /// Source files, in the order they were added.
+    pub files: Vec<FileChecksum>,
+}
+
+impl FileChecksums {
+    /// Add a source file and return the file ID referring to it.
+    pub fn add(&mut self, file: FileChecksum) -> Result<FileId, Error> {
+        let id = FileId(u32::try_from(Writable::<()>::size(&self.files))?);
+        self.files.push(file);
+        Ok(id)
+    }
+
+    /// Index of the entry that a file ID refers to.
+    #[cfg(feature = "gimli")]
+    pub(crate) fn position(&self, file: FileId) -> Option<usize> {
+        let mut offset = 0;
+        for (index, entry) in self.files.iter().enumerate() {
+            if offset == file.0 as usize {
+                return Some(index);
+            }
+            offset += Writable::<()>::size(entry);
+        }
+        None
+    }
+}
+
+/// A source file entry in the file checksum subsection.
 #[derive(Debug, Clone)]
-pub struct FileId(u32);
+pub struct FileChecksum {
+    /// Offset of the file path in the string table subsection.
+    pub name: StringOffset,
+
+    /// Algorithm that computed `checksum`.
+    pub kind: ChecksumKind,
+
+    /// Checksum of the file contents, empty for `ChecksumKind::None`.
+    pub checksum: Vec<u8>,
+}
+
+/// The algorithm of a file checksum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumKind {
+    /// No checksum.
+    None = 0,
+    /// MD5 checksum.
+    Md5 = 1,
+    /// SHA-1 checksum.
+    Sha1 = 2,
+    /// SHA-256 checksum.
+    Sha256 = 3,
+}
+
+writable_enum!(ChecksumKind, u8);
+
+impl<Reloc> Writable<Reloc> for FileChecksum {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        Writable::<Reloc>::write(&self.name, writer)?;
+        Writable::<Reloc>::write(&u8::try_from(self.checksum.len())?, writer)?;
+        Writable::<Reloc>::write(&self.kind, writer)?;
+        wu(writer.write(&self.checksum))?;
+        // Entries are 4-byte aligned.
+        let padding = Writable::<Reloc>::size(self) - 6 - self.checksum.len();
+        wu(writer.write(&[0; 3][0..padding]))
+    }
+
+    fn size(&self) -> usize {
+        (6 + self.checksum.len() + 3) & !3
+    }
+}
+
+impl<Reloc> Writable<Reloc> for FileChecksums {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        self.files.write(writer)
+    }
+
+    fn size(&self) -> usize {
+        Writable::<Reloc>::size(&self.files)
+    }
+}
@@ -0,0 +1,103 @@
+use crate::error::Error;
+use crate::string_table::StringOffset;
+use crate::struct_macro::*;
+
+/// A subsection that records frame data for 32-bit x86 functions.
+#[derive(Debug, Clone)]
+pub struct FrameData<Reloc> {
+    /// Function address.
+    pub address: Reloc,
+
+    /// Frame data entries.
+    pub entries: Vec<FrameDataEntry>,
+}
+
+/// A frame data entry describing the stack frame of a code range.
+#[derive(Debug, Clone)]
+pub struct FrameDataEntry {
+    /// Offset of the code range relative to the address specified in `FrameData::address`.
+    pub offset: u32,
+
+    /// Size of the code range.
+    pub code_size: u32,
+
+    /// Size of local variables.
+    pub locals_size: u32,
+
+    /// Size of parameters.
+    pub params_size: u32,
+
+    /// Maximum size of the stack used by the code range.
+    pub max_stack_size: u32,
+
+    /// Program string that computes the frame, such as `"$T0 $ebp = $eip $T0 4 + ^ ="`.
+    pub program: StringOffset,
+
+    /// Size of the prolog.
+    pub prolog_size: u16,
+
+    /// Size of saved registers.
+    pub saved_registers_size: u16,
+
+    /// Whether the code range has structured exception handling.
+    pub has_seh: bool,
+
+    /// Whether the code range has C++ exception handling.
+    pub has_eh: bool,
+
+    /// Whether the code range starts a function.
+    pub is_function_start: bool,
+}
+
+impl<Reloc> Writable<Reloc> for FrameDataEntry {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        let flags =
+            self.has_seh as u32 | (self.has_eh as u32) << 1 | (self.is_function_start as u32) << 2;
+        Writable::<Reloc>::write(&self.offset, writer)?;
+        Writable::<Reloc>::write(&self.code_size, writer)?;
+        Writable::<Reloc>::write(&self.locals_size, writer)?;
+        Writable::<Reloc>::write(&self.params_size, writer)?;
+        Writable::<Reloc>::write(&self.max_stack_size, writer)?;
+        Writable::<Reloc>::write(&self.program, writer)?;
+        Writable::<Reloc>::write(&self.prolog_size, writer)?;
+        Writable::<Reloc>::write(&self.saved_registers_size, writer)?;
+        Writable::<Reloc>::write(&flags, writer)
+    }
+
+    fn size(&self) -> usize {
+        32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::section_write::*;
+
+    #[test]
+    fn frame_data_entry() {
+        let mut sink = SectionSink::<()>::new();
+        FrameDataEntry {
+            offset: 0x10,
+            code_size: 0x20,
+            locals_size: 4,
+            params_size: 8,
+            max_stack_size: 0,
+            program: StringOffset(0x1234),
+            prolog_size: 3,
+            saved_registers_size: 0,
+            has_seh: false,
+            has_eh: true,
+            is_function_start: true,
+        }
+        .write(&mut sink)
+        .unwrap();
+        assert_eq!(
+            sink.data,
+            &[
+                0x10, 0, 0, 0, 0x20, 0, 0, 0, 4, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0x34, 0x12, 0, 0,
+                3, 0, 0, 0, 6, 0, 0, 0,
+            ]
+        );
+    }
+}
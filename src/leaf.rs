@@ -1,22 +1,232 @@
 #![allow(redundant_semicolons)]
 
-use crate::error::Error;
-use crate::section_write::SectionWrite;
+use crate::encoding::ByteString;
+use crate::error::{wu, Error};
 use crate::struct_macro::*;
 use std::convert::*;
+use std::fmt;
+use thiserror::Error as ThisError;
 
-/// An identifier for a leaf record.
-#[derive(Debug, Clone)]
+/// An identifier for a leaf record, or a primitive type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct LeafId(pub(crate) u32);
 
 impl LeafId {
+    /// The null leaf index, used where no leaf is referenced.
     pub fn null() -> LeafId {
         LeafId(0)
     }
+
+    /// Leaf index of a primitive type.
+    pub fn primitive(primitive: Primitive) -> LeafId {
+        LeafId(primitive.index())
+    }
+
+    /// Leaf index of a pointer to a primitive type, with the given pointer size in bytes.
+    pub fn primitive_pointer(primitive: Primitive, pointer_size: u8) -> LeafId {
+        let mode = if pointer_size == 8 { 0x600 } else { 0x400 };
+        LeafId(primitive.index() | mode)
+    }
+
+    /// Raw leaf index.
+    pub fn index(self) -> u32 {
+        self.0
+    }
+
+    /// Whether the index refers to a primitive type instead of a leaf record.
+    pub fn is_primitive(self) -> bool {
+        self.0 < 0x1000
+    }
+}
+
+impl fmt::Display for LeafId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+impl From<Primitive> for LeafId {
+    fn from(primitive: Primitive) -> LeafId {
+        LeafId::primitive(primitive)
+    }
+}
+
+/// An error converting a raw index that is neither a primitive type nor a leaf index.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Invalid leaf index {0:#x}")]
+pub struct InvalidLeafId(pub u32);
+
+impl TryFrom<u32> for LeafId {
+    type Error = InvalidLeafId;
+
+    /// Values below 0x1000 are primitive types, which have the type in bits 0-7 and the pointer
+    /// mode in bits 8-10. Values from 0x1000 are leaf indices, where the top bit is reserved.
+    fn try_from(index: u32) -> Result<LeafId, InvalidLeafId> {
+        if (0x800..0x1000).contains(&index) || index & 0x8000_0000 != 0 {
+            return Err(InvalidLeafId(index));
+        }
+        Ok(LeafId(index))
+    }
+}
+
+/// A primitive type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Primitive {
+    Void,
+    Bool,
+    Char,
+    WideChar,
+    Char16,
+    Char32,
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    I128,
+    U128,
+    F32,
+    F64,
+}
+
+impl Primitive {
+    /// Primitive type with the given index, without pointer mode bits.
+    #[cfg(feature = "gimli")]
+    pub(crate) fn from_index(index: u32) -> Option<Primitive> {
+        const ALL: [Primitive; 18] = [
+            Primitive::Void,
+            Primitive::Bool,
+            Primitive::Char,
+            Primitive::WideChar,
+            Primitive::Char16,
+            Primitive::Char32,
+            Primitive::I8,
+            Primitive::U8,
+            Primitive::I16,
+            Primitive::U16,
+            Primitive::I32,
+            Primitive::U32,
+            Primitive::I64,
+            Primitive::U64,
+            Primitive::I128,
+            Primitive::U128,
+            Primitive::F32,
+            Primitive::F64,
+        ];
+        ALL.iter()
+            .copied()
+            .find(|primitive| primitive.index() == index)
+    }
+
+    /// Leaf index of the primitive type.
+    fn index(self) -> u32 {
+        match self {
+            Primitive::Void => 0x0003,
+            Primitive::Bool => 0x0030,
+            Primitive::Char => 0x0070,
+            Primitive::WideChar => 0x0071,
+            Primitive::Char16 => 0x007A,
+            Primitive::Char32 => 0x007B,
+            Primitive::I8 => 0x0068,
+            Primitive::U8 => 0x0069,
+            Primitive::I16 => 0x0072,
+            Primitive::U16 => 0x0073,
+            Primitive::I32 => 0x0074,
+            Primitive::U32 => 0x0075,
+            Primitive::I64 => 0x0076,
+            Primitive::U64 => 0x0077,
+            Primitive::I128 => 0x0078,
+            Primitive::U128 => 0x0079,
+            Primitive::F32 => 0x0040,
+            Primitive::F64 => 0x0041,
+        }
+    }
+
+    pub(crate) fn size(self) -> Option<u64> {
+        match self {
+            Primitive::Void => None,
+            Primitive::Bool | Primitive::Char | Primitive::I8 | Primitive::U8 => Some(1),
+            Primitive::WideChar | Primitive::Char16 | Primitive::I16 | Primitive::U16 => Some(2),
+            Primitive::Char32 | Primitive::I32 | Primitive::U32 | Primitive::F32 => Some(4),
+            Primitive::I64 | Primitive::U64 | Primitive::F64 => Some(8),
+            Primitive::I128 | Primitive::U128 => Some(16),
+        }
+    }
 }
 
 writable_transparent!(LeafId);
 
+/// A numeric value, encoded in the smallest form that can hold it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Numeric {
+    /// Signed value.
+    Signed(i64),
+
+    /// Unsigned value.
+    Unsigned(u64),
+}
+
+impl Numeric {
+    /// Leaf type of the encoded value, if any, and the size of the value.
+    fn encoding(&self) -> (Option<u16>, usize) {
+        match *self {
+            Numeric::Unsigned(v) if v < 0x8000 => (None, 2),
+            Numeric::Unsigned(v) if v <= 0xFFFF => (Some(0x8002), 2),
+            Numeric::Unsigned(v) if v <= 0xFFFF_FFFF => (Some(0x8004), 4),
+            Numeric::Unsigned(_) => (Some(0x800A), 8),
+            Numeric::Signed(v) if v >= 0 => Numeric::Unsigned(v as u64).encoding(),
+            Numeric::Signed(v) if v >= i8::MIN as i64 => (Some(0x8000), 1),
+            Numeric::Signed(v) if v >= i16::MIN as i64 => (Some(0x8001), 2),
+            Numeric::Signed(v) if v >= i32::MIN as i64 => (Some(0x8003), 4),
+            Numeric::Signed(_) => (Some(0x8009), 8),
+        }
+    }
+}
+
+impl From<u64> for Numeric {
+    fn from(value: u64) -> Numeric {
+        Numeric::Unsigned(value)
+    }
+}
+
+impl From<u32> for Numeric {
+    fn from(value: u32) -> Numeric {
+        Numeric::Unsigned(value.into())
+    }
+}
+
+impl From<i64> for Numeric {
+    fn from(value: i64) -> Numeric {
+        Numeric::Signed(value)
+    }
+}
+
+impl From<i32> for Numeric {
+    fn from(value: i32) -> Numeric {
+        Numeric::Signed(value.into())
+    }
+}
+
+impl<Reloc> Writable<Reloc> for Numeric {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        let (leaf_type, len) = self.encoding();
+        Writable::<Reloc>::write(&leaf_type, writer)?;
+        let bits = match *self {
+            Numeric::Signed(v) => v as u64,
+            Numeric::Unsigned(v) => v,
+        };
+        wu(writer.write(&bits.to_le_bytes()[0..len]))
+    }
+
+    fn size(&self) -> usize {
+        let (leaf_type, len) = self.encoding();
+        leaf_type.map_or(0, |_| 2) + len
+    }
+}
+
 record! {
     /// Build information.
     [BuildInfo = 0x1603]
@@ -62,22 +272,407 @@ record! {
     /// Substring list. Point to `Leaf::SubstrList`.
     substr: LeafId,
     /// String content.
-    content: String,
+    content: ByteString,
+}
+
+record! {
+    /// Function ID, such as the functions of `Symbol::Callees` and `Symbol::Callers`, or the inlinee
+    /// of `Symbol::InlineSite`.
+    [FuncId = 0x1601]
+    /// Enclosing scope, or the null leaf index for the global scope. Point to `Leaf::StringId`.
+    scope: LeafId,
+    /// Leaf index of the function type. Point to `Leaf::Procedure`.
+    function_type: LeafId,
+    /// Name of the function.
+    name: String,
+}
+
+record! {
+    /// Reference to an external type server.
+    ///
+    /// Written by `Codeview::with_type_server`.
+    [TypeServer2 = 0x1515]
+    /// GUID of the program database.
+    guid: [u8; 16],
+    /// Age of the program database.
+    age: u32,
+    /// Path of the program database.
+    name: ByteString,
+}
+
+record! {
+    /// Reference to precompiled types.
+    ///
+    /// Written by `Codeview::with_precomp`.
+    [Precomp = 0x1509, legacy = 0x100E]
+    /// First leaf index of the precompiled types.
+    start: u32,
+    /// Number of precompiled types.
+    count: u32,
+    /// Signature of the precompiled types.
+    signature: u32,
+    /// Path of the object containing the precompiled types.
+    name: ByteString,
+}
+
+record! {
+    /// End of precompiled types.
+    ///
+    /// Written by `Codeview::with_end_precomp`.
+    [EndPrecomp = 0x0014]
+    /// Signature of the precompiled types.
+    signature: u32,
+}
+
+record! {
+    /// Type modifier.
+    [Modifier = 0x1001]
+    /// Modified type.
+    modified_type: LeafId,
+    /// Modifiers. Bit 0 is `const`, bit 1 is `volatile` and bit 2 is `unaligned`.
+    modifiers: u16,
+}
+
+record! {
+    /// Pointer type.
+    [Pointer = 0x1002]
+    /// Type that the pointer points to.
+    pointee: LeafId,
+    /// Pointer attributes (`CV_PTR_*` kind, mode and flags, and the size in bytes).
+    attributes: u32,
+}
+
+impl Pointer {
+    /// Create a new plain `Pointer` instance with the given pointer size in bytes.
+    pub fn new(pointee: LeafId, size: u8) -> Pointer {
+        let kind = if size == 8 { 0x0C } else { 0x0A };
+        Pointer {
+            pointee,
+            attributes: kind | (size as u32) << 13,
+        }
+    }
+}
+
+record! {
+    /// Procedure type.
+    [Procedure = 0x1008]
+    /// Return type.
+    return_type: LeafId,
+    /// Calling convention (`CV_call_e`).
+    calling_convention: u8,
+    /// Function attributes (`CV_funcattr_t`).
+    function_attributes: u8,
+    /// Number of parameters.
+    parameter_count: u16,
+    /// Parameter types. Point to `Leaf::ArgList`.
+    argument_list: LeafId,
+}
+
+record! {
+    /// Argument list.
+    [ArgList = 0x1201]
+    ///
+    (len(arguments)): u32,
+    /// Argument types.
+    arguments: Vec<LeafId>,
+}
+
+record! {
+    /// Array type.
+    [Array = 0x1503, legacy = 0x1003]
+    /// Element type.
+    element_type: LeafId,
+    /// Index type.
+    index_type: LeafId,
+    /// Size of the array in bytes.
+    size: Numeric,
+    /// Name of the array.
+    name: String,
+}
+
+record! {
+    /// Structure type.
+    [Structure = 0x1505, legacy = 0x1005]
+    /// Number of members.
+    member_count: u16,
+    /// Type properties (`CV_prop_t`).
+    properties: u16,
+    /// Members. Point to `Leaf::FieldList`.
+    field_list: LeafId,
+    /// Derivation list.
+    derived_from: LeafId,
+    /// Shape of the virtual function table. Point to `Leaf::VTShape`.
+    vtable_shape: LeafId,
+    /// Size of the structure in bytes.
+    size: Numeric,
+    /// Name of the structure.
+    name: String,
+    /// Unique name of the structure, if the properties have `HasUniqueName` set.
+    unique_name: Option<String>,
+}
+
+record! {
+    /// Union type.
+    [Union = 0x1506, legacy = 0x1006]
+    /// Number of members.
+    member_count: u16,
+    /// Type properties (`CV_prop_t`).
+    properties: u16,
+    /// Members. Point to `Leaf::FieldList`.
+    field_list: LeafId,
+    /// Size of the union in bytes.
+    size: Numeric,
+    /// Name of the union.
+    name: String,
+    /// Unique name of the union, if the properties have `HasUniqueName` set.
+    unique_name: Option<String>,
+}
+
+record! {
+    /// Enumeration type.
+    [Enum = 0x1507, legacy = 0x1007]
+    /// Number of enumerators.
+    member_count: u16,
+    /// Type properties (`CV_prop_t`).
+    properties: u16,
+    /// Underlying integer type.
+    underlying_type: LeafId,
+    /// Enumerators. Point to `Leaf::FieldList`.
+    field_list: LeafId,
+    /// Name of the enumeration.
+    name: String,
+    /// Unique name of the enumeration, if the properties have `HasUniqueName` set.
+    unique_name: Option<String>,
+}
+
+record! {
+    /// Data member of a field list.
+    [Member = 0x150D, legacy = 0x1405]
+    /// Member attributes (`CV_fldattr_t`).
+    attributes: u16,
+    /// Type of the member.
+    member_type: LeafId,
+    /// Offset of the member in bytes.
+    offset: Numeric,
+    /// Name of the member.
+    name: String,
+}
+
+record! {
+    /// Enumerator of a field list.
+    [Enumerate = 0x1502, legacy = 0x0403]
+    /// Enumerator attributes (`CV_fldattr_t`).
+    attributes: u16,
+    /// Value of the enumerator.
+    value: Numeric,
+    /// Name of the enumerator.
+    name: String,
+}
+
+record! {
+    /// Static data member of a field list.
+    [StaticMember = 0x150E, legacy = 0x1406]
+    /// Member attributes (`CV_fldattr_t`).
+    attributes: u16,
+    /// Type of the member.
+    member_type: LeafId,
+    /// Name of the member.
+    name: String,
+}
+
+/// Kind of an entry in a virtual function table.
+#[derive(Debug, Clone, Copy)]
+pub enum VTableSlotKind {
+    /// 16-bit near pointer.
+    Near16 = 0,
+    /// 16-bit far pointer.
+    Far16 = 1,
+    /// Thin pointer.
+    This = 2,
+    /// Address point displacement to outermost class.
+    Outer = 3,
+    /// Far pointer to metaclass descriptor.
+    Meta = 4,
+    /// 32-bit or 64-bit near pointer.
+    Near = 5,
+    /// 32-bit or 64-bit far pointer.
+    Far = 6,
+}
+
+/// Shape of a virtual function table.
+#[derive(Debug, Clone)]
+pub struct VTShape {
+    /// Kinds of the entries.
+    pub slots: Vec<VTableSlotKind>,
+}
+
+impl<Reloc> Writable<Reloc> for VTShape {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        Writable::<Reloc>::write(&u16::try_from(self.slots.len())?, writer)?;
+        // Two entries per byte, the first one in the high nibble.
+        for pair in self.slots.chunks(2) {
+            let low = pair.get(1).map_or(0, |slot| *slot as u8);
+            Writable::<Reloc>::write(&((pair[0] as u8) << 4 | low), writer)?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        2 + self.slots.len().div_ceil(2)
+    }
+}
+
+impl<Reloc> WritableRecord<Reloc> for VTShape {
+    fn type_id(&self) -> u16 {
+        0x000A
+    }
+
+    fn kind(&self) -> &'static str {
+        "VTShape"
+    }
+}
+
+record! {
+    /// Virtual function table.
+    [VFTable = 0x151D]
+    /// Class that the table is complete for.
+    complete_class: LeafId,
+    /// Table that this table overrides. Point to `Leaf::VFTable`.
+    overridden_vftable: LeafId,
+    /// Offset of the virtual function table pointer in the object.
+    vfptr_offset: u32,
+    ///
+    (size(names)): u32,
+    /// Name of the table, followed by names of the methods.
+    names: Vec<String>,
+}
+
+record! {
+    /// Virtual function table pointer member of a field list.
+    [VFuncTab = 0x1409]
+    ///
+    (zero(padding)): u16,
+    /// Type of the pointer. Point to a pointer to `Leaf::VTShape`.
+    pointer_type: LeafId,
+}
+
+record! {
+    /// Continuation of a field list.
+    [Index = 0x1404]
+    ///
+    (zero(padding)): u16,
+    /// Continuation of the members. Point to `Leaf::FieldList`.
+    continuation: LeafId,
+}
+
+all_fields! {
+    /// A member of a field list.
+    #[derive(Debug, Clone)]
+    pub enum Field {
+        VFuncTab,
+        Member,
+        Enumerate,
+        Index,
+        StaticMember,
+    }
+}
+
+record! {
+    /// Field list.
+    [FieldList = 0x1203, nested = true]
+    /// Members.
+    fields: Vec<Field>,
 }
 
 all_records! {
     /// A record ("leaf") in the CodeView type section.
     #[derive(Debug, Clone)]
     pub enum Leaf {
+        FuncId,
         BuildInfo,
         SubstrList,
         StringId,
+        VTShape,
+        VFTable,
+        FieldList,
+        Modifier,
+        Pointer,
+        Procedure,
+        ArgList,
+        Array,
+        Structure,
+        Union,
+        Enum,
+    }
+}
+
+/// Whether a leaf describes a type, or is an ID record such as a string or build information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeafKind {
+    Type,
+    Id,
+}
+
+impl fmt::Display for LeafKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LeafKind::Type => write!(f, "type"),
+            LeafKind::Id => write!(f, "ID"),
+        }
+    }
+}
+
+/// The kind of a leaf.
+pub(crate) fn kind(leaf: &Leaf) -> LeafKind {
+    match leaf {
+        Leaf::FuncId(_) | Leaf::BuildInfo(_) | Leaf::SubstrList(_) | Leaf::StringId(_) => {
+            LeafKind::Id
+        }
+        _ => LeafKind::Type,
+    }
+}
+
+/// Leaf indices referenced by a leaf, with the kind of leaf each must point to.
+pub(crate) fn references(leaf: &Leaf) -> Vec<(LeafId, LeafKind)> {
+    let types = |ids: &[LeafId]| ids.iter().map(|id| (*id, LeafKind::Type)).collect();
+    let ids = |ids: &[LeafId]| ids.iter().map(|id| (*id, LeafKind::Id)).collect();
+    match leaf {
+        Leaf::FuncId(leaf) => vec![
+            (leaf.scope, LeafKind::Id),
+            (leaf.function_type, LeafKind::Type),
+        ],
+        Leaf::BuildInfo(leaf) => ids(&leaf.args),
+        Leaf::SubstrList(leaf) => ids(&leaf.strings),
+        Leaf::StringId(leaf) => ids(&[leaf.substr]),
+        Leaf::VTShape(_) => vec![],
+        Leaf::VFTable(leaf) => types(&[leaf.complete_class, leaf.overridden_vftable]),
+        Leaf::FieldList(leaf) => leaf
+            .fields
+            .iter()
+            .filter_map(|field| match field {
+                Field::VFuncTab(field) => Some(field.pointer_type),
+                Field::Member(field) => Some(field.member_type),
+                Field::Enumerate(_) => None,
+                Field::Index(field) => Some(field.continuation),
+                Field::StaticMember(field) => Some(field.member_type),
+            })
+            .map(|id| (id, LeafKind::Type))
+            .collect(),
+        Leaf::Modifier(leaf) => types(&[leaf.modified_type]),
+        Leaf::Pointer(leaf) => types(&[leaf.pointee]),
+        Leaf::Procedure(leaf) => types(&[leaf.return_type, leaf.argument_list]),
+        Leaf::ArgList(leaf) => types(&leaf.arguments),
+        Leaf::Array(leaf) => types(&[leaf.element_type, leaf.index_type]),
+        Leaf::Structure(leaf) => types(&[leaf.field_list, leaf.derived_from, leaf.vtable_shape]),
+        Leaf::Union(leaf) => types(&[leaf.field_list]),
+        Leaf::Enum(leaf) => types(&[leaf.underlying_type, leaf.field_list]),
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::encoding::StringPolicy;
     use crate::section_write::*;
 
     fn test_leaf(leaf: Leaf, data: &[u8]) {
@@ -86,8 +681,106 @@ mod test {
         assert_eq!(sink.data, data);
     }
 
+    fn test_record<T: WritableRecord<()>>(record: T, data: &[u8]) {
+        let mut sink = SectionSink::<()>::new();
+        write_record(&record, &mut sink).unwrap();
+        assert_eq!(sink.data, data);
+    }
+
+    fn test_numeric(numeric: Numeric, data: &[u8]) {
+        let mut sink = SectionSink::<()>::new();
+        Writable::<()>::write(&numeric, &mut sink).unwrap();
+        assert_eq!(sink.data, data);
+        assert_eq!(Writable::<()>::size(&numeric), data.len());
+    }
+
+    #[test]
+    fn legacy_leaves() {
+        let mut sink = SectionSink::<()>::new();
+        let mut writer = PolicyWriter {
+            inner: &mut sink,
+            policy: StringPolicy::LengthPrefixed,
+        };
+        let field_list = Leaf::FieldList(FieldList {
+            fields: vec![Field::Member(Member {
+                attributes: 3,
+                member_type: LeafId(0x74),
+                offset: Numeric::Unsigned(0),
+                name: "a".to_string(),
+            })],
+        });
+        write(&field_list, &mut writer).unwrap();
+        let string = Leaf::StringId(StringId {
+            substr: LeafId::null(),
+            content: "s".into(),
+        });
+        write(&string, &mut writer).unwrap();
+        assert_eq!(
+            sink.data,
+            &[
+                14, 0, 0x03, 0x12, 0x05, 0x14, 3, 0, 0x74, 0, 0, 0, 0, 0, 1, b'a', 8, 0, 0x05,
+                0x16, 0, 0, 0, 0, b's', 0,
+            ]
+        );
+    }
+
+    #[test]
+    fn leaf_ids() {
+        assert_eq!(LeafId::try_from(0), Ok(LeafId::null()));
+        assert_eq!(
+            LeafId::try_from(0x74),
+            Ok(LeafId::primitive(Primitive::I32))
+        );
+        assert_eq!(
+            LeafId::try_from(0x674),
+            Ok(LeafId::primitive_pointer(Primitive::I32, 8))
+        );
+        assert_eq!(LeafId::try_from(0x1234).map(LeafId::index), Ok(0x1234));
+        assert_eq!(LeafId::try_from(0x800), Err(InvalidLeafId(0x800)));
+        assert_eq!(
+            LeafId::try_from(0x8000_1000),
+            Err(InvalidLeafId(0x8000_1000))
+        );
+        assert!(LeafId::primitive(Primitive::Void).is_primitive());
+        assert!(!LeafId(0x1000).is_primitive());
+    }
+
+    #[test]
+    fn numerics() {
+        test_numeric(Numeric::Unsigned(0x7FFF), &[0xFF, 0x7F]);
+        test_numeric(Numeric::Unsigned(0x8000), &[0x02, 0x80, 0x00, 0x80]);
+        test_numeric(
+            Numeric::Unsigned(0x1_0000),
+            &[0x04, 0x80, 0x00, 0x00, 0x01, 0x00],
+        );
+        test_numeric(
+            Numeric::Unsigned(0x1_0000_0000),
+            &[0x0A, 0x80, 0, 0, 0, 0, 1, 0, 0, 0],
+        );
+        test_numeric(Numeric::Signed(5), &[5, 0]);
+        test_numeric(Numeric::Signed(-1), &[0x00, 0x80, 0xFF]);
+        test_numeric(Numeric::Signed(-0x100), &[0x01, 0x80, 0x00, 0xFF]);
+        test_numeric(
+            Numeric::Signed(-0x1_0000),
+            &[0x03, 0x80, 0x00, 0x00, 0xFF, 0xFF],
+        );
+        test_numeric(
+            Numeric::Signed(i64::MIN),
+            &[0x09, 0x80, 0, 0, 0, 0, 0, 0, 0, 0x80],
+        );
+    }
+
     #[test]
     fn leaves() {
+        test_leaf(
+            Leaf::FuncId(FuncId {
+                scope: LeafId::null(),
+                function_type: LeafId(0x1002),
+                name: "f".to_string(),
+            }),
+            &[12, 0, 0x01, 0x16, 0, 0, 0, 0, 0x02, 0x10, 0, 0, b'f', 0],
+        );
+
         test_leaf(
             Leaf::BuildInfo(BuildInfo {
                 args: vec![LeafId(1), LeafId(0x22), LeafId(0x3344)],
@@ -109,11 +802,125 @@ mod test {
         test_leaf(
             Leaf::StringId(StringId {
                 substr: LeafId(0x1122_3344),
-                content: "hello".to_string(),
+                content: "hello".into(),
             }),
             &[
                 12, 0, 0x05, 0x16, 0x44, 0x33, 0x22, 0x11, b'h', b'e', b'l', b'l', b'o', 0,
             ],
         );
+
+        test_record(
+            TypeServer2 {
+                guid: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+                age: 3,
+                name: "a.pdb".into(),
+            },
+            &[
+                28, 0, 0x15, 0x15, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 3, 0, 0,
+                0, b'a', b'.', b'p', b'd', b'b', 0,
+            ],
+        );
+
+        test_record(
+            Precomp {
+                start: 0x1000,
+                count: 0x20,
+                signature: 0x1122_3344,
+                name: "a".into(),
+            },
+            &[
+                16, 0, 0x09, 0x15, 0, 0x10, 0, 0, 0x20, 0, 0, 0, 0x44, 0x33, 0x22, 0x11, b'a', 0,
+            ],
+        );
+
+        test_record(
+            EndPrecomp {
+                signature: 0x1122_3344,
+            },
+            &[6, 0, 0x14, 0x00, 0x44, 0x33, 0x22, 0x11],
+        );
+
+        test_leaf(
+            Leaf::VTShape(VTShape {
+                slots: vec![
+                    VTableSlotKind::Near,
+                    VTableSlotKind::Near,
+                    VTableSlotKind::Outer,
+                ],
+            }),
+            &[6, 0, 0x0A, 0x00, 3, 0, 0x55, 0x30],
+        );
+
+        test_leaf(
+            Leaf::VFTable(VFTable {
+                complete_class: LeafId(0x1001),
+                overridden_vftable: LeafId(0),
+                vfptr_offset: 8,
+                names: vec!["t".to_string(), "f".to_string()],
+            }),
+            &[
+                22, 0, 0x1D, 0x15, 0x01, 0x10, 0, 0, 0, 0, 0, 0, 8, 0, 0, 0, 4, 0, 0, 0, b't', 0,
+                b'f', 0,
+            ],
+        );
+
+        test_leaf(
+            Leaf::FieldList(FieldList {
+                fields: vec![Field::VFuncTab(VFuncTab {
+                    pointer_type: LeafId(0x1002),
+                })],
+            }),
+            &[10, 0, 0x03, 0x12, 0x09, 0x14, 0, 0, 0x02, 0x10, 0, 0],
+        );
+
+        test_leaf(
+            Leaf::FieldList(FieldList {
+                fields: vec![
+                    Field::Member(Member {
+                        attributes: 3,
+                        member_type: LeafId(0x74),
+                        offset: Numeric::Unsigned(4),
+                        name: "a".to_string(),
+                    }),
+                    Field::Enumerate(Enumerate {
+                        attributes: 3,
+                        value: Numeric::Signed(-1),
+                        name: "b".to_string(),
+                    }),
+                    Field::StaticMember(StaticMember {
+                        attributes: 3,
+                        member_type: LeafId(0x75),
+                        name: "c".to_string(),
+                    }),
+                ],
+            }),
+            &[
+                38, 0, 0x03, 0x12, 0x0D, 0x15, 3, 0, 0x74, 0, 0, 0, 4, 0, b'a', 0, 0x02, 0x15, 3,
+                0, 0x00, 0x80, 0xFF, b'b', 0, 0xF3, 0xF2, 0xF1, 0x0E, 0x15, 3, 0, 0x75, 0, 0, 0,
+                b'c', 0, 0xF2, 0xF1,
+            ],
+        );
+
+        test_leaf(
+            Leaf::Pointer(Pointer::new(LeafId(0x1000), 8)),
+            &[10, 0, 0x02, 0x10, 0, 0x10, 0, 0, 0x0C, 0, 1, 0],
+        );
+
+        test_leaf(
+            Leaf::Structure(Structure {
+                member_count: 1,
+                properties: 0x200,
+                field_list: LeafId(0x1001),
+                derived_from: LeafId(0),
+                vtable_shape: LeafId(0),
+                size: Numeric::Unsigned(8),
+                name: "S".to_string(),
+                unique_name: Some("u".to_string()),
+            }),
+            &[
+                24, 0, 0x05, 0x15, 1, 0, 0, 2, 0x01, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, 0,
+                b'S', 0, b'u', 0,
+            ],
+        );
     }
 }
@@ -2,19 +2,46 @@
 mod struct_macro;
 
 mod codeview;
+#[cfg(feature = "gimli")]
+mod dwarf;
+#[cfg(feature = "gimli")]
+mod dwarf_write;
+mod encoding;
 mod error;
 mod file;
+mod frame_data;
 pub mod leaf;
 mod line;
+mod pdb;
+mod reloc;
 mod section_write;
+mod string_table;
 mod subsection;
 pub mod symbol;
+pub mod type_builder;
+mod validate;
+mod writer;
 
 pub use codeview::Codeview;
-pub use error::Error;
-pub use file::FileId;
-pub use leaf::{Leaf, LeafId};
+#[cfg(feature = "gimli")]
+pub use dwarf::{convert_dwarf, DwarfConversion, UnsupportedDwarf};
+#[cfg(feature = "gimli")]
+pub use dwarf_write::{convert_codeview, CodeviewConversion, CodeviewRecord, UnsupportedCodeview};
+pub use encoding::{ByteString, StringPolicy};
+pub use error::{Error, ErrorContext};
+pub use file::{ChecksumKind, FileChecksum, FileChecksums, FileId, InvalidFileId};
+pub use frame_data::{FrameData, FrameDataEntry};
+pub use leaf::{InvalidLeafId, Leaf, LeafId, LeafKind};
 pub use line::{Block, Line, Lines};
-pub use section_write::{SectionSink, SectionWrite};
+pub use pdb::{Pdb, PdbFunction, PdbLines, PdbLocal, PdbSection};
+pub use reloc::{Rva, SecRel, SectionIndex};
+pub use section_write::{
+    RelocKind, RelocTarget, Relocation, SectionSink, SectionWrite, MACHINE_ARM64, MACHINE_X64,
+    MACHINE_X86,
+};
+pub use string_table::StringOffset;
 pub use subsection::Subsection;
 pub use symbol::Symbol;
+pub use type_builder::TypeBuilder;
+pub use validate::{Referrer, ValidationError};
+pub use writer::CodeviewWriter;
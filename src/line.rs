@@ -1,4 +1,7 @@
+use crate::error::{wu, Error};
 use crate::file::FileId;
+use crate::struct_macro::*;
+use std::convert::*;
 
 /// A subsection that records line number information.
 #[derive(Debug, Clone)]
@@ -6,6 +9,9 @@ pub struct Lines<Reloc> {
     /// Code address.
     pub address: Reloc,
 
+    /// Length of the code covered by the line numbers.
+    pub code_length: u32,
+
     /// Blocks of lines.
     pub blocks: Vec<Block>,
     // TODO: flags
@@ -36,3 +42,92 @@ pub struct Line {
     /// Whether the line is a statement or an expression.
     pub is_statement: bool,
 }
+
+impl<Reloc> Writable<Reloc> for Lines<Reloc> {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        wu(writer.write_secrel(&self.address, 0))?;
+        wu(writer.write_section(&self.address, 0))?;
+        Writable::<Reloc>::write(&0u16, writer)?;
+        Writable::<Reloc>::write(&self.code_length, writer)?;
+        self.blocks.write(writer)
+    }
+
+    fn size(&self) -> usize {
+        12 + Writable::<Reloc>::size(&self.blocks)
+    }
+}
+
+impl<Reloc> Writable<Reloc> for Block {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        Writable::<Reloc>::write(&self.file, writer)?;
+        Writable::<Reloc>::write(&u32::try_from(self.lines.len())?, writer)?;
+        Writable::<Reloc>::write(&u32::try_from(Writable::<Reloc>::size(self))?, writer)?;
+        self.lines.write(writer)
+    }
+
+    fn size(&self) -> usize {
+        12 + self.lines.len() * 8
+    }
+}
+
+impl<Reloc> Writable<Reloc> for Line {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        let delta = self.line_delta.unwrap_or(0);
+        if self.line_start >= 1 << 24 {
+            return Err(Error::LineNumberOverflow(self.line_start));
+        }
+        if delta >= 1 << 7 {
+            return Err(Error::LineDeltaOverflow(delta));
+        }
+        let flags = self.line_start | delta << 24 | u32::from(self.is_statement) << 31;
+        Writable::<Reloc>::write(&self.offset, writer)?;
+        Writable::<Reloc>::write(&flags, writer)
+    }
+
+    fn size(&self) -> usize {
+        8
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::section_write::SectionSink;
+
+    #[test]
+    fn blocks() {
+        let line = |offset, line_start, line_delta| Line {
+            offset,
+            line_start,
+            line_delta,
+            is_statement: true,
+        };
+        let block = Block {
+            file: FileId::try_from(0x18).unwrap(),
+            lines: vec![line(0, 10, None), line(4, 0x12_3456, Some(2))],
+        };
+        let mut sink = SectionSink::<()>::new();
+        Writable::<()>::write(&block, &mut sink).unwrap();
+        assert_eq!(
+            sink.data,
+            [
+                0x18, 0, 0, 0, 2, 0, 0, 0, 28, 0, 0, 0, 0, 0, 0, 0, 10, 0, 0, 0x80, 4, 0, 0, 0,
+                0x56, 0x34, 0x12, 0x82,
+            ]
+        );
+
+        let block = Block {
+            file: FileId::try_from(0).unwrap(),
+            lines: vec![line(0, 1 << 24, None)],
+        };
+        let error = Writable::<()>::write(&block, &mut SectionSink::<()>::new()).unwrap_err();
+        assert!(matches!(error, Error::LineNumberOverflow(0x100_0000)));
+
+        let block = Block {
+            file: FileId::try_from(0).unwrap(),
+            lines: vec![line(0, 10, Some(0x80))],
+        };
+        let error = Writable::<()>::write(&block, &mut SectionSink::<()>::new()).unwrap_err();
+        assert!(matches!(error, Error::LineDeltaOverflow(0x80)));
+    }
+}
@@ -0,0 +1,1085 @@
+use crate::codeview::Codeview;
+use crate::encoding::ByteString;
+use crate::error::{wu, Error};
+use crate::file::{ChecksumKind, FileChecksum, FileChecksums};
+use crate::leaf::{self, Leaf, LeafId, LeafKind};
+use crate::line::{Block, Line, Lines};
+use crate::reloc::{SecRel, SectionIndex};
+use crate::section_write::{RelocTarget, SectionSink, SectionWrite};
+use crate::string_table::StringOffset;
+use crate::struct_macro::Writable;
+use crate::symbol::{self, Symbol};
+use std::cmp::Ordering;
+use std::convert::*;
+
+const BLOCK_SIZE: usize = 0x1000;
+
+const STREAM_INFO: u16 = 1;
+const STREAM_TPI: u16 = 2;
+const STREAM_DBI: u16 = 3;
+const STREAM_IPI: u16 = 4;
+const STREAM_NAMES: u16 = 5;
+const STREAM_TPI_HASH: u16 = 6;
+const STREAM_IPI_HASH: u16 = 7;
+const STREAM_MODULE: u16 = 8;
+const STREAM_GLOBALS: u16 = 9;
+const STREAM_PUBLICS: u16 = 10;
+const STREAM_SYMBOLS: u16 = 11;
+const STREAM_SECTION_HEADERS: u16 = 12;
+const STREAM_COUNT: usize = 13;
+
+/// Number of hash buckets in global and public symbol hash tables.
+const GSI_HASH_BUCKETS: usize = 4096;
+
+/// Number of hash buckets in type hash tables.
+const TPI_HASH_BUCKETS: u32 = 0x3FFFF;
+
+/// A section of the image containing JIT-compiled code.
+#[derive(Debug, Clone)]
+pub struct PdbSection {
+    /// Name of the section, such as `".text"`. Only the first 8 bytes are used.
+    pub name: String,
+
+    /// Relative virtual address (RVA) of the section.
+    pub rva: u32,
+
+    /// Size of the section in memory.
+    pub size: u32,
+
+    /// Section flags (`IMAGE_SCN_*`).
+    pub characteristics: u32,
+}
+
+/// A JIT-compiled function.
+#[derive(Debug, Clone)]
+pub struct PdbFunction {
+    /// Name of the function.
+    pub name: String,
+
+    /// Relative virtual address (RVA) of the function.
+    pub rva: u32,
+
+    /// Length of the function code.
+    pub length: u32,
+
+    /// Leaf index of the function type. Point to `Leaf::Procedure`.
+    pub function_type: LeafId,
+
+    /// Line number information, grouped by source file.
+    pub lines: Vec<PdbLines>,
+
+    /// Local variables and parameters.
+    pub locals: Vec<PdbLocal>,
+}
+
+/// Line number information of a function from one source file.
+#[derive(Debug, Clone)]
+pub struct PdbLines {
+    /// Path of the source file.
+    pub file: ByteString,
+
+    /// Line numbers, with offsets relative to the start of the function.
+    pub lines: Vec<Line>,
+}
+
+/// A local variable or parameter stored relative to a register.
+#[derive(Debug, Clone)]
+pub struct PdbLocal {
+    /// Name of the variable.
+    pub name: String,
+
+    /// Leaf index of the variable type.
+    pub variable_type: LeafId,
+
+    /// Register number (`CV_HREG_e`), usually of the frame or stack pointer.
+    pub register: u16,
+
+    /// Offset of the variable from the register value.
+    pub offset: i32,
+}
+
+/// A standalone PDB file describing JIT-compiled functions.
+///
+/// The PDB contains a single module with the functions, and the types added to a `Codeview`.
+/// Debuggers load it for an image whose debug directory has the data returned by
+/// `debug_directory_data`.
+#[derive(Debug, Clone)]
+pub struct Pdb {
+    guid: [u8; 16],
+    age: u32,
+    machine: u16,
+    sections: Vec<PdbSection>,
+    functions: Vec<PdbFunction>,
+}
+
+impl Pdb {
+    /// Create a new `Pdb` instance with the given signature, for an image of the given machine,
+    /// such as `MACHINE_X64`.
+    pub fn new(guid: [u8; 16], age: u32, machine: u16) -> Pdb {
+        Pdb {
+            guid,
+            age,
+            machine,
+            sections: vec![],
+            functions: vec![],
+        }
+    }
+
+    /// Add a section of the image. Sections are numbered from 1 in the order they are added.
+    pub fn add_section(&mut self, section: PdbSection) {
+        self.sections.push(section);
+    }
+
+    /// Add a JIT-compiled function.
+    ///
+    /// The code of the function must lie within a single added section, or `write` fails with
+    /// `Error::FunctionOutsideSections`.
+    pub fn add_function(&mut self, function: PdbFunction) {
+        self.functions.push(function);
+    }
+
+    /// Get the CodeView data (`CV_INFO_PDB70`) for the debug directory of the image, which
+    /// refers to this PDB at the given path.
+    pub fn debug_directory_data(&self, path: &str) -> Vec<u8> {
+        let mut data = b"RSDS".to_vec();
+        data.extend_from_slice(&self.guid);
+        data.extend_from_slice(&self.age.to_le_bytes());
+        data.extend_from_slice(path.as_bytes());
+        data.push(0);
+        data
+    }
+
+    /// Find the section and offset of an address.
+    fn locate(&self, rva: u32) -> Option<RelocTarget> {
+        self.sections
+            .iter()
+            .enumerate()
+            .find_map(|(index, section)| {
+                let offset = rva.checked_sub(section.rva)?;
+                if offset >= section.size {
+                    return None;
+                }
+                Some(RelocTarget {
+                    section: u16::try_from(index + 1).ok()?,
+                    offset,
+                    rva,
+                })
+            })
+    }
+
+    /// Write the PDB file, with the types added to `types`.
+    ///
+    /// `types` must not use an external type server or precompiled types, and must only contain
+    /// type leaves, since ID leaves are numbered separately in a PDB.
+    pub fn write<Reloc>(&self, types: &Codeview<Reloc>) -> Result<Vec<u8>, Error> {
+        let leafs = types.local_leafs().ok_or(Error::ExternalTypes)?;
+
+        let mut locations = vec![];
+        for function in &self.functions {
+            let end = function.rva.checked_add(function.length.saturating_sub(1));
+            match (
+                self.locate(function.rva),
+                end.and_then(|end| self.locate(end)),
+            ) {
+                (Some(start), Some(end)) if start.section == end.section => locations.push(start),
+                _ => return Err(Error::FunctionOutsideSections(function.name.clone())),
+            }
+        }
+
+        let mut files: Vec<&ByteString> = vec![];
+        for lines in self.functions.iter().flat_map(|function| &function.lines) {
+            if !files.contains(&&lines.file) {
+                files.push(&lines.file);
+            }
+        }
+        let (names, name_offsets) = string_table(&files)?;
+
+        let (tpi, tpi_hash) = type_stream(leafs, STREAM_TPI_HASH)?;
+        let (ipi, ipi_hash) = type_stream(&[], STREAM_IPI_HASH)?;
+        let module = self.module_stream(&files, &name_offsets)?;
+        let globals = self.global_streams(&module.procs, &locations)?;
+        let dbi = self.dbi_stream(&module, &files, &locations)?;
+
+        let mut streams = vec![vec![]; STREAM_COUNT];
+        streams[STREAM_INFO as usize] = self.info_stream();
+        streams[STREAM_TPI as usize] = tpi;
+        streams[STREAM_DBI as usize] = dbi;
+        streams[STREAM_IPI as usize] = ipi;
+        streams[STREAM_NAMES as usize] = names;
+        streams[STREAM_TPI_HASH as usize] = tpi_hash;
+        streams[STREAM_IPI_HASH as usize] = ipi_hash;
+        streams[STREAM_MODULE as usize] = module.data;
+        streams[STREAM_GLOBALS as usize] = globals.globals;
+        streams[STREAM_PUBLICS as usize] = globals.publics;
+        streams[STREAM_SYMBOLS as usize] = globals.symbols;
+        streams[STREAM_SECTION_HEADERS as usize] = self.section_headers();
+        msf(&streams)
+    }
+
+    /// Write the PDB information stream with the signature and the named stream map.
+    fn info_stream(&self) -> Vec<u8> {
+        let mut data = Buffer::default();
+        data.u32(20000404); // VC70
+        data.u32(0);
+        data.u32(self.age);
+        data.bytes(&self.guid);
+
+        // Named stream map with "/names" as the only entry.
+        let name = b"/names";
+        data.u32(name.len() as u32 + 1);
+        data.bytes(name);
+        data.u8(0);
+        let capacity = 8;
+        let bucket = hash_string_v1(name) as u16 as u32 % capacity;
+        data.u32(1);
+        data.u32(capacity);
+        data.u32(1);
+        data.u32(1 << bucket);
+        data.u32(0);
+        data.u32(0);
+        data.u32(u32::from(STREAM_NAMES));
+        data.u32(0);
+
+        data.u32(20140508); // VC140, which indicates the IPI stream is present
+        data.data
+    }
+
+    /// Write the symbols and line numbers of the only module.
+    fn module_stream(&self, files: &[&ByteString], name_offsets: &[u32]) -> Result<Module, Error> {
+        let mut sink = SectionSink::new();
+        wu(sink.write(&4u32.to_le_bytes()))?;
+
+        let mut procs = vec![];
+        for function in &self.functions {
+            let start = sink.data.len();
+            procs.push(u32::try_from(start)?);
+            let symbol = Symbol::GlobalProc32(symbol::GlobalProc32 {
+                length: function.length,
+                debug_start: 0,
+                debug_end: function.length,
+                function_type: function.function_type,
+                offset: SecRel(function.rva),
+                section: SectionIndex(function.rva),
+                flags: 0,
+                name: function.name.clone(),
+            });
+            write_padded(&mut sink, 0, |sink| symbol::write(&symbol, sink))?;
+            for local in &function.locals {
+                let symbol = Symbol::RegRel32(symbol::RegRel32 {
+                    offset: local.offset,
+                    variable_type: local.variable_type,
+                    register: local.register,
+                    name: local.name.clone(),
+                });
+                write_padded(&mut sink, 0, |sink| symbol::write(&symbol, sink))?;
+            }
+            let end = u32::try_from(sink.data.len())?;
+            let symbol = Symbol::<u32>::End(symbol::End {});
+            write_padded(&mut sink, 0, |sink| symbol::write(&symbol, sink))?;
+            wu(sink.patch(start + 8, &end.to_le_bytes()))?;
+        }
+        let symbols_size = sink.data.len();
+
+        let mut checksums = FileChecksums::default();
+        let mut file_ids = vec![];
+        for offset in name_offsets {
+            file_ids.push(checksums.add(FileChecksum {
+                name: StringOffset(*offset),
+                kind: ChecksumKind::None,
+                checksum: vec![],
+            })?);
+        }
+        let file_id = |file: &ByteString| {
+            let index = files.iter().position(|f| *f == file).unwrap_or_default();
+            file_ids[index]
+        };
+        for function in &self.functions {
+            if function.lines.is_empty() {
+                continue;
+            }
+            let lines = Lines {
+                address: function.rva,
+                code_length: function.length,
+                blocks: function
+                    .lines
+                    .iter()
+                    .map(|lines| Block {
+                        file: file_id(&lines.file),
+                        lines: lines.lines.clone(),
+                    })
+                    .collect(),
+            };
+            let len = Writable::<u32>::size(&lines);
+            wu(sink.write(&0xF2u32.to_le_bytes()))?;
+            wu(sink.write(&u32::try_from(len)?.to_le_bytes()))?;
+            lines.write(&mut sink)?;
+        }
+        if !files.is_empty() {
+            let len = Writable::<u32>::size(&checksums);
+            wu(sink.write(&0xF4u32.to_le_bytes()))?;
+            wu(sink.write(&u32::try_from(len)?.to_le_bytes()))?;
+            checksums.write(&mut sink)?;
+        }
+        let lines_size = sink.data.len() - symbols_size;
+
+        // No global references.
+        wu(sink.write(&0u32.to_le_bytes()))?;
+
+        Ok(Module {
+            data: sink.resolve(0, |rva| self.locate(*rva))?,
+            procs,
+            symbols_size: u32::try_from(symbols_size)?,
+            lines_size: u32::try_from(lines_size)?,
+        })
+    }
+
+    /// Write the symbol record stream, and the global and public symbol streams indexing it.
+    fn global_streams(
+        &self,
+        procs: &[u32],
+        locations: &[RelocTarget],
+    ) -> Result<GlobalStreams, Error> {
+        let mut sink = SectionSink::<u32>::new();
+        let mut globals = vec![];
+        let mut publics = vec![];
+        for ((function, location), proc) in self.functions.iter().zip(locations).zip(procs) {
+            globals.push((u32::try_from(sink.data.len())?, function.name.as_str()));
+            let symbol = Symbol::ProcRef(symbol::ProcRef {
+                sum_name: 0,
+                symbol_offset: *proc,
+                module: 1,
+                name: function.name.clone(),
+            });
+            write_padded(&mut sink, 0, |sink| symbol::write(&symbol, sink))?;
+
+            publics.push((u32::try_from(sink.data.len())?, function.name.as_str()));
+            let symbol = Symbol::Public32(symbol::Public32 {
+                flags: 2, // cvpsfFunction
+                offset: location.offset,
+                section: location.section,
+                name: function.name.clone(),
+            });
+            write_padded(&mut sink, 0, |sink| symbol::write(&symbol, sink))?;
+        }
+
+        let hash = gsi_hash(&globals)?;
+
+        let public_hash = gsi_hash(&publics)?;
+        let mut addresses: Vec<_> = publics
+            .iter()
+            .zip(locations)
+            .map(|((offset, name), location)| (location.section, location.offset, *name, *offset))
+            .collect();
+        addresses.sort_by(|a, b| (a.0, a.1, a.2).cmp(&(b.0, b.1, b.2)));
+        let mut public = Buffer::default();
+        public.u32(u32::try_from(public_hash.len())?);
+        public.u32(u32::try_from(addresses.len() * 4)?);
+        public.bytes(&[0; 20]); // No thunks or sections.
+        public.bytes(&public_hash);
+        for (.., offset) in addresses {
+            public.u32(offset);
+        }
+
+        Ok(GlobalStreams {
+            symbols: sink.data,
+            globals: hash,
+            publics: public.data,
+        })
+    }
+
+    /// Write the debug information stream, which describes modules and sections.
+    fn dbi_stream(
+        &self,
+        module: &Module,
+        files: &[&ByteString],
+        locations: &[RelocTarget],
+    ) -> Result<Vec<u8>, Error> {
+        let mut contributions: Vec<_> = self
+            .functions
+            .iter()
+            .zip(locations)
+            .map(|(function, location)| {
+                let section = &self.sections[location.section as usize - 1];
+                (
+                    location.section,
+                    location.offset,
+                    function.length,
+                    section.characteristics,
+                )
+            })
+            .collect();
+        contributions.sort();
+
+        let mut modules = Buffer::default();
+        modules.u32(0);
+        match contributions.first() {
+            Some(&(section, offset, size, characteristics)) => {
+                section_contribution(&mut modules, section, offset, size, characteristics, 0)
+            }
+            None => section_contribution(&mut modules, 0xFFFF, u32::MAX, u32::MAX, 0, 0xFFFF),
+        }
+        modules.u16(0);
+        modules.u16(STREAM_MODULE);
+        modules.u32(module.symbols_size);
+        modules.u32(0);
+        modules.u32(module.lines_size);
+        modules.u16(u16::try_from(files.len())?);
+        modules.u16(0);
+        modules.u32(0);
+        modules.u32(0);
+        modules.u32(0);
+        modules.bytes(b"jit\0jit\0");
+        modules.align();
+
+        let mut section_contributions = Buffer::default();
+        section_contributions.u32(0xF12E_BA2D); // Ver60
+        for (section, offset, size, characteristics) in contributions {
+            section_contribution(
+                &mut section_contributions,
+                section,
+                offset,
+                size,
+                characteristics,
+                0,
+            );
+        }
+
+        let mut section_map = Buffer::default();
+        let count = u16::try_from(self.sections.len() + 1)?;
+        section_map.u16(count);
+        section_map.u16(count);
+        for (index, section) in self.sections.iter().enumerate() {
+            let characteristics = section.characteristics;
+            let mut flags = 0x0108; // IsSelector, AddressIs32Bit
+            if characteristics & 0x4000_0000 != 0 {
+                flags |= 0x01; // Read
+            }
+            if characteristics & 0x8000_0000 != 0 {
+                flags |= 0x02; // Write
+            }
+            if characteristics & 0x2000_0000 != 0 {
+                flags |= 0x04; // Execute
+            }
+            section_map_entry(&mut section_map, flags, index as u16 + 1, section.size);
+        }
+        // Absolute addresses.
+        section_map_entry(&mut section_map, 0x0208, count, u32::MAX);
+
+        let mut file_info = Buffer::default();
+        let file_count = u16::try_from(files.len())?;
+        file_info.u16(1);
+        file_info.u16(file_count);
+        file_info.u16(0);
+        file_info.u16(file_count);
+        let mut file_names = vec![];
+        for file in files {
+            file_info.u32(u32::try_from(file_names.len())?);
+            file_names.extend_from_slice(&file.0);
+            file_names.push(0);
+        }
+        file_info.bytes(&file_names);
+        file_info.align();
+
+        let (ec_names, _) = string_table(&[])?;
+
+        let mut debug_header = Buffer::default();
+        for index in 0..11 {
+            // Only the section header stream is present.
+            debug_header.u16(if index == 5 {
+                STREAM_SECTION_HEADERS
+            } else {
+                0xFFFF
+            });
+        }
+
+        let mut data = Buffer::default();
+        data.u32(0xFFFF_FFFF);
+        data.u32(19990903); // V70
+        data.u32(self.age);
+        data.u16(STREAM_GLOBALS);
+        data.u16(0x8E00); // New format, version 14.00
+        data.u16(STREAM_PUBLICS);
+        data.u16(0);
+        data.u16(STREAM_SYMBOLS);
+        data.u16(0);
+        data.u32(u32::try_from(modules.data.len())?);
+        data.u32(u32::try_from(section_contributions.data.len())?);
+        data.u32(u32::try_from(section_map.data.len())?);
+        data.u32(u32::try_from(file_info.data.len())?);
+        data.u32(0);
+        data.u32(0);
+        data.u32(u32::try_from(debug_header.data.len())?);
+        data.u32(u32::try_from(ec_names.len())?);
+        data.u16(0);
+        data.u16(self.machine);
+        data.u32(0);
+        data.bytes(&modules.data);
+        data.bytes(&section_contributions.data);
+        data.bytes(&section_map.data);
+        data.bytes(&file_info.data);
+        data.bytes(&ec_names);
+        data.bytes(&debug_header.data);
+        Ok(data.data)
+    }
+
+    /// Write the `IMAGE_SECTION_HEADER`s of the sections.
+    fn section_headers(&self) -> Vec<u8> {
+        let mut data = Buffer::default();
+        for section in &self.sections {
+            let mut name = [0; 8];
+            let len = section.name.len().min(8);
+            name[..len].copy_from_slice(&section.name.as_bytes()[..len]);
+            data.bytes(&name);
+            data.u32(section.size);
+            data.u32(section.rva);
+            data.u32(section.size);
+            data.bytes(&[0; 16]);
+            data.u32(section.characteristics);
+        }
+        data.data
+    }
+}
+
+/// The module stream, and where its parts are.
+struct Module {
+    data: Vec<u8>,
+
+    /// Offsets of the procedure symbols, in the order of functions.
+    procs: Vec<u32>,
+
+    symbols_size: u32,
+    lines_size: u32,
+}
+
+/// The symbol record stream, and the hash tables of global and public symbols in it.
+struct GlobalStreams {
+    symbols: Vec<u8>,
+    globals: Vec<u8>,
+    publics: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Buffer {
+    data: Vec<u8>,
+}
+
+impl Buffer {
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn align(&mut self) {
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0);
+        }
+    }
+}
+
+fn section_contribution(
+    data: &mut Buffer,
+    section: u16,
+    offset: u32,
+    size: u32,
+    characteristics: u32,
+    module: u16,
+) {
+    data.u16(section);
+    data.u16(0);
+    data.u32(offset);
+    data.u32(size);
+    data.u32(characteristics);
+    data.u16(module);
+    data.u16(0);
+    data.u32(0); // Data CRC
+    data.u32(0); // Relocation CRC
+}
+
+fn section_map_entry(data: &mut Buffer, flags: u16, frame: u16, length: u32) {
+    data.u16(flags);
+    data.u16(0);
+    data.u16(0);
+    data.u16(frame);
+    data.u16(0xFFFF);
+    data.u16(0xFFFF);
+    data.u32(0);
+    data.u32(length);
+}
+
+/// Write a record, and pad it to a multiple of 4 bytes as PDB streams require.
+///
+/// Type records are padded with `LF_PAD` bytes (`pad_base` 0xF0), and symbol records with zeros.
+fn write_padded(
+    sink: &mut SectionSink<u32>,
+    pad_base: u8,
+    write: impl FnOnce(&mut SectionSink<u32>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let start = sink.data.len();
+    write(sink)?;
+    let padding = (4 - (sink.data.len() - start) % 4) % 4;
+    for remaining in (1..=padding as u8).rev() {
+        sink.data.push(if pad_base == 0 {
+            0
+        } else {
+            pad_base + remaining
+        });
+    }
+    let len = u16::try_from(sink.data.len() - start - 2)?;
+    sink.data[start..start + 2].copy_from_slice(&len.to_le_bytes());
+    Ok(())
+}
+
+/// Write a type stream with its hash stream.
+fn type_stream(leafs: &[Leaf], hash_stream: u16) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let crc_table = crc_table();
+    let mut sink = SectionSink::new();
+    let mut hashes = Buffer::default();
+    let mut index_offsets = Buffer::default();
+    for (i, leaf) in leafs.iter().enumerate() {
+        let id = LeafId(0x1000 + u32::try_from(i)?);
+        if leaf::kind(leaf) != LeafKind::Type {
+            return Err(Error::UnsupportedIdLeaf.in_leaf(id));
+        }
+        let start = sink.data.len();
+        write_padded(&mut sink, 0xF0, |sink| leaf::write(leaf, sink)).map_err(|e| e.in_leaf(id))?;
+        if i == 0 || sink.data.len() / 0x2000 > start / 0x2000 {
+            index_offsets.u32(id.0);
+            index_offsets.u32(u32::try_from(start)?);
+        }
+        hashes.u32(type_hash(leaf, &sink.data[start..], &crc_table) % TPI_HASH_BUCKETS);
+    }
+
+    let mut data = Buffer::default();
+    let end = 0x1000 + u32::try_from(leafs.len())?;
+    data.u32(20040203); // V80
+    data.u32(56);
+    data.u32(0x1000);
+    data.u32(end);
+    data.u32(u32::try_from(sink.data.len())?);
+    data.u16(hash_stream);
+    data.u16(0xFFFF);
+    data.u32(4);
+    data.u32(TPI_HASH_BUCKETS);
+    let hashes_len = u32::try_from(hashes.data.len())?;
+    let index_offsets_len = u32::try_from(index_offsets.data.len())?;
+    data.u32(0);
+    data.u32(hashes_len);
+    data.u32(hashes_len);
+    data.u32(index_offsets_len);
+    data.u32(hashes_len + index_offsets_len);
+    data.u32(0);
+    data.bytes(&sink.data);
+
+    hashes.bytes(&index_offsets.data);
+    Ok((data.data, hashes.data))
+}
+
+/// Hash a type record, by name for complete user-defined types and by content otherwise.
+fn type_hash(leaf: &Leaf, record: &[u8], crc_table: &[u32; 256]) -> u32 {
+    let (properties, name, unique_name) = match leaf {
+        Leaf::Structure(leaf) => (leaf.properties, &leaf.name, &leaf.unique_name),
+        Leaf::Union(leaf) => (leaf.properties, &leaf.name, &leaf.unique_name),
+        Leaf::Enum(leaf) => (leaf.properties, &leaf.name, &leaf.unique_name),
+        _ => return jam_crc(record, crc_table),
+    };
+    let forward_ref = properties & 0x0080 != 0;
+    let scoped = properties & 0x0100 != 0;
+    let has_unique_name = properties & 0x0200 != 0;
+    let anonymous = has_unique_name
+        && ["<unnamed-tag>", "__unnamed"]
+            .iter()
+            .any(|anon| name == anon || name.ends_with(&format!("::{}", anon)));
+    match unique_name {
+        _ if !forward_ref && !scoped && !anonymous => hash_string_v1(name.as_bytes()),
+        Some(unique_name) if !forward_ref && has_unique_name && !anonymous => {
+            hash_string_v1(unique_name.as_bytes())
+        }
+        _ => jam_crc(record, crc_table),
+    }
+}
+
+fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut value = i as u32;
+        for _ in 0..8 {
+            value = if value & 1 != 0 {
+                0xEDB8_8320 ^ (value >> 1)
+            } else {
+                value >> 1
+            };
+        }
+        *entry = value;
+    }
+    table
+}
+
+/// CRC-32 without the final inversion, starting from 0.
+fn jam_crc(data: &[u8], table: &[u32; 256]) -> u32 {
+    data.iter().fold(0, |crc, byte| {
+        table[((crc ^ u32::from(*byte)) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// The string hash used by PDB hash tables (`LHashPbCb`).
+fn hash_string_v1(string: &[u8]) -> u32 {
+    let mut chunks = string.chunks_exact(4);
+    let mut result = 0;
+    for chunk in &mut chunks {
+        result ^= u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    let mut remainder = chunks.remainder();
+    if remainder.len() >= 2 {
+        result ^= u32::from(u16::from_le_bytes([remainder[0], remainder[1]]));
+        remainder = &remainder[2..];
+    }
+    if let Some(byte) = remainder.first() {
+        result ^= u32::from(*byte);
+    }
+    result |= 0x2020_2020;
+    result ^= result >> 11;
+    result ^ (result >> 16)
+}
+
+/// Write a string table stream, such as `/names`, and return the offsets of the strings in it.
+fn string_table(strings: &[&ByteString]) -> Result<(Vec<u8>, Vec<u32>), Error> {
+    let mut buffer = vec![0];
+    let mut offsets = vec![];
+    for string in strings {
+        offsets.push(u32::try_from(buffer.len())?);
+        buffer.extend_from_slice(&string.0);
+        buffer.push(0);
+    }
+
+    let mut buckets = vec![0; strings.len() * 2 + 1];
+    for (string, offset) in strings.iter().zip(&offsets) {
+        let hash = hash_string_v1(&string.0) as usize;
+        let len = buckets.len();
+        if let Some(slot) = (0..len)
+            .map(|i| (hash + i) % len)
+            .find(|slot| buckets[*slot] == 0)
+        {
+            buckets[slot] = *offset;
+        }
+    }
+
+    let mut data = Buffer::default();
+    data.u32(0xEFFE_EFFE);
+    data.u32(1);
+    data.u32(u32::try_from(buffer.len())?);
+    data.bytes(&buffer);
+    data.u32(u32::try_from(buckets.len())?);
+    for bucket in buckets {
+        data.u32(bucket);
+    }
+    data.u32(u32::try_from(strings.len())?);
+    Ok((data.data, offsets))
+}
+
+/// Compare symbol names in the order of hash buckets.
+fn gsi_name_cmp(a: &str, b: &str) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| {
+        if a.is_ascii() && b.is_ascii() {
+            a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase())
+        } else {
+            a.as_bytes().cmp(b.as_bytes())
+        }
+    })
+}
+
+/// Write a hash table of symbols, given their offsets in the symbol record stream and names.
+fn gsi_hash(symbols: &[(u32, &str)]) -> Result<Vec<u8>, Error> {
+    let mut records: Vec<_> = symbols
+        .iter()
+        .map(|(offset, name)| {
+            let bucket = hash_string_v1(name.as_bytes()) as usize % GSI_HASH_BUCKETS;
+            (bucket, *name, *offset)
+        })
+        .collect();
+    records.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| gsi_name_cmp(a.1, b.1)));
+
+    let mut bitmap = vec![0u32; (GSI_HASH_BUCKETS + 32) / 32];
+    let mut buckets = vec![];
+    for (i, (bucket, ..)) in records.iter().enumerate() {
+        if bitmap[bucket / 32] & 1 << (bucket % 32) == 0 {
+            bitmap[bucket / 32] |= 1 << (bucket % 32);
+            buckets.push(u32::try_from(i * 12)?);
+        }
+    }
+
+    let mut data = Buffer::default();
+    data.u32(0xFFFF_FFFF);
+    data.u32(0xF12F_091A); // V70
+    data.u32(u32::try_from(records.len() * 8)?);
+    data.u32(u32::try_from((bitmap.len() + buckets.len()) * 4)?);
+    for (.., offset) in &records {
+        data.u32(offset + 1);
+        data.u32(1);
+    }
+    for word in bitmap {
+        data.u32(word);
+    }
+    for bucket in buckets {
+        data.u32(bucket);
+    }
+    Ok(data.data)
+}
+
+/// Lay out streams in a multi-stream file (MSF).
+fn msf(streams: &[Vec<u8>]) -> Result<Vec<u8>, Error> {
+    // The first block is the superblock, and two free block maps are in every interval of
+    // `BLOCK_SIZE` blocks.
+    let mut next_block = 3;
+    let mut allocate = |len: usize| -> Vec<usize> {
+        let count = len.div_ceil(BLOCK_SIZE);
+        let mut blocks = vec![];
+        while blocks.len() < count {
+            if !matches!(next_block % BLOCK_SIZE, 1 | 2) {
+                blocks.push(next_block);
+            }
+            next_block += 1;
+        }
+        blocks
+    };
+
+    let stream_blocks: Vec<_> = streams.iter().map(|data| allocate(data.len())).collect();
+    let mut directory = Buffer::default();
+    directory.u32(u32::try_from(streams.len())?);
+    for data in streams {
+        directory.u32(u32::try_from(data.len())?);
+    }
+    for block in stream_blocks.iter().flatten() {
+        directory.u32(u32::try_from(*block)?);
+    }
+    let directory_blocks = allocate(directory.data.len());
+    if directory_blocks.len() > BLOCK_SIZE / 4 {
+        return Err(Error::PdbTooLarge);
+    }
+    let block_map = allocate(4)[0];
+    let block_count = next_block;
+
+    let mut file = vec![0; block_count * BLOCK_SIZE];
+    let mut put = |block: usize, data: &[u8]| {
+        let start = block * BLOCK_SIZE;
+        file[start..start + data.len()].copy_from_slice(data);
+    };
+
+    let mut superblock = Buffer::default();
+    superblock.bytes(b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0");
+    superblock.u32(BLOCK_SIZE as u32);
+    superblock.u32(1);
+    superblock.u32(u32::try_from(block_count)?);
+    superblock.u32(u32::try_from(directory.data.len())?);
+    superblock.u32(0);
+    superblock.u32(u32::try_from(block_map)?);
+    put(0, &superblock.data);
+
+    // All blocks are in use, so only the bits past the end of the file are set.
+    for interval in 0..block_count.div_ceil(BLOCK_SIZE) {
+        let map: Vec<u8> = (0..BLOCK_SIZE)
+            .map(|i| {
+                let first = (interval * BLOCK_SIZE + i) * 8;
+                (0..8)
+                    .filter(|bit| first + bit >= block_count)
+                    .fold(0, |byte, bit| byte | 1 << bit)
+            })
+            .collect();
+        for block in [interval * BLOCK_SIZE + 1, interval * BLOCK_SIZE + 2] {
+            if block < block_count {
+                put(block, &map);
+            }
+        }
+    }
+
+    for (data, blocks) in streams.iter().zip(&stream_blocks) {
+        for (chunk, block) in data.chunks(BLOCK_SIZE).zip(blocks) {
+            put(*block, chunk);
+        }
+    }
+    for (chunk, block) in directory.data.chunks(BLOCK_SIZE).zip(&directory_blocks) {
+        put(*block, chunk);
+    }
+    let mut map = Buffer::default();
+    for block in &directory_blocks {
+        map.u32(u32::try_from(*block)?);
+    }
+    put(block_map, &map.data);
+    Ok(file)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::leaf::Primitive;
+    use crate::section_write::MACHINE_X64;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Read the streams back from an MSF file.
+    fn read_msf(file: &[u8]) -> Vec<Vec<u8>> {
+        let block = |index: u32| &file[index as usize * BLOCK_SIZE..][..BLOCK_SIZE];
+        let directory_len = u32_at(file, 0x2C) as usize;
+        let directory: Vec<u8> = block(u32_at(file, 0x34))
+            .chunks(4)
+            .take(directory_len.div_ceil(BLOCK_SIZE))
+            .flat_map(|index| block(u32_at(index, 0)).to_vec())
+            .collect();
+        let count = u32_at(&directory, 0) as usize;
+        let mut blocks = 4 + count * 4;
+        (0..count)
+            .map(|i| {
+                let len = u32_at(&directory, 4 + i * 4) as usize;
+                let mut data = vec![];
+                for _ in 0..len.div_ceil(BLOCK_SIZE) {
+                    data.extend_from_slice(block(u32_at(&directory, blocks)));
+                    blocks += 4;
+                }
+                data.truncate(len);
+                data
+            })
+            .collect()
+    }
+
+    #[test]
+    fn functions() {
+        let mut types = Codeview::<()>::new();
+        let arguments = types
+            .add_leaf(Leaf::ArgList(leaf::ArgList {
+                arguments: vec![LeafId::primitive(Primitive::I32)],
+            }))
+            .unwrap();
+        let procedure = types
+            .add_leaf(Leaf::Procedure(leaf::Procedure {
+                return_type: LeafId::primitive(Primitive::I32),
+                calling_convention: 0,
+                function_attributes: 0,
+                parameter_count: 1,
+                argument_list: arguments,
+            }))
+            .unwrap();
+
+        let mut pdb = Pdb::new([0x11; 16], 1, MACHINE_X64);
+        pdb.add_section(PdbSection {
+            name: ".text".to_string(),
+            rva: 0x1000,
+            size: 0x100,
+            characteristics: 0x6000_0020,
+        });
+        pdb.add_function(PdbFunction {
+            name: "jitted".to_string(),
+            rva: 0x1010,
+            length: 0x20,
+            function_type: procedure,
+            lines: vec![PdbLines {
+                file: "jit.rs".into(),
+                lines: vec![
+                    Line {
+                        offset: 0,
+                        line_start: 3,
+                        line_delta: None,
+                        is_statement: true,
+                    },
+                    Line {
+                        offset: 8,
+                        line_start: 4,
+                        line_delta: None,
+                        is_statement: true,
+                    },
+                ],
+            }],
+            locals: vec![PdbLocal {
+                name: "x".to_string(),
+                variable_type: LeafId::primitive(Primitive::I32),
+                register: 334,
+                offset: -8,
+            }],
+        });
+        let streams = read_msf(&pdb.write(&types).unwrap());
+        assert_eq!(streams.len(), STREAM_COUNT);
+
+        let info = &streams[STREAM_INFO as usize];
+        assert_eq!(
+            &info[8..28],
+            &[
+                1, 0, 0, 0, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11,
+                0x11, 0x11, 0x11, 0x11
+            ]
+        );
+
+        let tpi = &streams[STREAM_TPI as usize];
+        assert_eq!(u32_at(tpi, 12), 0x1002);
+        assert_eq!(
+            &tpi[56..68],
+            &[0x0A, 0, 0x01, 0x12, 1, 0, 0, 0, 0x74, 0, 0, 0]
+        );
+        assert_eq!(
+            &streams[STREAM_TPI_HASH as usize][0..4],
+            &[0xCA, 0x0A, 0x01, 0]
+        );
+
+        // The procedure, with the offset of its end, section 1 and offset 0x10, and its local
+        // variable padded to 16 bytes.
+        let module = &streams[STREAM_MODULE as usize];
+        assert_eq!(u32_at(module, 0), 4);
+        assert_eq!(&module[4..8], &[46, 0, 0x10, 0x11]);
+        assert_eq!(u32_at(module, 12), 68);
+        assert_eq!(&module[36..42], &[0x10, 0, 0, 0, 1, 0]);
+        assert_eq!(
+            &module[52..68],
+            &[14, 0, 0x11, 0x11, 0xF8, 0xFF, 0xFF, 0xFF, 0x74, 0, 0, 0, 0x4E, 0x01, b'x', 0]
+        );
+        assert_eq!(&module[68..72], &[2, 0, 0x06, 0]);
+
+        let dbi = &streams[STREAM_DBI as usize];
+        assert_eq!(u32_at(dbi, 8), 1);
+        assert_eq!(&dbi[58..60], &[0x64, 0x86]);
+
+        assert_eq!(
+            pdb.debug_directory_data("a.pdb")[16..],
+            [0x11, 0x11, 0x11, 0x11, 1, 0, 0, 0, b'a', b'.', b'p', b'd', b'b', 0]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut pdb = Pdb::new([0; 16], 1, MACHINE_X64);
+        pdb.add_function(PdbFunction {
+            name: "f".to_string(),
+            rva: 0x1000,
+            length: 0x10,
+            function_type: LeafId::null(),
+            lines: vec![],
+            locals: vec![],
+        });
+        let error = pdb.write(&Codeview::<()>::new()).unwrap_err();
+        assert!(matches!(error, Error::FunctionOutsideSections(name) if name == "f"));
+
+        let mut types = Codeview::<()>::new();
+        types
+            .add_leaf(Leaf::StringId(leaf::StringId {
+                substr: LeafId::null(),
+                content: "a".into(),
+            }))
+            .unwrap();
+        let error = Pdb::new([0; 16], 1, MACHINE_X64).write(&types).unwrap_err();
+        assert!(matches!(error.root(), Error::UnsupportedIdLeaf));
+        assert_eq!(error.context().unwrap().leaf_index(), Some(LeafId(0x1000)));
+
+        let types = Codeview::<()>::with_end_precomp(0);
+        let error = Pdb::new([0; 16], 1, MACHINE_X64).write(&types).unwrap_err();
+        assert!(matches!(error, Error::ExternalTypes));
+    }
+}
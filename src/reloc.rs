@@ -0,0 +1,44 @@
+use crate::error::{wu, Error};
+use crate::struct_macro::*;
+
+/// A relative virtual address (RVA) of a relocation target.
+#[derive(Debug, Clone)]
+pub struct Rva<Reloc>(pub Reloc);
+
+/// The section index of a relocation target.
+#[derive(Debug, Clone)]
+pub struct SectionIndex<Reloc>(pub Reloc);
+
+/// The section-relative offset of a relocation target.
+#[derive(Debug, Clone)]
+pub struct SecRel<Reloc>(pub Reloc);
+
+impl<Reloc> Writable<Reloc> for Rva<Reloc> {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        wu(writer.write_rva(&self.0, 0))
+    }
+
+    fn size(&self) -> usize {
+        4
+    }
+}
+
+impl<Reloc> Writable<Reloc> for SectionIndex<Reloc> {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        wu(writer.write_section(&self.0, 0))
+    }
+
+    fn size(&self) -> usize {
+        2
+    }
+}
+
+impl<Reloc> Writable<Reloc> for SecRel<Reloc> {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        wu(writer.write_secrel(&self.0, 0))
+    }
+
+    fn size(&self) -> usize {
+        4
+    }
+}
@@ -1,3 +1,110 @@
+use crate::error::Error;
+use std::convert::TryFrom;
+
+/// `IMAGE_FILE_MACHINE_I386`
+pub const MACHINE_X86: u16 = 0x014C;
+
+/// `IMAGE_FILE_MACHINE_AMD64`
+pub const MACHINE_X64: u16 = 0x8664;
+
+/// `IMAGE_FILE_MACHINE_ARM64`
+pub const MACHINE_ARM64: u16 = 0xAA64;
+
+/// The kind of a relocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RelocKind {
+    /// 32-bit relative virtual address (RVA) of the target. (`IMAGE_REL_*_ADDR32NB`)
+    Addr32Nb,
+
+    /// 16-bit index of the section containing the target. (`IMAGE_REL_*_SECTION`)
+    Section,
+
+    /// 32-bit offset of the target from the start of its section. (`IMAGE_REL_*_SECREL`)
+    SecRel,
+
+    /// 32-bit virtual address of the target. (`IMAGE_REL_*_ADDR32`)
+    Addr32,
+
+    /// 64-bit virtual address of the target. (`IMAGE_REL_*_ADDR64`)
+    Addr64,
+
+    /// 32-bit CLR token of the target. (`IMAGE_REL_*_TOKEN`)
+    Token,
+}
+
+impl RelocKind {
+    /// Size of the relocation island in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            RelocKind::Section => 2,
+            RelocKind::Addr64 => 8,
+            _ => 4,
+        }
+    }
+
+    /// Whether the addend fits in the relocation island, as a signed or unsigned value.
+    pub fn fits_addend(self, addend: i64) -> bool {
+        let bits = self.size() as u32 * 8;
+        bits == 64 || (addend >= -(1 << (bits - 1)) && addend < 1 << bits)
+    }
+
+    /// The COFF relocation type for the given machine, such as `MACHINE_X64`.
+    ///
+    /// Returns `None` if the machine is unknown or has no such relocation.
+    pub fn coff_type(self, machine: u16) -> Option<u16> {
+        match (machine, self) {
+            (MACHINE_X86, RelocKind::Addr32) => Some(0x0006),
+            (MACHINE_X86, RelocKind::Addr32Nb) => Some(0x0007),
+            (MACHINE_X86, RelocKind::Section) => Some(0x000A),
+            (MACHINE_X86, RelocKind::SecRel) => Some(0x000B),
+            (MACHINE_X86, RelocKind::Token) => Some(0x000C),
+            (MACHINE_X86, RelocKind::Addr64) => None,
+            (MACHINE_X64, RelocKind::Addr64) => Some(0x0001),
+            (MACHINE_X64, RelocKind::Addr32) => Some(0x0002),
+            (MACHINE_X64, RelocKind::Addr32Nb) => Some(0x0003),
+            (MACHINE_X64, RelocKind::Section) => Some(0x000A),
+            (MACHINE_X64, RelocKind::SecRel) => Some(0x000B),
+            (MACHINE_X64, RelocKind::Token) => Some(0x000D),
+            (MACHINE_ARM64, RelocKind::Addr32) => Some(0x0001),
+            (MACHINE_ARM64, RelocKind::Addr32Nb) => Some(0x0002),
+            (MACHINE_ARM64, RelocKind::SecRel) => Some(0x0008),
+            (MACHINE_ARM64, RelocKind::Token) => Some(0x000C),
+            (MACHINE_ARM64, RelocKind::Section) => Some(0x000D),
+            (MACHINE_ARM64, RelocKind::Addr64) => Some(0x000E),
+            _ => None,
+        }
+    }
+}
+
+/// A relocation recorded by `SectionSink`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation<Reloc> {
+    /// Offset of the relocation island in the section.
+    pub offset: usize,
+
+    /// Kind of the relocation.
+    pub kind: RelocKind,
+
+    /// Relocation target.
+    pub target: Reloc,
+
+    /// Value added to the target address.
+    pub addend: i64,
+}
+
+/// Where a relocation target is placed in a linked image, used by `SectionSink::resolve`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RelocTarget {
+    /// One-based index of the section containing the target.
+    pub section: u16,
+
+    /// Offset of the target from the start of its section.
+    pub offset: u32,
+
+    /// Relative virtual address (RVA) of the target.
+    pub rva: u32,
+}
+
 /// A trait for byte-oriented sinks that supports adding relocation information.
 ///
 /// `Reloc` can be any type that carries relocation symbol information.
@@ -8,39 +115,68 @@ pub trait SectionWrite<Reloc> {
     /// Write plain data into the sink.
     fn write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
 
+    /// Write a relocation island and record a relocation of the given kind on it.
+    ///
+    /// Implementation should write `kind.size()` bytes. COFF relocations have implicit addends,
+    /// so the addend is usually written into the island. Callers must only pass addends for which
+    /// `kind.fits_addend` is true, and implementations may reject or panic on other addends.
+    fn write_reloc(
+        &mut self,
+        kind: RelocKind,
+        reloc: &Reloc,
+        addend: i64,
+    ) -> Result<(), Self::Error>;
+
     /// Write a relative virtual address (RVA) with relocation.
     ///
-    /// Implementation should writes a 4-byte island and record a `IMAGE_REL_*_ADDR32NB`
-    /// relocation on it.
-    fn write_rva(&mut self, reloc: &Reloc) -> Result<(), Self::Error>;
+    /// This writes a 4-byte island with a `IMAGE_REL_*_ADDR32NB` relocation on it.
+    fn write_rva(&mut self, reloc: &Reloc, addend: i64) -> Result<(), Self::Error> {
+        self.write_reloc(RelocKind::Addr32Nb, reloc, addend)
+    }
 
     /// Write a section index with relocation.
     ///
-    /// Implementation should writes a 2-byte island and record a `IMAGE_REL_*_SECTION`
-    /// relocation on it.
-    fn write_section(&mut self, reloc: &Reloc) -> Result<(), Self::Error>;
+    /// This writes a 2-byte island with a `IMAGE_REL_*_SECTION` relocation on it.
+    fn write_section(&mut self, reloc: &Reloc, addend: i64) -> Result<(), Self::Error> {
+        self.write_reloc(RelocKind::Section, reloc, addend)
+    }
 
     /// Write a section-relative address with relocation.
     ///
-    /// Implementation should writes a 4-byte island and record a `IMAGE_REL_*_SECREL`
-    /// relocation on it.
-    fn write_secrel(&mut self, reloc: &Reloc) -> Result<(), Self::Error>;
+    /// This writes a 4-byte island with a `IMAGE_REL_*_SECREL` relocation on it.
+    fn write_secrel(&mut self, reloc: &Reloc, addend: i64) -> Result<(), Self::Error> {
+        self.write_reloc(RelocKind::SecRel, reloc, addend)
+    }
+
+    /// Get the number of bytes written so far, if the writer supports overwriting data with
+    /// `patch`.
+    ///
+    /// When this is supported, lengths and offsets that depend on data written later are
+    /// patched after writing, instead of being computed in advance.
+    fn position(&self) -> Option<usize> {
+        None
+    }
+
+    /// Overwrite data previously written at the given position.
+    ///
+    /// This is only called if `position` returns `Some`.
+    fn patch(&mut self, position: usize, data: &[u8]) -> Result<(), Self::Error> {
+        let _ = (position, data);
+        Ok(())
+    }
 }
 
 /// A simple section writer that collects all data and relocations.
+///
+/// Addends are written into relocation islands, as COFF expects. Writing a relocation panics if
+/// the addend doesn't fit in the island.
 #[derive(Debug, Clone, Default)]
 pub struct SectionSink<Reloc> {
     /// Section data
     pub data: Vec<u8>,
 
-    /// Relative virtual address relocations.
-    pub reloc_rva: Vec<(usize, Reloc)>,
-
-    /// Section index relocations.
-    pub reloc_section: Vec<(usize, Reloc)>,
-
-    /// Section-relative address relocations.
-    pub reloc_secrel: Vec<(usize, Reloc)>,
+    /// Relocations, in the order they are written.
+    pub relocations: Vec<Relocation<Reloc>>,
 }
 
 impl<Reloc> SectionSink<Reloc> {
@@ -48,10 +184,55 @@ impl<Reloc> SectionSink<Reloc> {
     pub fn new() -> SectionSink<Reloc> {
         SectionSink {
             data: vec![],
-            reloc_rva: vec![],
-            reloc_section: vec![],
-            reloc_secrel: vec![],
+            relocations: vec![],
+        }
+    }
+
+    /// Relocations of the given kind.
+    pub fn relocations_of(&self, kind: RelocKind) -> impl Iterator<Item = &Relocation<Reloc>> {
+        self.relocations
+            .iter()
+            .filter(move |relocation| relocation.kind == kind)
+    }
+
+    /// Get the section data with all relocations applied, for use without a linker.
+    ///
+    /// `target` gives the placement of each relocation target, or `None` if it is unknown.
+    /// Absolute addresses are relative to `image_base`. The addend is added to every value,
+    /// including section indices. `Token` relocations can't be resolved.
+    pub fn resolve(
+        &self,
+        image_base: u64,
+        mut target: impl FnMut(&Reloc) -> Option<RelocTarget>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut data = self.data.clone();
+        for (index, relocation) in self.relocations.iter().enumerate() {
+            let offset = relocation.offset;
+            let error = |error: Error| error.in_relocation(index, relocation.kind);
+            let placement = target(&relocation.target)
+                .ok_or(Error::UnresolvedRelocation)
+                .map_err(error)?;
+            let value = match relocation.kind {
+                RelocKind::Addr32Nb => i128::from(placement.rva),
+                RelocKind::Section => i128::from(placement.section),
+                RelocKind::SecRel => i128::from(placement.offset),
+                RelocKind::Addr32 | RelocKind::Addr64 => {
+                    i128::from(image_base) + i128::from(placement.rva)
+                }
+                RelocKind::Token => {
+                    return Err(error(Error::UnsupportedRelocation(relocation.kind)))
+                }
+            };
+            let value = value + i128::from(relocation.addend);
+            let bytes = match relocation.kind.size() {
+                2 => u16::try_from(value).map(|v| v.to_le_bytes().to_vec()),
+                4 => u32::try_from(value).map(|v| v.to_le_bytes().to_vec()),
+                _ => u64::try_from(value).map(|v| v.to_le_bytes().to_vec()),
+            }
+            .map_err(|e| error(e.into()))?;
+            data[offset..offset + bytes.len()].copy_from_slice(&bytes);
         }
+        Ok(data)
     }
 }
 
@@ -61,19 +242,126 @@ impl<Reloc: Clone> SectionWrite<Reloc> for SectionSink<Reloc> {
         self.data.extend_from_slice(data);
         Ok(())
     }
-    fn write_rva(&mut self, reloc: &Reloc) -> Result<(), Self::Error> {
-        self.reloc_rva.push((self.data.len(), reloc.clone()));
-        self.data.extend_from_slice(&[0; 4]);
+    fn write_reloc(
+        &mut self,
+        kind: RelocKind,
+        reloc: &Reloc,
+        addend: i64,
+    ) -> Result<(), Self::Error> {
+        assert!(
+            kind.fits_addend(addend),
+            "Addend {:#x} doesn't fit in a {:?} relocation",
+            addend,
+            kind
+        );
+        self.relocations.push(Relocation {
+            offset: self.data.len(),
+            kind,
+            target: reloc.clone(),
+            addend,
+        });
+        self.data
+            .extend_from_slice(&addend.to_le_bytes()[0..kind.size()]);
         Ok(())
     }
-    fn write_section(&mut self, reloc: &Reloc) -> Result<(), Self::Error> {
-        self.reloc_section.push((self.data.len(), reloc.clone()));
-        self.data.extend_from_slice(&[0; 2]);
-        Ok(())
+    fn position(&self) -> Option<usize> {
+        Some(self.data.len())
     }
-    fn write_secrel(&mut self, reloc: &Reloc) -> Result<(), Self::Error> {
-        self.reloc_secrel.push((self.data.len(), reloc.clone()));
-        self.data.extend_from_slice(&[0; 4]);
+    fn patch(&mut self, position: usize, data: &[u8]) -> Result<(), Self::Error> {
+        self.data[position..position + data.len()].copy_from_slice(data);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn relocations() {
+        let mut sink = SectionSink::new();
+        sink.write(&[0xAA]).unwrap();
+        sink.write_secrel(&"a", 0).unwrap();
+        sink.write_section(&"a", 0).unwrap();
+        sink.write_reloc(RelocKind::Addr64, &"b", 0x10).unwrap();
+        sink.write_rva(&"c", -1).unwrap();
+
+        assert_eq!(
+            sink.data,
+            [0xAA, 0, 0, 0, 0, 0, 0, 0x10, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+        let relocations: Vec<_> = sink
+            .relocations
+            .iter()
+            .map(|r| (r.offset, r.kind, r.target, r.addend))
+            .collect();
+        assert_eq!(
+            relocations,
+            [
+                (1, RelocKind::SecRel, "a", 0),
+                (5, RelocKind::Section, "a", 0),
+                (7, RelocKind::Addr64, "b", 0x10),
+                (15, RelocKind::Addr32Nb, "c", -1),
+            ]
+        );
+        assert_eq!(sink.relocations_of(RelocKind::Section).count(), 1);
+
+        assert!(RelocKind::Section.fits_addend(0xFFFF));
+        assert!(RelocKind::Section.fits_addend(-0x8000));
+        assert!(!RelocKind::Section.fits_addend(0x1_0000));
+        assert!(!RelocKind::SecRel.fits_addend(-0x8000_0001));
+        assert!(RelocKind::Addr64.fits_addend(i64::MIN));
+        let result = std::panic::catch_unwind(|| {
+            SectionSink::new()
+                .write_secrel(&"a", 0x1_0000_0000)
+                .unwrap();
+        });
+        assert!(result.is_err());
+
+        assert_eq!(RelocKind::SecRel.coff_type(MACHINE_X64), Some(0x000B));
+        assert_eq!(RelocKind::Section.coff_type(MACHINE_ARM64), Some(0x000D));
+        assert_eq!(RelocKind::Addr32Nb.coff_type(MACHINE_X86), Some(0x0007));
+        assert_eq!(RelocKind::Addr64.coff_type(MACHINE_X86), None);
+    }
+
+    #[test]
+    fn resolve() {
+        let mut sink = SectionSink::new();
+        sink.write_secrel(&"a", 0).unwrap();
+        sink.write_section(&"a", 0).unwrap();
+        sink.write_rva(&"b", 4).unwrap();
+        sink.write_reloc(RelocKind::Addr64, &"b", 0).unwrap();
+
+        let target = |reloc: &&str| match *reloc {
+            "a" => Some(RelocTarget {
+                section: 2,
+                offset: 0x10,
+                rva: 0x2010,
+            }),
+            "b" => Some(RelocTarget {
+                section: 1,
+                offset: 0x20,
+                rva: 0x1020,
+            }),
+            _ => None,
+        };
+        assert_eq!(
+            sink.resolve(0x1_4000_0000, target).unwrap(),
+            [0x10, 0, 0, 0, 2, 0, 0x24, 0x10, 0, 0, 0x20, 0x10, 0, 0x40, 1, 0, 0, 0]
+        );
+
+        sink.write_rva(&"c", 0).unwrap();
+        let error = sink.resolve(0, target).unwrap_err();
+        assert!(matches!(error.root(), Error::UnresolvedRelocation));
+        let context = error.context().unwrap();
+        assert_eq!(context.relocation(), Some((4, RelocKind::Addr32Nb)));
+        assert_eq!(context.to_string(), "Addr32Nb relocation 4");
+
+        let mut sink = SectionSink::new();
+        sink.write_rva(&"a", -0x3000).unwrap();
+        assert!(matches!(
+            sink.resolve(0, target).unwrap_err().root(),
+            Error::IntError(_)
+        ));
+    }
+}
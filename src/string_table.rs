@@ -0,0 +1,54 @@
+use crate::encoding::StringPolicy;
+use crate::error::Error;
+use crate::struct_macro::*;
+use std::collections::HashMap;
+use std::convert::*;
+use std::num::TryFromIntError;
+
+/// An offset into the string table subsection.
+#[derive(Debug, Clone)]
+pub struct StringOffset(pub(crate) u32);
+
+writable_transparent!(StringOffset);
+
+/// Strings referenced by offset from other subsections.
+#[derive(Debug, Default)]
+pub(crate) struct StringTable {
+    strings: Vec<String>,
+    offsets: HashMap<String, u32>,
+    size: usize,
+}
+
+impl StringTable {
+    pub fn add(&mut self, string: &str) -> Result<StringOffset, TryFromIntError> {
+        if let Some(offset) = self.offsets.get(string) {
+            return Ok(StringOffset(*offset));
+        }
+
+        // Offset 0 is reserved for the empty string at the beginning of the table.
+        let offset = (self.size + 1).try_into()?;
+        self.size += string.len() + 1;
+        self.strings.push(string.to_string());
+        self.offsets.insert(string.to_string(), offset);
+        Ok(StringOffset(offset))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+impl<Reloc> Writable<Reloc> for StringTable {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        let mut writer = PolicyWriter {
+            inner: writer,
+            policy: StringPolicy::NulTerminated,
+        };
+        Writable::<Reloc>::write(&0u8, &mut writer)?;
+        Writable::<Reloc>::write(&self.strings, &mut writer)
+    }
+
+    fn size(&self) -> usize {
+        self.size + 1
+    }
+}
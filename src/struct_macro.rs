@@ -1,16 +1,65 @@
+use crate::encoding::StringPolicy;
 use crate::error::{wu, Error};
-use crate::section_write::SectionWrite;
+use crate::section_write::{RelocKind, SectionSink, SectionWrite};
 use std::convert::*;
 
+/// A section writer that also knows how to encode strings in records.
+pub(crate) trait RecordWrite<Reloc>: SectionWrite<Reloc> {
+    fn string_policy(&self) -> StringPolicy;
+}
+
+impl<Reloc: Clone> RecordWrite<Reloc> for SectionSink<Reloc> {
+    fn string_policy(&self) -> StringPolicy {
+        StringPolicy::default()
+    }
+}
+
+/// A writer that encodes strings with the given policy.
+pub(crate) struct PolicyWriter<'a, W> {
+    pub inner: &'a mut W,
+    pub policy: StringPolicy,
+}
+
+impl<'a, Reloc, W: SectionWrite<Reloc>> SectionWrite<Reloc> for PolicyWriter<'a, W> {
+    type Error = W::Error;
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.inner.write(data)
+    }
+
+    fn write_reloc(
+        &mut self,
+        kind: RelocKind,
+        reloc: &Reloc,
+        addend: i64,
+    ) -> Result<(), Self::Error> {
+        self.inner.write_reloc(kind, reloc, addend)
+    }
+
+    fn position(&self) -> Option<usize> {
+        self.inner.position()
+    }
+
+    fn patch(&mut self, position: usize, data: &[u8]) -> Result<(), Self::Error> {
+        self.inner.patch(position, data)
+    }
+}
+
+impl<'a, Reloc, W: SectionWrite<Reloc>> RecordWrite<Reloc> for PolicyWriter<'a, W> {
+    fn string_policy(&self) -> StringPolicy {
+        self.policy
+    }
+}
+
 pub(crate) trait Writable<Reloc> {
-    fn write<W: SectionWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>>;
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>>;
     fn size(&self) -> usize;
 }
 
 macro_rules! writable_primitive {
     ($t:ty) => {
         impl<Reloc> Writable<Reloc> for $t {
-            fn write<W: SectionWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+            fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
                 wu(writer.write(&self.to_le_bytes()))?;
                 Ok(())
             }
@@ -25,33 +74,16 @@ macro_rules! writable_primitive {
 writable_primitive!(u8);
 writable_primitive!(u16);
 writable_primitive!(u32);
+writable_primitive!(u64);
 writable_primitive!(i8);
 writable_primitive!(i16);
 writable_primitive!(i32);
-
-impl<Reloc> Writable<Reloc> for String {
-    fn write<W: SectionWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
-        // It is uncertain which encoding CodeView uses.
-        // Maybe it is locale-dependent.
-        // However, on my en-US Windows system, Chinese characters are encoded in UTF-8
-        // in Visual Studio-generated CodeView data, so UTF-8 is a good bet here.
-        if self.as_bytes().iter().any(|c| *c == 0) {
-            return Result::Err(Error::StringError(self.clone()));
-        }
-        wu(writer.write(&self.as_bytes()))?;
-        wu(writer.write(&[0]))?;
-        Ok(())
-    }
-
-    fn size(&self) -> usize {
-        self.len() + 1
-    }
-}
+writable_primitive!(i64);
 
 macro_rules! writable_transparent {
     ($t:ty) => {
         impl<Reloc> Writable<Reloc> for $t {
-            fn write<W: SectionWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+            fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
                 Writable::<Reloc>::write(&self.0, writer)
             }
 
@@ -62,8 +94,48 @@ macro_rules! writable_transparent {
     };
 }
 
+macro_rules! writable_enum {
+    ($t:ty, $repr:ty) => {
+        impl<Reloc> Writable<Reloc> for $t {
+            fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+                Writable::<Reloc>::write(&(*self as $repr), writer)
+            }
+
+            fn size(&self) -> usize {
+                std::mem::size_of::<$repr>()
+            }
+        }
+    };
+}
+
 impl<Reloc, T: Writable<Reloc>> Writable<Reloc> for Vec<T> {
-    fn write<W: SectionWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        for element in self {
+            element.write(writer)?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.iter().map(|e| e.size()).sum()
+    }
+}
+
+impl<Reloc, T: Writable<Reloc>> Writable<Reloc> for Option<T> {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        match self {
+            Some(value) => value.write(writer),
+            None => Ok(()),
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.as_ref().map_or(0, |value| value.size())
+    }
+}
+
+impl<Reloc, T: Writable<Reloc>, const N: usize> Writable<Reloc> for [T; N] {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
         for element in self {
             element.write(writer)?;
         }
@@ -75,38 +147,75 @@ impl<Reloc, T: Writable<Reloc>> Writable<Reloc> for Vec<T> {
     }
 }
 
+impl<Reloc, A: Writable<Reloc>, B: Writable<Reloc>> Writable<Reloc> for (A, B) {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        self.0.write(writer)?;
+        self.1.write(writer)
+    }
+
+    fn size(&self) -> usize {
+        self.0.size() + self.1.size()
+    }
+}
+
 pub(crate) trait WritableRecord<Reloc>: Writable<Reloc> {
     fn type_id(&self) -> u16;
+    fn kind(&self) -> &'static str;
+
+    /// Record kind of the legacy form with length-prefixed strings, if there is one.
+    fn legacy_type_id(&self) -> Option<u16> {
+        None
+    }
+
+    /// Whether the record contains other records, which choose their own encoding.
+    fn has_nested_records(&self) -> bool {
+        false
+    }
+}
+
+/// Choose the record kind and string policy of a record.
+fn record_encoding<Reloc, T: WritableRecord<Reloc>>(
+    record: &T,
+    policy: StringPolicy,
+) -> (u16, StringPolicy) {
+    match (policy, record.legacy_type_id()) {
+        (StringPolicy::LengthPrefixed, Some(type_id)) => (type_id, policy),
+        (StringPolicy::LengthPrefixed, None) if record.has_nested_records() => {
+            (record.type_id(), policy)
+        }
+        (StringPolicy::LengthPrefixed, None) => (record.type_id(), StringPolicy::NulTerminated),
+        _ => (record.type_id(), policy),
+    }
 }
 
 macro_rules! record_struct {
     ($(#[$outer:meta])*
-    [$name:ident]
+    [$name:ident $(<$reloc:ident>)?]
     [{$(#[$inner1:meta])*} $m1:ident : $t1:ty , $({$(#[$inner:meta])*} $m:tt : $t:ty,)*]
     [$({$(#[$inner2:meta])*} $m2:ident : $t2:ty,)*]) => {
         record_struct!($(#[$outer])*
-            [$name]
+            [$name $(<$reloc>)?]
             [$({$(#[$inner])*} $m : $t,)*]
             [$({$(#[$inner2])*} $m2 : $t2,)* {$(#[$inner1])*} $m1 : $t1,]);
     };
 
     ($(#[$outer:meta])*
-    [$name:ident]
+    [$name:ident $(<$reloc:ident>)?]
     [{$(#[$inner1:meta])*} $m1:tt : $t1:ty , $({$(#[$inner:meta])*} $m:tt : $t:ty,)*]
-    [$({$(#[$inner2:meta])*} $m2:ty : $t2:ty,)*]) => {
+    [$({$(#[$inner2:meta])*} $m2:ident : $t2:ty,)*]) => {
         record_struct!($(#[$outer])*
-            [$name]
+            [$name $(<$reloc>)?]
             [$({$(#[$inner])*} $m : $t,)*]
             [$({$(#[$inner2])*} $m2 : $t2,)*]);
     };
 
     ($(#[$outer:meta])*
-    [$name:ident]
+    [$name:ident $(<$reloc:ident>)?]
     []
     [$({$(#[$inner2:meta])*} $m2:ident : $t2:ty,)*]) => {
         $(#[$outer])*
         #[derive(Debug, Clone)]
-        pub struct $name {
+        pub struct $name $(<$reloc>)? {
             $($(#[$inner2])* pub $m2 : $t2,)*
         }
     };
@@ -117,7 +226,8 @@ macro_rules! record_write {
     [$m1:ident : $t1:ty , $($m:tt : $t:ty,)*]
     [$($s:stmt)*]) => {
         record_write!([$self, $writer, $reloc] [$($m : $t,)*] [$($s)*
-            Writable::<$reloc>::write(&$self.$m1, $writer)?;
+            Writable::<$reloc>::write(&$self.$m1, $writer)
+                .map_err(|e| e.in_field(stringify!($m1)))?;
         ])
     };
 
@@ -125,7 +235,29 @@ macro_rules! record_write {
     [(len($m1:ident)) : $t1:ty , $($m:tt : $t:ty,)*]
     [$($s:stmt)*]) => {
         record_write!([$self, $writer, $reloc] [$($m : $t,)*] [$($s)*
-            Writable::<$reloc>::write(&<$t1>::try_from($self.$m1.len())?, $writer)?;
+            <$t1>::try_from($self.$m1.len())
+                .map_err(Error::from)
+                .and_then(|len| Writable::<$reloc>::write(&len, $writer))
+                .map_err(|e| e.in_field(stringify!($m1)))?;
+        ])
+    };
+
+    ([$self:ident, $writer:ident, $reloc:ident]
+    [(size($m1:ident)) : $t1:ty , $($m:tt : $t:ty,)*]
+    [$($s:stmt)*]) => {
+        record_write!([$self, $writer, $reloc] [$($m : $t,)*] [$($s)*
+            <$t1>::try_from(Writable::<$reloc>::size(&$self.$m1))
+                .map_err(Error::from)
+                .and_then(|size| Writable::<$reloc>::write(&size, $writer))
+                .map_err(|e| e.in_field(stringify!($m1)))?;
+        ])
+    };
+
+    ([$self:ident, $writer:ident, $reloc:ident]
+    [(zero($m1:ident)) : $t1:ty , $($m:tt : $t:ty,)*]
+    [$($s:stmt)*]) => {
+        record_write!([$self, $writer, $reloc] [$($m : $t,)*] [$($s)*
+            Writable::<$reloc>::write(&<$t1>::default(), $writer)?;
         ])
     };
 
@@ -153,6 +285,22 @@ macro_rules! record_size {
             [$($s)* std::mem::size_of::<$t1>() ])
     };
 
+    ([$self:ident, $reloc:ident]
+    [(size($m1:ident)) : $t1:ty , $($m:tt : $t:ty,)*]
+    [$($s:expr)*]) => {
+        record_size!([$self, $reloc]
+            [$($m : $t,)*]
+            [$($s)* std::mem::size_of::<$t1>() ])
+    };
+
+    ([$self:ident, $reloc:ident]
+    [(zero($m1:ident)) : $t1:ty , $($m:tt : $t:ty,)*]
+    [$($s:expr)*]) => {
+        record_size!([$self, $reloc]
+            [$($m : $t,)*]
+            [$($s)* std::mem::size_of::<$t1>() ])
+    };
+
     ([$self:ident, $reloc:ident]
     []
     [$($s:expr)*]) => {
@@ -162,12 +310,14 @@ macro_rules! record_size {
 
 macro_rules! record {
     ( $(#[$outer:meta])*
-    [ $name:ident = $type_id:literal ]
+    [ $name:ident $(<$reloc:ident>)? = $type_id:literal $(, legacy = $legacy_id:literal)?
+        $(, nested = $nested:literal)? ]
     $(#[doc=$ds:literal] $m:tt : $t:ty,)* ) => {
-        record_struct!($(#[$outer])* [$name] [$({#[doc=$ds]} $m : $t,)*] []);
+        record_struct!($(#[$outer])* [$name $(<$reloc>)?] [$({#[doc=$ds]} $m : $t,)*] []);
 
-        impl<Reloc> Writable<Reloc> for $name{
-            fn write<W: SectionWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        impl<Reloc> Writable<Reloc> for $name $(<$reloc>)? {
+            #[allow(unused_variables)]
+            fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
                 record_write!([self, writer, Reloc] [$($m : $t,)*] [] );
                 Ok(())
             }
@@ -177,38 +327,62 @@ macro_rules! record {
             }
         }
 
-        impl<Reloc> WritableRecord<Reloc> for $name {
+        impl<Reloc> WritableRecord<Reloc> for $name $(<$reloc>)? {
             fn type_id(&self) -> u16 {
                 $type_id
             }
+
+            fn kind(&self) -> &'static str {
+                stringify!($name)
+            }
+
+            $(
+                fn legacy_type_id(&self) -> Option<u16> {
+                    Some($legacy_id)
+                }
+            )?
+
+            $(
+                fn has_nested_records(&self) -> bool {
+                    $nested
+                }
+            )?
         }
     };
 }
 
-pub(crate) fn write_record<Reloc, T: WritableRecord<Reloc>, W: SectionWrite<Reloc>>(
+pub(crate) fn write_record<Reloc, T: WritableRecord<Reloc>, W: RecordWrite<Reloc>>(
     record: &T,
     writer: &mut W,
 ) -> Result<(), Error<W::Error>> {
-    u16::try_from(record.size() + 2)?.write(writer)?;
-    record.type_id().write(writer)?;
-    record.write(writer)
+    let (type_id, policy) = record_encoding(record, writer.string_policy());
+    let write = |writer: &mut W| {
+        u16::try_from(record.size() + 2)?.write(writer)?;
+        type_id.write(writer)?;
+        record.write(&mut PolicyWriter {
+            inner: writer,
+            policy,
+        })
+    };
+    write(writer).map_err(|e| e.in_record(record.kind()))
 }
 
 macro_rules! all_records {
-    ($(#[$outer:meta])* pub enum $name:ident$(<$reloc:ident>)? {$($t:ident,)*}) => {
+    ($(#[$outer:meta])* pub enum $name:ident$(<$reloc:ident>)? {$($t:ident $(<$treloc:ident>)?,)*}) => {
         $(#[$outer])*
         pub enum $name$(<$reloc>)? {
-            $( $t($t), )*
+            $( $t($t $(<$treloc>)?), )*
             $(Phantom(std::marker::PhantomData<$reloc>),)?
         }
 
-        pub(crate) fn write<Reloc, W: SectionWrite<Reloc>>(
+        pub(crate) fn write<Reloc, W: RecordWrite<Reloc>>(
             record: &$name$(<$reloc>)?,
             writer: &mut W
         ) -> Result<(), Error<W::Error>> {
             match record {
                 $( $name::$t(s) => write_record(s, writer) ,)*
-                _ => panic!()
+                #[allow(unreachable_patterns)]
+                _ => Err(Error::PhantomRecord(stringify!($name)))
             }
         }
 
@@ -216,7 +390,66 @@ macro_rules! all_records {
         pub(crate) fn size<Reloc>(record: &$name$(<$reloc>)?) -> usize {
             match record {
                 $( $name::$t(s) => Writable::<Reloc>::size(s) + 4 ,)*
-                _ => panic!()
+                #[allow(unreachable_patterns)]
+                _ => 0
+            }
+        }
+
+        /// Name of the variant holding the record.
+        #[allow(dead_code)]
+        pub(crate) fn kind_name$(<$reloc>)?(record: &$name$(<$reloc>)?) -> &'static str {
+            match record {
+                $( $name::$t(_) => stringify!($t) ,)*
+                #[allow(unreachable_patterns)]
+                _ => "Phantom"
+            }
+        }
+    };
+}
+
+/// Write a member of a field list, padded to 4-byte alignment.
+pub(crate) fn write_field<Reloc, T: WritableRecord<Reloc>, W: RecordWrite<Reloc>>(
+    field: &T,
+    writer: &mut W,
+) -> Result<(), Error<W::Error>> {
+    let (type_id, policy) = record_encoding(field, writer.string_policy());
+    let write = |writer: &mut W| {
+        type_id.write(writer)?;
+        field.write(&mut PolicyWriter {
+            inner: writer,
+            policy,
+        })?;
+        let padding = field_size(field) - field.size() - 2;
+        for i in (1..=padding).rev() {
+            (0xF0 + i as u8).write(writer)?;
+        }
+        Ok(())
+    };
+    write(writer).map_err(|e: Error<W::Error>| e.in_record(field.kind()))
+}
+
+pub(crate) fn field_size<Reloc, T: WritableRecord<Reloc>>(field: &T) -> usize {
+    (field.size() + 2 + 3) & !3
+}
+
+macro_rules! all_fields {
+    ($(#[$outer:meta])* pub enum $name:ident {$($t:ident,)*}) => {
+        $(#[$outer])*
+        pub enum $name {
+            $( $t($t), )*
+        }
+
+        impl<Reloc> Writable<Reloc> for $name {
+            fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+                match self {
+                    $( $name::$t(s) => write_field(s, writer) ,)*
+                }
+            }
+
+            fn size(&self) -> usize {
+                match self {
+                    $( $name::$t(s) => field_size::<Reloc, _>(s) ,)*
+                }
             }
         }
     };
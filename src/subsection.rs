@@ -1,3 +1,5 @@
+use crate::file::FileChecksums;
+use crate::frame_data::FrameData;
 use crate::line::Lines;
 use crate::symbol::Symbol;
 
@@ -9,4 +11,10 @@ pub enum Subsection<Reloc> {
 
     /// A subsection containing line records,
     Lines(Lines<Reloc>),
+
+    /// A subsection listing the source files referred to by line records,
+    FileChecksums(FileChecksums),
+
+    /// A subsection containing frame data for 32-bit x86 functions,
+    FrameData(FrameData<Reloc>),
 }
@@ -1,8 +1,10 @@
 #![allow(redundant_semicolons)]
 
-use crate::error::Error;
-use crate::leaf::LeafId;
-use crate::section_write::SectionWrite;
+use crate::encoding::ByteString;
+use crate::error::{wu, Error};
+use crate::file::FileId;
+use crate::leaf::{LeafId, LeafKind, Numeric};
+use crate::reloc::{SecRel, SectionIndex};
 use crate::struct_macro::*;
 use std::convert::*;
 
@@ -13,11 +15,903 @@ record! {
     leaf: LeafId,
 }
 
+record! {
+    /// End of a scope.
+    ///
+    /// Closes the innermost scope opened by `Symbol::GlobalProc32`, `Symbol::LocalProc32`,
+    /// `Symbol::Block32`, `Symbol::Thunk32` or `Symbol::SepCode`.
+    [End = 0x0006]
+}
+
+record! {
+    /// End of an inline site.
+    ///
+    /// Closes the innermost scope opened by `Symbol::InlineSite`.
+    [InlineSiteEnd = 0x114E]
+}
+
+record! {
+    /// Global procedure.
+    ///
+    /// Must be closed by a `Symbol::End`. The offsets of the enclosing scope and the closing
+    /// symbol are filled in by `Codeview::write`.
+    [GlobalProc32<Reloc> = 0x1110, legacy = 0x100B]
+    ///
+    (zero(parent)): u32,
+    ///
+    (zero(end)): u32,
+    ///
+    (zero(next)): u32,
+    /// Length of the procedure.
+    length: u32,
+    /// Offset of the end of the prolog, relative to the procedure start.
+    debug_start: u32,
+    /// Offset of the start of the epilog, relative to the procedure start.
+    debug_end: u32,
+    /// Leaf index of the procedure type. Point to `Leaf::Procedure`.
+    function_type: LeafId,
+    /// Offset of the procedure.
+    offset: SecRel<Reloc>,
+    /// Section of the procedure.
+    section: SectionIndex<Reloc>,
+    /// Procedure flags (`CV_PROCFLAGS`).
+    flags: u8,
+    /// Name of the procedure.
+    name: String,
+}
+
+record! {
+    /// Local procedure.
+    ///
+    /// Must be closed by a `Symbol::End`. The offsets of the enclosing scope and the closing
+    /// symbol are filled in by `Codeview::write`.
+    [LocalProc32<Reloc> = 0x110F, legacy = 0x100A]
+    ///
+    (zero(parent)): u32,
+    ///
+    (zero(end)): u32,
+    ///
+    (zero(next)): u32,
+    /// Length of the procedure.
+    length: u32,
+    /// Offset of the end of the prolog, relative to the procedure start.
+    debug_start: u32,
+    /// Offset of the start of the epilog, relative to the procedure start.
+    debug_end: u32,
+    /// Leaf index of the procedure type. Point to `Leaf::Procedure`.
+    function_type: LeafId,
+    /// Offset of the procedure.
+    offset: SecRel<Reloc>,
+    /// Section of the procedure.
+    section: SectionIndex<Reloc>,
+    /// Procedure flags (`CV_PROCFLAGS`).
+    flags: u8,
+    /// Name of the procedure.
+    name: String,
+}
+
+record! {
+    /// Block scope.
+    ///
+    /// Must be closed by a `Symbol::End`. The offsets of the enclosing scope and the closing
+    /// symbol are filled in by `Codeview::write`.
+    [Block32<Reloc> = 0x1103, legacy = 0x0207]
+    ///
+    (zero(parent)): u32,
+    ///
+    (zero(end)): u32,
+    /// Length of the block.
+    length: u32,
+    /// Offset of the block start.
+    offset: SecRel<Reloc>,
+    /// Section of the block start.
+    section: SectionIndex<Reloc>,
+    /// Name of the block.
+    name: String,
+}
+
+record! {
+    /// Code label.
+    [Label32<Reloc> = 0x1105, legacy = 0x0209]
+    /// Offset of the label.
+    offset: SecRel<Reloc>,
+    /// Section of the label.
+    section: SectionIndex<Reloc>,
+    /// Procedure flags (`CV_PROCFLAGS`).
+    flags: u8,
+    /// Name of the label.
+    name: String,
+}
+
+/// Kind of a thunk, with the data specific to the kind.
+#[derive(Debug, Clone)]
+pub enum ThunkOrdinal {
+    /// Standard thunk.
+    Standard,
+
+    /// `this` adjustor thunk.
+    ThisAdjustor {
+        /// Adjustment applied to `this`.
+        delta: u16,
+        /// Name of the target function.
+        target: String,
+    },
+
+    /// Virtual call thunk.
+    VirtualCall {
+        /// Offset into the virtual function table.
+        vtable_offset: u16,
+    },
+
+    /// P-code thunk.
+    PCode,
+
+    /// Delay load thunk.
+    DelayLoad,
+
+    /// Incremental linking trampoline.
+    TrampolineIncremental,
+
+    /// Branch island trampoline.
+    TrampolineBranchIsland,
+}
+
+impl ThunkOrdinal {
+    fn ordinal(&self) -> u8 {
+        match self {
+            ThunkOrdinal::Standard => 0,
+            ThunkOrdinal::ThisAdjustor { .. } => 1,
+            ThunkOrdinal::VirtualCall { .. } => 2,
+            ThunkOrdinal::PCode => 3,
+            ThunkOrdinal::DelayLoad => 4,
+            ThunkOrdinal::TrampolineIncremental => 5,
+            ThunkOrdinal::TrampolineBranchIsland => 6,
+        }
+    }
+}
+
+/// Thunk.
+///
+/// Must be closed by a `Symbol::End`. The offsets of the enclosing scope and the closing
+/// symbol are filled in by `Codeview::write`.
+#[derive(Debug, Clone)]
+pub struct Thunk32<Reloc> {
+    /// Offset of the thunk.
+    pub offset: SecRel<Reloc>,
+    /// Section of the thunk.
+    pub section: SectionIndex<Reloc>,
+    /// Length of the thunk.
+    pub length: u16,
+    /// Kind of the thunk.
+    pub ordinal: ThunkOrdinal,
+    /// Name of the thunk.
+    pub name: String,
+}
+
+impl<Reloc> Writable<Reloc> for Thunk32<Reloc> {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        // Offsets of the enclosing scope, the closing symbol and the next thunk.
+        for _ in 0..3 {
+            Writable::<Reloc>::write(&0u32, writer)?;
+        }
+        self.offset.write(writer)?;
+        self.section.write(writer)?;
+        Writable::<Reloc>::write(&self.length, writer)?;
+        Writable::<Reloc>::write(&self.ordinal.ordinal(), writer)?;
+        Writable::<Reloc>::write(&self.name, writer)?;
+        match &self.ordinal {
+            ThunkOrdinal::ThisAdjustor { delta, target } => {
+                Writable::<Reloc>::write(delta, writer)?;
+                Writable::<Reloc>::write(target, writer)
+            }
+            ThunkOrdinal::VirtualCall { vtable_offset } => {
+                Writable::<Reloc>::write(vtable_offset, writer)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn size(&self) -> usize {
+        let variant = match &self.ordinal {
+            ThunkOrdinal::ThisAdjustor { target, .. } => 2 + Writable::<Reloc>::size(target),
+            ThunkOrdinal::VirtualCall { .. } => 2,
+            _ => 0,
+        };
+        21 + Writable::<Reloc>::size(&self.name) + variant
+    }
+}
+
+impl<Reloc> WritableRecord<Reloc> for Thunk32<Reloc> {
+    fn type_id(&self) -> u16 {
+        0x1102
+    }
+
+    fn kind(&self) -> &'static str {
+        "Thunk32"
+    }
+
+    fn legacy_type_id(&self) -> Option<u16> {
+        Some(0x0206)
+    }
+}
+
+/// Kind of a trampoline.
+#[derive(Debug, Clone, Copy)]
+pub enum TrampolineKind {
+    /// Incremental linking thunk.
+    Incremental = 0,
+    /// Branch island.
+    BranchIsland = 1,
+}
+
+writable_enum!(TrampolineKind, u16);
+
+record! {
+    /// Trampoline.
+    [Trampoline<Reloc> = 0x112C]
+    /// Kind of the trampoline.
+    kind: TrampolineKind,
+    /// Size of the thunk.
+    size: u16,
+    /// Offset of the thunk.
+    thunk_offset: SecRel<Reloc>,
+    /// Offset of the target of the thunk.
+    target_offset: SecRel<Reloc>,
+    /// Section of the thunk.
+    thunk_section: SectionIndex<Reloc>,
+    /// Section of the target of the thunk.
+    target_section: SectionIndex<Reloc>,
+}
+
+record! {
+    /// Indirect call site information.
+    [CallSiteInfo<Reloc> = 0x1139]
+    /// Offset of the call instruction.
+    offset: SecRel<Reloc>,
+    /// Section of the call instruction.
+    section: SectionIndex<Reloc>,
+    ///
+    (zero(padding)): u16,
+    /// Leaf index of the function signature of the call target.
+    function_type: LeafId,
+}
+
+record! {
+    /// Heap allocation site.
+    [HeapAllocSite<Reloc> = 0x115E]
+    /// Offset of the call instruction.
+    offset: SecRel<Reloc>,
+    /// Section of the call instruction.
+    section: SectionIndex<Reloc>,
+    /// Length of the call instruction.
+    instruction_length: u16,
+    /// Leaf index of the function signature of the allocator, or the allocated type.
+    function_type: LeafId,
+}
+
+record! {
+    /// Functions called by the enclosing function.
+    [Callees = 0x115A]
+    ///
+    (len(functions)): u32,
+    /// Leaf indices of the called functions. Point to `Leaf::FuncId`.
+    functions: Vec<LeafId>,
+    /// Invocation counts in the same order as `functions`, or empty if unknown.
+    invocations: Vec<u32>,
+}
+
+record! {
+    /// Functions calling the enclosing function.
+    [Callers = 0x115B]
+    ///
+    (len(functions)): u32,
+    /// Leaf indices of the calling functions. Point to `Leaf::FuncId`.
+    functions: Vec<LeafId>,
+    /// Invocation counts in the same order as `functions`, or empty if unknown.
+    invocations: Vec<u32>,
+}
+
+/// How a frame cookie is computed.
+#[derive(Debug, Clone, Copy)]
+pub enum FrameCookieType {
+    /// Plain copy of the cookie.
+    Copy = 0,
+    /// Cookie XORed with the stack pointer.
+    XorSp = 1,
+    /// Cookie XORed with the frame pointer.
+    XorBp = 2,
+    /// Cookie XORed with R13.
+    XorR13 = 3,
+}
+
+writable_enum!(FrameCookieType, u8);
+
+record! {
+    /// Security cookie on the stack frame.
+    [FrameCookie = 0x113A]
+    /// Frame-relative offset of the cookie.
+    offset: i32,
+    /// Register that the offset is relative to.
+    register: u16,
+    /// How the cookie is computed.
+    cookie_type: FrameCookieType,
+    /// Flags.
+    flags: u8,
+}
+
+record! {
+    /// Separated code, such as a cold block split out of its function.
+    ///
+    /// Must be closed by a `Symbol::End`. The offsets of the enclosing scope and the closing
+    /// symbol are filled in by `Codeview::write`.
+    [SepCode<Reloc> = 0x1132]
+    ///
+    (zero(parent)): u32,
+    ///
+    (zero(end)): u32,
+    /// Length of the separated code.
+    length: u32,
+    /// Flags (`CV_SEPCODEFLAGS`).
+    flags: u32,
+    /// Offset of the separated code.
+    offset: SecRel<Reloc>,
+    /// Offset of the parent procedure.
+    parent_offset: SecRel<Reloc>,
+    /// Section of the separated code.
+    section: SectionIndex<Reloc>,
+    /// Section of the parent procedure.
+    parent_section: SectionIndex<Reloc>,
+}
+
+record! {
+    /// Build environment.
+    [EnvBlock = 0x113D]
+    ///
+    (zero(flags)): u8,
+    /// Key-value pairs, such as `("cwd", "D:\\test")` and `("cl", "cl.exe")`.
+    pairs: Vec<(String, ByteString)>,
+    ///
+    (zero(terminator)): u8,
+}
+
+record! {
+    /// Annotation from `__annotation`.
+    [Annotation<Reloc> = 0x1019]
+    /// Offset of the annotation.
+    offset: SecRel<Reloc>,
+    /// Section of the annotation.
+    section: SectionIndex<Reloc>,
+    ///
+    (len(strings)): u16,
+    /// Annotation strings.
+    strings: Vec<String>,
+}
+
+record! {
+    /// Section of a linked image.
+    [Section = 0x1136]
+    /// Section number.
+    section: u16,
+    /// Alignment of the section, as a power of two.
+    alignment: u8,
+    ///
+    (zero(reserved)): u8,
+    /// Relative virtual address of the section.
+    rva: u32,
+    /// Size of the section.
+    size: u32,
+    /// Section characteristics (`IMAGE_SCN_*`).
+    characteristics: u32,
+    /// Name of the section.
+    name: String,
+}
+
+record! {
+    /// Group of COFF sections merged into a section of a linked image, such as `.text$mn`.
+    [CoffGroup = 0x1137]
+    /// Size of the group.
+    size: u32,
+    /// Section characteristics (`IMAGE_SCN_*`).
+    characteristics: u32,
+    /// Offset of the group in the section.
+    offset: u32,
+    /// Section number.
+    section: u16,
+    /// Name of the group.
+    name: String,
+}
+
+record! {
+    /// Exported symbol of a linked image.
+    [Export = 0x1138]
+    /// Export ordinal.
+    ordinal: u16,
+    /// Export flags (`CV_EXPORTFLAGS`).
+    flags: u16,
+    /// Name of the export.
+    name: String,
+}
+
+record! {
+    /// Public symbol.
+    [Public32 = 0x110E, legacy = 0x1009]
+    /// Public symbol flags (`CV_PUBSYMFLAGS`).
+    flags: u32,
+    /// Offset of the symbol in its section.
+    offset: u32,
+    /// Section number of the symbol.
+    section: u16,
+    /// Name of the symbol.
+    name: String,
+}
+
+record! {
+    /// Reference to a global procedure in a module symbol stream.
+    [ProcRef = 0x1125, legacy = 0x0400]
+    /// Checksum of the name.
+    sum_name: u32,
+    /// Offset of the referenced symbol in the module symbol stream.
+    symbol_offset: u32,
+    /// Index of the module containing the referenced symbol, starting from 1.
+    module: u16,
+    /// Name of the referenced symbol.
+    name: String,
+}
+
+record! {
+    /// Reference to a data symbol in a module symbol stream.
+    [DataRef = 0x1126, legacy = 0x0401]
+    /// Checksum of the name.
+    sum_name: u32,
+    /// Offset of the referenced symbol in the module symbol stream.
+    symbol_offset: u32,
+    /// Index of the module containing the referenced symbol, starting from 1.
+    module: u16,
+    /// Name of the referenced symbol.
+    name: String,
+}
+
+record! {
+    /// Reference to a local procedure in a module symbol stream.
+    [LocalProcRef = 0x1127, legacy = 0x0403]
+    /// Checksum of the name.
+    sum_name: u32,
+    /// Offset of the referenced symbol in the module symbol stream.
+    symbol_offset: u32,
+    /// Index of the module containing the referenced symbol, starting from 1.
+    module: u16,
+    /// Name of the referenced symbol.
+    name: String,
+}
+
+record! {
+    /// Variable stored in a register.
+    [Register = 0x1106, legacy = 0x1001]
+    /// Leaf index of the variable type.
+    variable_type: LeafId,
+    /// Register number (`CV_HREG_e`).
+    register: u16,
+    /// Name of the variable.
+    name: String,
+}
+
+record! {
+    /// Variable stored in multiple registers.
+    [ManyReg = 0x110A, legacy = 0x1005]
+    /// Leaf index of the variable type.
+    variable_type: LeafId,
+    ///
+    (len(registers)): u8,
+    /// Register numbers (`CV_HREG_e`), from the most significant part.
+    registers: Vec<u8>,
+    /// Name of the variable.
+    name: String,
+}
+
+record! {
+    /// Variable stored in multiple registers, with 16-bit register numbers.
+    [ManyReg2 = 0x1117, legacy = 0x1014]
+    /// Leaf index of the variable type.
+    variable_type: LeafId,
+    ///
+    (len(registers)): u16,
+    /// Register numbers (`CV_HREG_e`), from the most significant part.
+    registers: Vec<u16>,
+    /// Name of the variable.
+    name: String,
+}
+
+record! {
+    /// Variable stored relative to a register, such as a local variable on the stack.
+    [RegRel32 = 0x1111, legacy = 0x100D]
+    /// Offset of the variable from the register value.
+    offset: i32,
+    /// Leaf index of the variable type.
+    variable_type: LeafId,
+    /// Register number (`CV_HREG_e`).
+    register: u16,
+    /// Name of the variable.
+    name: String,
+}
+
+record! {
+    /// Local variable or parameter, whose location is given by the `Symbol::DefRangeRegister`
+    /// and `Symbol::DefRangeRegisterRel` symbols following it.
+    [Local = 0x113E]
+    /// Leaf index of the variable type.
+    variable_type: LeafId,
+    /// Variable flags (`CV_LVARFLAGS`), such as 0x01 for parameters and 0x100 if optimized out.
+    flags: u16,
+    /// Name of the variable.
+    name: String,
+}
+
+/// Code range where a `Symbol::Local` has a location.
+#[derive(Debug, Clone)]
+pub struct AddressRange<Reloc> {
+    /// Offset of the start of the range.
+    pub offset: SecRel<Reloc>,
+    /// Section of the start of the range.
+    pub section: SectionIndex<Reloc>,
+    /// Length of the range.
+    pub length: u16,
+}
+
+impl<Reloc> Writable<Reloc> for AddressRange<Reloc> {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        self.offset.write(writer)?;
+        self.section.write(writer)?;
+        Writable::<Reloc>::write(&self.length, writer)
+    }
+
+    fn size(&self) -> usize {
+        8
+    }
+}
+
+/// Part of an `AddressRange` where the variable has no location.
+#[derive(Debug, Clone, Copy)]
+pub struct AddressGap {
+    /// Offset of the gap, relative to the start of the range.
+    pub offset: u16,
+    /// Length of the gap.
+    pub length: u16,
+}
+
+impl<Reloc> Writable<Reloc> for AddressGap {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        Writable::<Reloc>::write(&self.offset, writer)?;
+        Writable::<Reloc>::write(&self.length, writer)
+    }
+
+    fn size(&self) -> usize {
+        4
+    }
+}
+
+record! {
+    /// Location of the preceding `Symbol::Local` in a register.
+    [DefRangeRegister<Reloc> = 0x1141]
+    /// Register number (`CV_HREG_e`).
+    register: u16,
+    /// Whether the variable may have no name on some control flow paths.
+    may_have_no_name: u16,
+    /// Code range where the location applies.
+    range: AddressRange<Reloc>,
+    /// Parts of the range where the location doesn't apply.
+    gaps: Vec<AddressGap>,
+}
+
+record! {
+    /// Location of the preceding `Symbol::Local` relative to a register.
+    [DefRangeRegisterRel<Reloc> = 0x1145]
+    /// Register number (`CV_HREG_e`).
+    base_register: u16,
+    /// Bit 0 marks a member of a spilled structure, and bits 4-15 are its offset in it.
+    flags: u16,
+    /// Offset of the variable from the register value.
+    base_offset: i32,
+    /// Code range where the location applies.
+    range: AddressRange<Reloc>,
+    /// Parts of the range where the location doesn't apply.
+    gaps: Vec<AddressGap>,
+}
+
+/// An instruction describing the code ranges and source lines of an inline site.
+///
+/// Code offsets are relative to the enclosing procedure. Operands are written in a compressed
+/// form that holds values up to `0x1FFF_FFFF`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryAnnotation {
+    /// Set the code offset.
+    CodeOffset(u32),
+    /// Set the offset of the code in the section.
+    ChangeCodeOffsetBase(u32),
+    /// Advance the code offset and start a code range.
+    ChangeCodeOffset(u32),
+    /// Set the length of the current code range, and advance the code offset past it.
+    ChangeCodeLength(u32),
+    /// Change the source file.
+    ChangeFile(FileId),
+    /// Advance the line number.
+    ChangeLineOffset(i32),
+    /// Set the number of lines of the current statement.
+    ChangeLineEndDelta(u32),
+    /// Set whether lines are expressions (1) or statements (0).
+    ChangeRangeKind(u32),
+    /// Set the starting column.
+    ChangeColumnStart(u32),
+    /// Advance the ending column.
+    ChangeColumnEndDelta(i32),
+    /// Set the length of the current code range, and advance the code offset past it and then
+    /// by the given amount.
+    ChangeCodeLengthAndCodeOffset { length: u32, code_offset: u32 },
+    /// Set the ending column.
+    ChangeColumnEnd(u32),
+}
+
+impl BinaryAnnotation {
+    fn opcode_and_operands(&self) -> (u32, [Option<u32>; 2]) {
+        // A magnitude that doesn't survive the shift saturates, so writing it overflows.
+        let signed = |value: i32| {
+            let magnitude = (value as i64).unsigned_abs() as u32;
+            magnitude.saturating_mul(2) | u32::from(value < 0)
+        };
+        match *self {
+            BinaryAnnotation::CodeOffset(value) => (1, [Some(value), None]),
+            BinaryAnnotation::ChangeCodeOffsetBase(value) => (2, [Some(value), None]),
+            BinaryAnnotation::ChangeCodeOffset(value) => (3, [Some(value), None]),
+            BinaryAnnotation::ChangeCodeLength(value) => (4, [Some(value), None]),
+            BinaryAnnotation::ChangeFile(file) => (5, [Some(file.index()), None]),
+            BinaryAnnotation::ChangeLineOffset(value) => (6, [Some(signed(value)), None]),
+            BinaryAnnotation::ChangeLineEndDelta(value) => (7, [Some(value), None]),
+            BinaryAnnotation::ChangeRangeKind(value) => (8, [Some(value), None]),
+            BinaryAnnotation::ChangeColumnStart(value) => (9, [Some(value), None]),
+            BinaryAnnotation::ChangeColumnEndDelta(value) => (10, [Some(signed(value)), None]),
+            BinaryAnnotation::ChangeCodeLengthAndCodeOffset {
+                length,
+                code_offset,
+            } => (12, [Some(length), Some(code_offset)]),
+            BinaryAnnotation::ChangeColumnEnd(value) => (13, [Some(value), None]),
+        }
+    }
+}
+
+/// Size of an annotation operand in compressed form.
+fn compressed_size(value: u32) -> usize {
+    match value {
+        0..=0x7F => 1,
+        0x80..=0x3FFF => 2,
+        _ => 4,
+    }
+}
+
+fn write_compressed<Reloc, W: RecordWrite<Reloc>>(
+    value: u32,
+    writer: &mut W,
+) -> Result<(), Error<W::Error>> {
+    let bytes = value.to_be_bytes();
+    match value {
+        0..=0x7F => wu(writer.write(&bytes[3..])),
+        0x80..=0x3FFF => wu(writer.write(&[bytes[2] | 0x80, bytes[3]])),
+        0x4000..=0x1FFF_FFFF => wu(writer.write(&[bytes[0] | 0xC0, bytes[1], bytes[2], bytes[3]])),
+        _ => Err(Error::AnnotationOverflow(value)),
+    }
+}
+
+impl<Reloc> Writable<Reloc> for BinaryAnnotation {
+    fn write<W: RecordWrite<Reloc>>(&self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        let (opcode, operands) = self.opcode_and_operands();
+        write_compressed(opcode, writer)?;
+        for operand in operands.iter().flatten() {
+            write_compressed(*operand, writer)?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        let (opcode, operands) = self.opcode_and_operands();
+        compressed_size(opcode)
+            + operands
+                .iter()
+                .flatten()
+                .copied()
+                .map(compressed_size)
+                .sum::<usize>()
+    }
+}
+
+record! {
+    /// Inlined call site.
+    ///
+    /// Must be closed by a `Symbol::InlineSiteEnd`. The offsets of the enclosing scope and the
+    /// closing symbol are filled in by `Codeview::write`.
+    [InlineSite = 0x114D]
+    ///
+    (zero(parent)): u32,
+    ///
+    (zero(end)): u32,
+    /// Leaf index of the inlined function. Point to `Leaf::FuncId`.
+    inlinee: LeafId,
+    /// Code ranges and source lines of the inlined code.
+    annotations: Vec<BinaryAnnotation>,
+}
+
+/// Source language (`CV_CFL_LANG`).
+#[derive(Debug, Clone, Copy)]
+pub enum SourceLanguage {
+    C = 0x00,
+    Cpp = 0x01,
+    Fortran = 0x02,
+    Masm = 0x03,
+    Pascal = 0x04,
+    Basic = 0x05,
+    Cobol = 0x06,
+    Link = 0x07,
+    Cvtres = 0x08,
+    Cvtpgd = 0x09,
+    CSharp = 0x0A,
+    VisualBasic = 0x0B,
+    ILAsm = 0x0C,
+    Java = 0x0D,
+    JScript = 0x0E,
+    Msil = 0x0F,
+    Hlsl = 0x10,
+    ObjC = 0x11,
+    ObjCpp = 0x12,
+    Swift = 0x13,
+    AliasObj = 0x14,
+    Rust = 0x15,
+    Go = 0x16,
+}
+
+writable_enum!(SourceLanguage, u8);
+
+record! {
+    /// Compiler information.
+    [Compile3 = 0x113C]
+    /// Source language.
+    language: SourceLanguage,
+    /// Compile flags, starting from `fEC`.
+    flags: u16,
+    ///
+    (zero(padding)): u8,
+    /// Target processor (`CV_CPU_TYPE_e`).
+    machine: u16,
+    /// Front end version, as major, minor, build and QFE numbers.
+    frontend_version: [u16; 4],
+    /// Back end version, as major, minor, build and QFE numbers.
+    backend_version: [u16; 4],
+    /// Compiler version string.
+    version: String,
+}
+
+impl Compile3 {
+    /// Create a new `Compile3` instance for Rust code built by rustc of the given version.
+    pub fn rust(machine: u16, rustc_version: [u16; 3], version: String) -> Compile3 {
+        let [major, minor, patch] = rustc_version;
+        Compile3 {
+            language: SourceLanguage::Rust,
+            flags: 0,
+            machine,
+            frontend_version: [major, minor, patch, 0],
+            backend_version: [0; 4],
+            version,
+        }
+    }
+}
+
+record! {
+    /// Named constant.
+    [Constant = 0x1107, legacy = 0x1002]
+    /// Leaf index of the constant type.
+    constant_type: LeafId,
+    /// Value of the constant.
+    value: Numeric,
+    /// Name of the constant.
+    name: String,
+}
+
 all_records! {
     /// A symbol record.
     #[derive(Debug, Clone)]
     pub enum Symbol<Reloc> {
         BuildInfo,
+        End,
+        GlobalProc32<Reloc>,
+        LocalProc32<Reloc>,
+        Block32<Reloc>,
+        Label32<Reloc>,
+        Thunk32<Reloc>,
+        Trampoline<Reloc>,
+        CallSiteInfo<Reloc>,
+        HeapAllocSite<Reloc>,
+        Callees,
+        Callers,
+        FrameCookie,
+        SepCode<Reloc>,
+        EnvBlock,
+        Annotation<Reloc>,
+        Section,
+        CoffGroup,
+        Export,
+        Public32,
+        ProcRef,
+        DataRef,
+        LocalProcRef,
+        Register,
+        ManyReg,
+        ManyReg2,
+        RegRel32,
+        Local,
+        DefRangeRegister<Reloc>,
+        DefRangeRegisterRel<Reloc>,
+        InlineSite,
+        InlineSiteEnd,
+        Compile3,
+        Constant,
+    }
+}
+
+/// Whether the symbol opens a scope that is closed by `Symbol::End` or `Symbol::InlineSiteEnd`.
+pub(crate) fn is_scope_begin<Reloc>(symbol: &Symbol<Reloc>) -> bool {
+    matches!(
+        symbol,
+        Symbol::GlobalProc32(_)
+            | Symbol::LocalProc32(_)
+            | Symbol::Block32(_)
+            | Symbol::Thunk32(_)
+            | Symbol::SepCode(_)
+            | Symbol::InlineSite(_)
+    )
+}
+
+/// Whether the symbol closes a scope.
+pub(crate) fn is_scope_end<Reloc>(symbol: &Symbol<Reloc>) -> bool {
+    matches!(symbol, Symbol::End(_) | Symbol::InlineSiteEnd(_))
+}
+
+/// Functions of a `Callees` or `Callers` symbol, and their invocation counts.
+pub(crate) fn invocation_counts<Reloc>(symbol: &Symbol<Reloc>) -> Option<(&[LeafId], &[u32])> {
+    match symbol {
+        Symbol::Callees(Callees {
+            functions,
+            invocations,
+        })
+        | Symbol::Callers(Callers {
+            functions,
+            invocations,
+        }) => Some((functions, invocations)),
+        _ => None,
+    }
+}
+
+/// Whether `end` is the kind of symbol that closes the scope opened by `begin`.
+///
+/// `InlineSite` is closed by `InlineSiteEnd`, and all other scopes by `End`.
+pub(crate) fn closes_scope<Reloc>(begin: &Symbol<Reloc>, end: &Symbol<Reloc>) -> bool {
+    matches!(begin, Symbol::InlineSite(_)) == matches!(end, Symbol::InlineSiteEnd(_))
+}
+
+/// Leaf indices referenced by a symbol, with the kind of leaf each must point to.
+pub(crate) fn references<Reloc>(symbol: &Symbol<Reloc>) -> Vec<(LeafId, LeafKind)> {
+    match symbol {
+        Symbol::BuildInfo(symbol) => vec![(symbol.leaf, LeafKind::Id)],
+        Symbol::GlobalProc32(symbol) => vec![(symbol.function_type, LeafKind::Type)],
+        Symbol::LocalProc32(symbol) => vec![(symbol.function_type, LeafKind::Type)],
+        Symbol::CallSiteInfo(symbol) => vec![(symbol.function_type, LeafKind::Type)],
+        Symbol::HeapAllocSite(symbol) => vec![(symbol.function_type, LeafKind::Type)],
+        Symbol::Callees(Callees { functions, .. }) | Symbol::Callers(Callers { functions, .. }) => {
+            functions.iter().map(|id| (*id, LeafKind::Id)).collect()
+        }
+        Symbol::Register(symbol) => vec![(symbol.variable_type, LeafKind::Type)],
+        Symbol::ManyReg(symbol) => vec![(symbol.variable_type, LeafKind::Type)],
+        Symbol::ManyReg2(symbol) => vec![(symbol.variable_type, LeafKind::Type)],
+        Symbol::RegRel32(symbol) => vec![(symbol.variable_type, LeafKind::Type)],
+        Symbol::Local(symbol) => vec![(symbol.variable_type, LeafKind::Type)],
+        Symbol::InlineSite(symbol) => vec![(symbol.inlinee, LeafKind::Id)],
+        Symbol::Constant(symbol) => vec![(symbol.constant_type, LeafKind::Type)],
+        _ => vec![],
     }
 }
 
@@ -40,5 +934,356 @@ mod test {
             }),
             &[6, 0, 0x4C, 0x11, 0x77, 0x66, 0x55, 0x44],
         );
+
+        test_symbol(Symbol::End(End {}), &[2, 0, 0x06, 0x00]);
+
+        test_symbol(
+            Symbol::GlobalProc32(GlobalProc32 {
+                length: 0x10,
+                debug_start: 4,
+                debug_end: 0x0F,
+                function_type: LeafId(0x1001),
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                flags: 0x01,
+                name: "f".to_string(),
+            }),
+            &[
+                39, 0, 0x10, 0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0, 0, 0, 4, 0, 0, 0,
+                0x0F, 0, 0, 0, 0x01, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, b'f', 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::Block32(Block32 {
+                length: 0x10,
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                name: "b".to_string(),
+            }),
+            &[
+                22, 0, 0x03, 0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0, b'b', 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::Label32(Label32 {
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                flags: 0x08,
+                name: "l".to_string(),
+            }),
+            &[11, 0, 0x05, 0x11, 0, 0, 0, 0, 0, 0, 0x08, b'l', 0],
+        );
+
+        test_symbol(
+            Symbol::Thunk32(Thunk32 {
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                length: 5,
+                ordinal: ThunkOrdinal::ThisAdjustor {
+                    delta: 8,
+                    target: "f".to_string(),
+                },
+                name: "t".to_string(),
+            }),
+            &[
+                29, 0, 0x02, 0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 1,
+                b't', 0, 8, 0, b'f', 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::Trampoline(Trampoline {
+                kind: TrampolineKind::BranchIsland,
+                size: 12,
+                thunk_offset: SecRel(()),
+                target_offset: SecRel(()),
+                thunk_section: SectionIndex(()),
+                target_section: SectionIndex(()),
+            }),
+            &[
+                18, 0, 0x2C, 0x11, 1, 0, 12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::CallSiteInfo(CallSiteInfo {
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                function_type: LeafId(0x1234),
+            }),
+            &[14, 0, 0x39, 0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0x34, 0x12, 0, 0],
+        );
+
+        test_symbol(
+            Symbol::HeapAllocSite(HeapAllocSite {
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                instruction_length: 5,
+                function_type: LeafId(0x1234),
+            }),
+            &[14, 0, 0x5E, 0x11, 0, 0, 0, 0, 0, 0, 5, 0, 0x34, 0x12, 0, 0],
+        );
+
+        test_symbol(
+            Symbol::Callees(Callees {
+                functions: vec![LeafId(0x1001), LeafId(0x1002)],
+                invocations: vec![3, 1],
+            }),
+            &[
+                22, 0, 0x5A, 0x11, 2, 0, 0, 0, 0x01, 0x10, 0, 0, 0x02, 0x10, 0, 0, 3, 0, 0, 0, 1,
+                0, 0, 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::Callers(Callers {
+                functions: vec![LeafId(0x1001)],
+                invocations: vec![],
+            }),
+            &[10, 0, 0x5B, 0x11, 1, 0, 0, 0, 0x01, 0x10, 0, 0],
+        );
+
+        test_symbol(
+            Symbol::FrameCookie(FrameCookie {
+                offset: -8,
+                register: 335,
+                cookie_type: FrameCookieType::XorSp,
+                flags: 0,
+            }),
+            &[10, 0, 0x3A, 0x11, 0xF8, 0xFF, 0xFF, 0xFF, 0x4F, 0x01, 1, 0],
+        );
+
+        test_symbol(
+            Symbol::SepCode(SepCode {
+                length: 0x20,
+                flags: 2,
+                offset: SecRel(()),
+                parent_offset: SecRel(()),
+                section: SectionIndex(()),
+                parent_section: SectionIndex(()),
+            }),
+            &[
+                30, 0, 0x32, 0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0x20, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::EnvBlock(EnvBlock {
+                pairs: vec![("cl".to_string(), "a".into())],
+            }),
+            &[9, 0, 0x3D, 0x11, 0, b'c', b'l', 0, b'a', 0, 0],
+        );
+
+        test_symbol(
+            Symbol::Annotation(Annotation {
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                strings: vec!["a".to_string(), "bc".to_string()],
+            }),
+            &[
+                15, 0, 0x19, 0x10, 0, 0, 0, 0, 0, 0, 2, 0, b'a', 0, b'b', b'c', 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::Section(Section {
+                section: 1,
+                alignment: 12,
+                rva: 0x1000,
+                size: 0x234,
+                characteristics: 0x6000_0020,
+                name: ".text".to_string(),
+            }),
+            &[
+                24, 0, 0x36, 0x11, 1, 0, 12, 0, 0, 0x10, 0, 0, 0x34, 0x02, 0, 0, 0x20, 0, 0, 0x60,
+                b'.', b't', b'e', b'x', b't', 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::CoffGroup(CoffGroup {
+                size: 0x10,
+                characteristics: 0x6000_0020,
+                offset: 0x20,
+                section: 1,
+                name: ".a".to_string(),
+            }),
+            &[
+                19, 0, 0x37, 0x11, 0x10, 0, 0, 0, 0x20, 0, 0, 0x60, 0x20, 0, 0, 0, 1, 0, b'.',
+                b'a', 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::Export(Export {
+                ordinal: 3,
+                flags: 0x02,
+                name: "f".to_string(),
+            }),
+            &[8, 0, 0x38, 0x11, 3, 0, 2, 0, b'f', 0],
+        );
+
+        test_symbol(
+            Symbol::Public32(Public32 {
+                flags: 2,
+                offset: 0x10,
+                section: 1,
+                name: "f".to_string(),
+            }),
+            &[14, 0, 0x0E, 0x11, 2, 0, 0, 0, 0x10, 0, 0, 0, 1, 0, b'f', 0],
+        );
+
+        test_symbol(
+            Symbol::LocalProcRef(LocalProcRef {
+                sum_name: 0,
+                symbol_offset: 0x44,
+                module: 1,
+                name: "f".to_string(),
+            }),
+            &[14, 0, 0x27, 0x11, 0, 0, 0, 0, 0x44, 0, 0, 0, 1, 0, b'f', 0],
+        );
+
+        test_symbol(
+            Symbol::Register(Register {
+                variable_type: LeafId(0x74),
+                register: 17,
+                name: "x".to_string(),
+            }),
+            &[10, 0, 0x06, 0x11, 0x74, 0, 0, 0, 17, 0, b'x', 0],
+        );
+
+        test_symbol(
+            Symbol::ManyReg(ManyReg {
+                variable_type: LeafId(0x78),
+                registers: vec![19, 17],
+                name: "x".to_string(),
+            }),
+            &[11, 0, 0x0A, 0x11, 0x78, 0, 0, 0, 2, 19, 17, b'x', 0],
+        );
+
+        test_symbol(
+            Symbol::ManyReg2(ManyReg2 {
+                variable_type: LeafId(0x78),
+                registers: vec![331, 328],
+                name: "x".to_string(),
+            }),
+            &[
+                14, 0, 0x17, 0x11, 0x78, 0, 0, 0, 2, 0, 0x4B, 0x01, 0x48, 0x01, b'x', 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::RegRel32(RegRel32 {
+                offset: -0x10,
+                variable_type: LeafId(0x74),
+                register: 334,
+                name: "x".to_string(),
+            }),
+            &[
+                14, 0, 0x11, 0x11, 0xF0, 0xFF, 0xFF, 0xFF, 0x74, 0, 0, 0, 0x4E, 0x01, b'x', 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::Local(Local {
+                variable_type: LeafId(0x74),
+                flags: 1,
+                name: "x".to_string(),
+            }),
+            &[10, 0, 0x3E, 0x11, 0x74, 0, 0, 0, 1, 0, b'x', 0],
+        );
+
+        test_symbol(
+            Symbol::DefRangeRegister(DefRangeRegister {
+                register: 328,
+                may_have_no_name: 0,
+                range: AddressRange {
+                    offset: SecRel(()),
+                    section: SectionIndex(()),
+                    length: 0x10,
+                },
+                gaps: vec![AddressGap {
+                    offset: 4,
+                    length: 2,
+                }],
+            }),
+            &[
+                18, 0, 0x41, 0x11, 0x48, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0, 4, 0, 2, 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::DefRangeRegisterRel(DefRangeRegisterRel {
+                base_register: 335,
+                flags: 0,
+                base_offset: -8,
+                range: AddressRange {
+                    offset: SecRel(()),
+                    section: SectionIndex(()),
+                    length: 0x20,
+                },
+                gaps: vec![],
+            }),
+            &[
+                18, 0, 0x45, 0x11, 0x4F, 0x01, 0, 0, 0xF8, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0,
+                0x20, 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::InlineSite(InlineSite {
+                inlinee: LeafId(0x1001),
+                annotations: vec![
+                    BinaryAnnotation::ChangeCodeOffset(0x10),
+                    BinaryAnnotation::ChangeCodeLength(0x200),
+                    BinaryAnnotation::ChangeLineOffset(-3),
+                    BinaryAnnotation::CodeOffset(0x12345),
+                ],
+            }),
+            &[
+                26, 0, 0x4D, 0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x10, 0, 0, 3, 0x10, 4, 0x82, 0,
+                6, 7, 1, 0xC0, 0x01, 0x23, 0x45,
+            ],
+        );
+
+        test_symbol(Symbol::InlineSiteEnd(InlineSiteEnd {}), &[2, 0, 0x4E, 0x11]);
+
+        let mut sink = SectionSink::<()>::new();
+        let site = Symbol::InlineSite(InlineSite {
+            inlinee: LeafId(0x1001),
+            annotations: vec![BinaryAnnotation::CodeOffset(0x2000_0000)],
+        });
+        let error = write(&site, &mut sink).unwrap_err();
+        assert!(matches!(
+            error.root(),
+            Error::AnnotationOverflow(0x2000_0000)
+        ));
+
+        let site = Symbol::InlineSite(InlineSite {
+            inlinee: LeafId(0x1001),
+            annotations: vec![BinaryAnnotation::ChangeLineOffset(i32::MIN)],
+        });
+        let error = write(&site, &mut sink).unwrap_err();
+        assert!(matches!(error.root(), Error::AnnotationOverflow(u32::MAX)));
+
+        test_symbol(
+            Symbol::Compile3(Compile3::rust(0xD0, [1, 70, 2], "r".to_string())),
+            &[
+                26, 0, 0x3C, 0x11, 0x15, 0, 0, 0, 0xD0, 0, 1, 0, 70, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, b'r', 0,
+            ],
+        );
+
+        test_symbol(
+            Symbol::Constant(Constant {
+                constant_type: LeafId(0x74),
+                value: Numeric::Signed(-2),
+                name: "c".to_string(),
+            }),
+            &[11, 0, 0x07, 0x11, 0x74, 0, 0, 0, 0x00, 0x80, 0xFE, b'c', 0],
+        );
     }
 }
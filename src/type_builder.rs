@@ -0,0 +1,582 @@
+use crate::codeview::Codeview;
+use crate::error::Error;
+pub use crate::leaf::Primitive;
+use crate::leaf::{self, Leaf, LeafId, Numeric};
+use crate::symbol;
+use std::collections::HashMap;
+use std::convert::*;
+
+/// A language-neutral description of a type.
+#[derive(Debug, Clone)]
+pub enum Type {
+    /// Primitive type.
+    Primitive(Primitive),
+
+    /// Pointer to a type.
+    Pointer(Box<Type>),
+
+    /// Structure definition.
+    Struct(Struct),
+
+    /// Reference to a structure by name, such as a structure that is being defined.
+    StructRef(String),
+
+    /// Enumeration definition.
+    Enum(Enum),
+
+    /// Array of a type with the given number of elements.
+    Array(Box<Type>, u64),
+
+    /// Function signature.
+    Function(Function),
+
+    /// Rust enum definition.
+    RustEnum(RustEnum),
+}
+
+/// A structure definition.
+#[derive(Debug, Clone)]
+pub struct Struct {
+    /// Name of the structure.
+    pub name: String,
+
+    /// Size of the structure in bytes.
+    pub size: u64,
+
+    /// Fields of the structure.
+    pub fields: Vec<StructField>,
+}
+
+/// A field of a structure.
+#[derive(Debug, Clone)]
+pub struct StructField {
+    /// Name of the field.
+    pub name: String,
+
+    /// Type of the field.
+    pub field_type: Type,
+
+    /// Offset of the field in bytes.
+    pub offset: u64,
+}
+
+/// An enumeration definition.
+#[derive(Debug, Clone)]
+pub struct Enum {
+    /// Name of the enumeration.
+    pub name: String,
+
+    /// Underlying integer type.
+    pub underlying_type: Primitive,
+
+    /// Variants of the enumeration.
+    pub variants: Vec<EnumVariant>,
+}
+
+/// A variant of an enumeration.
+#[derive(Debug, Clone)]
+pub struct EnumVariant {
+    /// Name of the variant.
+    pub name: String,
+
+    /// Value of the variant.
+    pub value: Numeric,
+}
+
+/// A function signature.
+#[derive(Debug, Clone)]
+pub struct Function {
+    /// Return type.
+    pub return_type: Box<Type>,
+
+    /// Parameter types.
+    pub parameters: Vec<Type>,
+}
+
+/// A Rust enum definition.
+///
+/// The enum is described as a union of variants following the `enum2$<...>` conventions of
+/// rustc, which debugger visualizers understand.
+#[derive(Debug, Clone)]
+pub struct RustEnum {
+    /// Fully qualified name of the enum, such as `core::option::Option<u32>`.
+    pub name: String,
+
+    /// Size of the enum in bytes.
+    pub size: u64,
+
+    /// Type of the tag.
+    pub tag_type: Primitive,
+
+    /// Offset of the tag in bytes.
+    pub tag_offset: u64,
+
+    /// Variants of the enum.
+    pub variants: Vec<RustEnumVariant>,
+}
+
+/// A variant of a Rust enum.
+#[derive(Debug, Clone)]
+pub struct RustEnumVariant {
+    /// Name of the variant, such as `Some`.
+    pub name: String,
+
+    /// Tag values of the variant, or `None` for the variant that has no tag value in a
+    /// niche-filling layout.
+    pub discriminant: Option<Discriminant>,
+
+    /// Fields of the variant. Offsets are relative to the start of the enum.
+    pub fields: Vec<StructField>,
+}
+
+/// Tag values of a Rust enum variant.
+#[derive(Debug, Clone)]
+pub enum Discriminant {
+    /// A single tag value.
+    Exact(Numeric),
+
+    /// An inclusive range of tag values.
+    Range(Numeric, Numeric),
+}
+
+/// Converts type descriptions into leaf records.
+///
+/// Structures and enumerations are identified by name, and each is only added once.
+/// References to structures by name are resolved to forward declarations, which breaks
+/// recursive type cycles.
+///
+/// Rust enums also produce constants describing their variants, which should be added as
+/// symbols. See `TypeBuilder::take_constants`.
+#[derive(Debug)]
+pub struct TypeBuilder {
+    pointer_size: u8,
+    forward_refs: HashMap<String, LeafId>,
+    structs: HashMap<String, (LeafId, u64)>,
+    enums: HashMap<String, LeafId>,
+    unions: HashMap<String, LeafId>,
+    pointers: HashMap<LeafId, LeafId>,
+    constants: Vec<symbol::Constant>,
+}
+
+/// Member attributes for public members.
+const PUBLIC: u16 = 3;
+
+/// Type property for forward declarations.
+const FORWARD_REF: u16 = 0x80;
+
+impl TypeBuilder {
+    /// Create a new `TypeBuilder` instance for a target with the given pointer size in bytes.
+    pub fn new(pointer_size: u8) -> TypeBuilder {
+        TypeBuilder {
+            pointer_size,
+            forward_refs: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            unions: HashMap::new(),
+            pointers: HashMap::new(),
+            constants: vec![],
+        }
+    }
+
+    /// Take the constants produced since the last call.
+    pub fn take_constants(&mut self) -> Vec<symbol::Constant> {
+        std::mem::take(&mut self.constants)
+    }
+
+    /// Add leaves describing a type and the types it depends on.
+    pub fn build<Reloc>(
+        &mut self,
+        codeview: &mut Codeview<Reloc>,
+        ty: &Type,
+    ) -> Result<LeafId, Error> {
+        match ty {
+            Type::Primitive(primitive) => Ok(LeafId::primitive(*primitive)),
+            Type::Pointer(pointee) => {
+                // Only pointers to primitives have a primitive index, not pointers to those.
+                if let Type::Primitive(primitive) = **pointee {
+                    return Ok(LeafId::primitive_pointer(primitive, self.pointer_size));
+                }
+                let pointee = self.build(codeview, pointee)?;
+                if let Some(id) = self.pointers.get(&pointee) {
+                    return Ok(*id);
+                }
+                let id = codeview.add_leaf(Leaf::Pointer(leaf::Pointer::new(
+                    pointee,
+                    self.pointer_size,
+                )))?;
+                self.pointers.insert(pointee, id);
+                Ok(id)
+            }
+            Type::Struct(s) => {
+                if let Some((id, _)) = self.structs.get(&s.name) {
+                    return Ok(*id);
+                }
+                let mut fields = vec![];
+                for field in &s.fields {
+                    fields.push(leaf::Field::Member(leaf::Member {
+                        attributes: PUBLIC,
+                        member_type: self.build(codeview, &field.field_type)?,
+                        offset: field.offset.into(),
+                        name: field.name.clone(),
+                    }));
+                }
+                let field_list = codeview.add_leaf(Leaf::FieldList(leaf::FieldList { fields }))?;
+                let id = codeview.add_leaf(Leaf::Structure(leaf::Structure {
+                    member_count: s.fields.len().try_into()?,
+                    properties: 0,
+                    field_list,
+                    derived_from: LeafId::null(),
+                    vtable_shape: LeafId::null(),
+                    size: s.size.into(),
+                    name: s.name.clone(),
+                    unique_name: None,
+                }))?;
+                self.structs.insert(s.name.clone(), (id, s.size));
+                Ok(id)
+            }
+            Type::StructRef(name) => {
+                if let Some(id) = self.forward_refs.get(name) {
+                    return Ok(*id);
+                }
+                let id = codeview.add_leaf(Leaf::Structure(leaf::Structure {
+                    member_count: 0,
+                    properties: FORWARD_REF,
+                    field_list: LeafId::null(),
+                    derived_from: LeafId::null(),
+                    vtable_shape: LeafId::null(),
+                    size: Numeric::Unsigned(0),
+                    name: name.clone(),
+                    unique_name: None,
+                }))?;
+                self.forward_refs.insert(name.clone(), id);
+                Ok(id)
+            }
+            Type::Enum(e) => {
+                if let Some(id) = self.enums.get(&e.name) {
+                    return Ok(*id);
+                }
+                let fields = e
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        leaf::Field::Enumerate(leaf::Enumerate {
+                            attributes: PUBLIC,
+                            value: variant.value,
+                            name: variant.name.clone(),
+                        })
+                    })
+                    .collect();
+                let field_list = codeview.add_leaf(Leaf::FieldList(leaf::FieldList { fields }))?;
+                let id = codeview.add_leaf(Leaf::Enum(leaf::Enum {
+                    member_count: e.variants.len().try_into()?,
+                    properties: 0,
+                    underlying_type: LeafId::primitive(e.underlying_type),
+                    field_list,
+                    name: e.name.clone(),
+                    unique_name: None,
+                }))?;
+                self.enums.insert(e.name.clone(), id);
+                Ok(id)
+            }
+            Type::Array(element, _) => {
+                let size = self.size_of(ty)?;
+                let element_type = self.build(codeview, element)?;
+                let index_type = if self.pointer_size == 8 {
+                    Primitive::U64
+                } else {
+                    Primitive::U32
+                };
+                Ok(codeview.add_leaf(Leaf::Array(leaf::Array {
+                    element_type,
+                    index_type: LeafId::primitive(index_type),
+                    size: size.into(),
+                    name: "".to_string(),
+                }))?)
+            }
+            Type::Function(function) => {
+                let return_type = self.build(codeview, &function.return_type)?;
+                let mut arguments = vec![];
+                for parameter in &function.parameters {
+                    arguments.push(self.build(codeview, parameter)?);
+                }
+                let parameter_count = arguments.len().try_into()?;
+                let argument_list =
+                    codeview.add_leaf(Leaf::ArgList(leaf::ArgList { arguments }))?;
+                Ok(codeview.add_leaf(Leaf::Procedure(leaf::Procedure {
+                    return_type,
+                    calling_convention: 0,
+                    function_attributes: 0,
+                    parameter_count,
+                    argument_list,
+                }))?)
+            }
+            Type::RustEnum(e) => self.build_rust_enum(codeview, e),
+        }
+    }
+
+    fn build_rust_enum<Reloc>(
+        &mut self,
+        codeview: &mut Codeview<Reloc>,
+        e: &RustEnum,
+    ) -> Result<LeafId, Error> {
+        // rustc separates consecutive closing angle brackets with a space.
+        let close = if e.name.ends_with('>') { " >" } else { ">" };
+        let union_name = format!("enum2$<{}{}", e.name, close);
+        if let Some(id) = self.unions.get(&union_name) {
+            return Ok(*id);
+        }
+
+        let names_type = self.build(
+            codeview,
+            &Type::Enum(Enum {
+                name: format!("{}::VariantNames", union_name),
+                underlying_type: Primitive::I32,
+                variants: e
+                    .variants
+                    .iter()
+                    .enumerate()
+                    .map(|(i, variant)| EnumVariant {
+                        name: variant.name.clone(),
+                        value: Numeric::Unsigned(i as u64),
+                    })
+                    .collect(),
+            }),
+        )?;
+        let tag_type = LeafId::primitive(e.tag_type);
+
+        let mut union_fields = vec![];
+        for (i, variant) in e.variants.iter().enumerate() {
+            let value_type = self.build(
+                codeview,
+                &Type::Struct(Struct {
+                    name: format!("{}::{}", union_name, variant.name),
+                    size: e.size,
+                    fields: variant.fields.clone(),
+                }),
+            )?;
+
+            let variant_name = format!("{}::Variant{}", union_name, i);
+            let mut fields = vec![leaf::Field::Member(leaf::Member {
+                attributes: PUBLIC,
+                member_type: value_type,
+                offset: Numeric::Unsigned(0),
+                name: "value".to_string(),
+            })];
+            let mut constants = vec![("NAME", names_type, Numeric::Unsigned(i as u64))];
+            match &variant.discriminant {
+                Some(Discriminant::Exact(value)) => {
+                    constants.push(("DISCR_EXACT", tag_type, *value));
+                }
+                Some(Discriminant::Range(begin, end)) => {
+                    constants.push(("DISCR_BEGIN", tag_type, *begin));
+                    constants.push(("DISCR_END", tag_type, *end));
+                }
+                None => (),
+            }
+            for (name, constant_type, value) in constants {
+                fields.push(leaf::Field::StaticMember(leaf::StaticMember {
+                    attributes: PUBLIC,
+                    member_type: constant_type,
+                    name: name.to_string(),
+                }));
+                self.constants.push(symbol::Constant {
+                    constant_type,
+                    value,
+                    name: format!("{}::{}", variant_name, name),
+                });
+            }
+
+            let member_count = fields.len().try_into()?;
+            let field_list = codeview.add_leaf(Leaf::FieldList(leaf::FieldList { fields }))?;
+            let variant_type = codeview.add_leaf(Leaf::Structure(leaf::Structure {
+                member_count,
+                properties: 0,
+                field_list,
+                derived_from: LeafId::null(),
+                vtable_shape: LeafId::null(),
+                size: e.size.into(),
+                name: variant_name,
+                unique_name: None,
+            }))?;
+            union_fields.push(leaf::Field::Member(leaf::Member {
+                attributes: PUBLIC,
+                member_type: variant_type,
+                offset: Numeric::Unsigned(0),
+                name: format!("variant{}", i),
+            }));
+        }
+
+        union_fields.push(leaf::Field::Member(leaf::Member {
+            attributes: PUBLIC,
+            member_type: tag_type,
+            offset: e.tag_offset.into(),
+            name: "tag".to_string(),
+        }));
+        let member_count = union_fields.len().try_into()?;
+        let field_list = codeview.add_leaf(Leaf::FieldList(leaf::FieldList {
+            fields: union_fields,
+        }))?;
+        let id = codeview.add_leaf(Leaf::Union(leaf::Union {
+            member_count,
+            properties: 0,
+            field_list,
+            size: e.size.into(),
+            name: union_name.clone(),
+            unique_name: None,
+        }))?;
+        self.unions.insert(union_name, id);
+        Ok(id)
+    }
+
+    fn size_of(&self, ty: &Type) -> Result<u64, Error> {
+        match ty {
+            Type::Primitive(primitive) => primitive
+                .size()
+                .ok_or_else(|| Error::UnknownSize(format!("{:?}", primitive))),
+            Type::Pointer(_) => Ok(self.pointer_size.into()),
+            Type::Struct(s) => Ok(s.size),
+            Type::StructRef(name) => self
+                .structs
+                .get(name)
+                .map(|(_, size)| *size)
+                .ok_or_else(|| Error::UnknownSize(name.clone())),
+            Type::Enum(e) => self.size_of(&Type::Primitive(e.underlying_type)),
+            Type::Array(element, count) => self
+                .size_of(element)?
+                .checked_mul(*count)
+                .ok_or(Error::ArraySizeOverflow(*count)),
+            Type::Function(_) => Err(Error::UnknownSize("function".to_string())),
+            Type::RustEnum(e) => Ok(e.size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recursive_struct() {
+        let mut codeview = Codeview::<()>::new();
+        let mut builder = TypeBuilder::new(8);
+        let node = Type::Struct(Struct {
+            name: "Node".to_string(),
+            size: 16,
+            fields: vec![
+                StructField {
+                    name: "value".to_string(),
+                    field_type: Type::Primitive(Primitive::I32),
+                    offset: 0,
+                },
+                StructField {
+                    name: "next".to_string(),
+                    field_type: Type::Pointer(Box::new(Type::StructRef("Node".to_string()))),
+                    offset: 8,
+                },
+            ],
+        });
+        let id = builder.build(&mut codeview, &node).unwrap();
+        assert_eq!(id.0, 0x1003);
+        assert_eq!(builder.build(&mut codeview, &node).unwrap().0, 0x1003);
+
+        match codeview.leaf(LeafId(0x1000)) {
+            Some(Leaf::Structure(s)) => assert_eq!(s.properties, FORWARD_REF),
+            leaf => panic!("{:?}", leaf),
+        }
+        match codeview.leaf(LeafId(0x1001)) {
+            Some(Leaf::Pointer(p)) => assert_eq!(p.pointee.0, 0x1000),
+            leaf => panic!("{:?}", leaf),
+        }
+        match codeview.leaf(LeafId(0x1003)) {
+            Some(Leaf::Structure(s)) => {
+                assert_eq!(s.member_count, 2);
+                assert_eq!(s.field_list.0, 0x1002);
+            }
+            leaf => panic!("{:?}", leaf),
+        }
+
+        let array = Type::Array(Box::new(Type::StructRef("Node".to_string())), 3);
+        let id = builder.build(&mut codeview, &array).unwrap();
+        match codeview.leaf(id) {
+            Some(Leaf::Array(a)) => assert_eq!(a.size, Numeric::Unsigned(48)),
+            leaf => panic!("{:?}", leaf),
+        }
+
+        let pointer = Type::Pointer(Box::new(Type::Primitive(Primitive::U8)));
+        assert_eq!(builder.build(&mut codeview, &pointer).unwrap().0, 0x0669);
+        let pointer = Type::Pointer(Box::new(pointer));
+        let id = builder.build(&mut codeview, &pointer).unwrap();
+        match codeview.leaf(id) {
+            Some(Leaf::Pointer(p)) => assert_eq!(p.pointee.0, 0x0669),
+            leaf => panic!("{:?}", leaf),
+        }
+
+        let row = Type::Array(Box::new(Type::Primitive(Primitive::U64)), 1 << 40);
+        let grid = Type::Array(Box::new(row), 1 << 40);
+        assert!(matches!(
+            builder.build(&mut codeview, &grid),
+            Err(Error::ArraySizeOverflow(0x100_0000_0000))
+        ));
+        codeview.validate().unwrap();
+    }
+
+    #[test]
+    fn rust_enum() {
+        let mut codeview = Codeview::<()>::new();
+        let mut builder = TypeBuilder::new(8);
+        let option = Type::RustEnum(RustEnum {
+            name: "core::option::Option<u32>".to_string(),
+            size: 8,
+            tag_type: Primitive::U32,
+            tag_offset: 0,
+            variants: vec![
+                RustEnumVariant {
+                    name: "None".to_string(),
+                    discriminant: Some(Discriminant::Exact(Numeric::Unsigned(0))),
+                    fields: vec![],
+                },
+                RustEnumVariant {
+                    name: "Some".to_string(),
+                    discriminant: Some(Discriminant::Exact(Numeric::Unsigned(1))),
+                    fields: vec![StructField {
+                        name: "__0".to_string(),
+                        field_type: Type::Primitive(Primitive::U32),
+                        offset: 4,
+                    }],
+                },
+            ],
+        });
+        let id = builder.build(&mut codeview, &option).unwrap();
+        match codeview.leaf(id) {
+            Some(Leaf::Union(u)) => {
+                assert_eq!(u.name, "enum2$<core::option::Option<u32> >");
+                assert_eq!(u.member_count, 3);
+                assert_eq!(u.size, Numeric::Unsigned(8));
+            }
+            leaf => panic!("{:?}", leaf),
+        }
+
+        let structures: Vec<_> = (0x1000..)
+            .map_while(|index| codeview.leaf(LeafId(index)))
+            .filter_map(|leaf| match leaf {
+                Leaf::Structure(s) => Some(s.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert!(structures.contains(&"enum2$<core::option::Option<u32> >::Some"));
+
+        let constants = builder.take_constants();
+        let names: Vec<_> = constants.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "enum2$<core::option::Option<u32> >::Variant0::NAME",
+                "enum2$<core::option::Option<u32> >::Variant0::DISCR_EXACT",
+                "enum2$<core::option::Option<u32> >::Variant1::NAME",
+                "enum2$<core::option::Option<u32> >::Variant1::DISCR_EXACT",
+            ]
+        );
+        assert_eq!(constants[3].value, Numeric::Unsigned(1));
+        assert!(builder.take_constants().is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::leaf::{self, Leaf, LeafId, LeafKind};
+use crate::subsection::Subsection;
+use crate::symbol::{self, Symbol};
+use std::fmt;
+use thiserror::Error;
+
+/// A record that refers to a leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Referrer {
+    /// A leaf with the given index.
+    Leaf(LeafId),
+
+    /// A symbol, given by the index of its subsection and its index within the subsection.
+    Symbol { subsection: usize, symbol: usize },
+}
+
+impl fmt::Display for Referrer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Referrer::Leaf(id) => write!(f, "Leaf {}", id),
+            Referrer::Symbol { subsection, symbol } => {
+                write!(f, "Symbol {} in subsection {}", symbol, subsection)
+            }
+        }
+    }
+}
+
+/// An inconsistency in CodeView information found by `Codeview::validate`.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("{referrer} refers to leaf {index}, which does not exist")]
+    DanglingIndex { referrer: Referrer, index: LeafId },
+
+    #[error("Leaf {referrer} refers to leaf {index}, which is not added before it")]
+    ForwardReference { referrer: LeafId, index: LeafId },
+
+    #[error("{referrer} refers to leaf {index}, which is not a {expected} leaf")]
+    KindMismatch {
+        referrer: Referrer,
+        index: LeafId,
+        expected: LeafKind,
+    },
+
+    #[error("Symbol {symbol} in subsection {subsection} has {invocations} invocation counts for {functions} functions")]
+    InvocationCount {
+        subsection: usize,
+        symbol: usize,
+        functions: usize,
+        invocations: usize,
+    },
+
+    #[error("Symbol {symbol} in subsection {subsection} closes a scope that is not open")]
+    UnmatchedScopeEnd { subsection: usize, symbol: usize },
+
+    #[error(
+        "Symbol {symbol} in subsection {subsection} can't close the scope opened by symbol {begin}"
+    )]
+    MismatchedScopeEnd {
+        subsection: usize,
+        symbol: usize,
+        begin: usize,
+    },
+
+    #[error("Symbol {symbol} in subsection {subsection} opens a scope that is not closed")]
+    UnclosedScope { subsection: usize, symbol: usize },
+}
+
+/// Leaves of a `Codeview` instance, and which leaf indices refer to them.
+pub(crate) struct Leaves<'a> {
+    /// Leaves added to the instance.
+    pub leafs: &'a [Leaf],
+
+    /// Leaf index of the first added leaf.
+    pub first_index: u32,
+
+    /// Whether leaf indices that are not primitive types refer to an external type server.
+    pub external: bool,
+}
+
+impl<'a> Leaves<'a> {
+    /// Check that a reference points to an existing leaf of the expected kind.
+    ///
+    /// References to external leaves can't be checked and are accepted.
+    fn check(
+        &self,
+        referrer: Referrer,
+        index: LeafId,
+        expected: LeafKind,
+    ) -> Result<(), ValidationError> {
+        if index == LeafId::null() {
+            return Ok(());
+        }
+
+        let leaf = if index.is_primitive() {
+            None
+        } else if self.external || index.0 < self.first_index {
+            return Ok(());
+        } else {
+            let leaf = self.leafs.get((index.0 - self.first_index) as usize);
+            Some(leaf.ok_or(ValidationError::DanglingIndex { referrer, index })?)
+        };
+
+        let kind = leaf.map_or(LeafKind::Type, leaf::kind);
+        if kind != expected {
+            return Err(ValidationError::KindMismatch {
+                referrer,
+                index,
+                expected,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the leaf index refers to an added leaf.
+    fn contains(&self, index: LeafId) -> bool {
+        !self.external
+            && index.0 >= self.first_index
+            && ((index.0 - self.first_index) as usize) < self.leafs.len()
+    }
+}
+
+/// Check that leaves only refer to earlier leaves of the expected kind.
+pub(crate) fn validate_leaves(leaves: &Leaves) -> Result<(), ValidationError> {
+    for (i, leaf) in leaves.leafs.iter().enumerate() {
+        let referrer = LeafId(leaves.first_index + i as u32);
+        for (index, expected) in leaf::references(leaf) {
+            if index >= referrer && leaves.contains(index) {
+                return Err(ValidationError::ForwardReference { referrer, index });
+            }
+            leaves.check(Referrer::Leaf(referrer), index, expected)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that symbols refer to existing leaves of the expected kind, and that scopes are matched.
+pub(crate) fn validate_subsections<Reloc>(
+    leaves: &Leaves,
+    subsections: &[Subsection<Reloc>],
+) -> Result<(), ValidationError> {
+    for (subsection, symbols) in subsections.iter().enumerate() {
+        let symbols = match symbols {
+            Subsection::Symbols(symbols) => symbols,
+            _ => continue,
+        };
+        validate_symbols(leaves, subsection, symbols)?;
+    }
+    Ok(())
+}
+
+fn validate_symbols<Reloc>(
+    leaves: &Leaves,
+    subsection: usize,
+    symbols: &[Symbol<Reloc>],
+) -> Result<(), ValidationError> {
+    let mut scopes = vec![];
+    for (i, symbol) in symbols.iter().enumerate() {
+        let referrer = Referrer::Symbol {
+            subsection,
+            symbol: i,
+        };
+        for (index, expected) in symbol::references(symbol) {
+            leaves.check(referrer, index, expected)?;
+        }
+
+        if let Some((functions, invocations)) = symbol::invocation_counts(symbol) {
+            if !invocations.is_empty() && invocations.len() != functions.len() {
+                return Err(ValidationError::InvocationCount {
+                    subsection,
+                    symbol: i,
+                    functions: functions.len(),
+                    invocations: invocations.len(),
+                });
+            }
+        }
+
+        if symbol::is_scope_end(symbol) {
+            let begin = scopes.pop().ok_or(ValidationError::UnmatchedScopeEnd {
+                subsection,
+                symbol: i,
+            })?;
+            if !symbol::closes_scope(&symbols[begin], symbol) {
+                return Err(ValidationError::MismatchedScopeEnd {
+                    subsection,
+                    symbol: i,
+                    begin,
+                });
+            }
+        }
+        if symbol::is_scope_begin(symbol) {
+            scopes.push(i);
+        }
+    }
+
+    if let Some(symbol) = scopes.pop() {
+        return Err(ValidationError::UnclosedScope { subsection, symbol });
+    }
+    Ok(())
+}
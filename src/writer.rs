@@ -0,0 +1,705 @@
+use crate::codeview::TypeOrigin;
+use crate::encoding::StringPolicy;
+use crate::error::{wu, Error};
+use crate::leaf::{self, Leaf, LeafId};
+use crate::section_write::{RelocKind, SectionWrite};
+use crate::string_table::{StringOffset, StringTable};
+use crate::struct_macro::{write_record, PolicyWriter, RecordWrite, Writable, WritableRecord};
+use crate::subsection::Subsection;
+use crate::symbol::{self, Symbol};
+use std::convert::*;
+use std::marker::PhantomData;
+
+/// Maximum size of a field list record before it is split.
+pub(crate) const MAX_FIELD_LIST_SIZE: usize = 0xFF00;
+
+/// Writes CodeView information to object sections as records are added.
+///
+/// Unlike `Codeview`, records are not kept after they are added, so leaves can't be looked up
+/// and nothing can be validated. Both sections are open until `finish` is called.
+///
+/// `Reloc` can be any type that carries relocation symbol information.
+pub struct CodeviewWriter<Reloc, W> {
+    types: TypeStream<Reloc, W>,
+    symbols: SymbolStream<Reloc, W>,
+    strings: StringTable,
+}
+
+impl<Reloc, W: SectionWrite<Reloc>> CodeviewWriter<Reloc, W> {
+    /// Create a new `CodeviewWriter` instance writing to `.debug$T` and `.debug$S` sections.
+    pub fn new(type_section: W, symbol_section: W) -> Result<Self, Error<W::Error>> {
+        CodeviewWriter::with_type_origin(TypeOrigin::Local, type_section, symbol_section)
+    }
+
+    /// Create a new `CodeviewWriter` instance whose types are in an external type server.
+    ///
+    /// See `Codeview::with_type_server`.
+    pub fn with_type_server(
+        type_server: leaf::TypeServer2,
+        type_section: W,
+        symbol_section: W,
+    ) -> Result<Self, Error<W::Error>> {
+        let origin = TypeOrigin::TypeServer(type_server);
+        CodeviewWriter::with_type_origin(origin, type_section, symbol_section)
+    }
+
+    /// Create a new `CodeviewWriter` instance that uses precompiled types from another object.
+    ///
+    /// See `Codeview::with_precomp`.
+    pub fn with_precomp(
+        precomp: leaf::Precomp,
+        type_section: W,
+        symbol_section: W,
+    ) -> Result<Self, Error<W::Error>> {
+        let origin = TypeOrigin::Precomp(precomp);
+        CodeviewWriter::with_type_origin(origin, type_section, symbol_section)
+    }
+
+    /// Create a new `CodeviewWriter` instance whose types are shared as precompiled types.
+    ///
+    /// `type_section` should write to `.debug$P`. See `Codeview::with_end_precomp`.
+    pub fn with_end_precomp(
+        signature: u32,
+        type_section: W,
+        symbol_section: W,
+    ) -> Result<Self, Error<W::Error>> {
+        let origin = TypeOrigin::EndPrecomp(leaf::EndPrecomp { signature });
+        CodeviewWriter::with_type_origin(origin, type_section, symbol_section)
+    }
+
+    fn with_type_origin(
+        origin: TypeOrigin,
+        type_section: W,
+        symbol_section: W,
+    ) -> Result<Self, Error<W::Error>> {
+        let policy = StringPolicy::default();
+        Ok(CodeviewWriter {
+            types: TypeStream::new(type_section, &origin, policy)?,
+            symbols: SymbolStream::new(symbol_section, policy)?,
+            strings: StringTable::default(),
+        })
+    }
+
+    /// Set how strings in records added afterwards are encoded.
+    ///
+    /// See `Codeview::set_string_policy`.
+    pub fn set_string_policy(&mut self, string_policy: StringPolicy) {
+        self.types.policy = string_policy;
+        self.symbols.policy = string_policy;
+    }
+
+    /// Write a leaf and return its leaf index.
+    ///
+    /// Large field lists are split, and errors are reported, as in `Codeview::add_leaf`.
+    pub fn add_leaf(&mut self, leaf: Leaf) -> Result<LeafId, Error<W::Error>> {
+        let types = &mut self.types;
+        add_leaf::<Reloc, _>(leaf, |leaf| types.write_leaf(&leaf))
+    }
+
+    /// Write a subsection.
+    pub fn add_subsection(&mut self, subsection: Subsection<Reloc>) -> Result<(), Error<W::Error>> {
+        self.symbols.add_subsection(&subsection)
+    }
+
+    /// Add a string to the string table subsection, which is written by `finish`.
+    ///
+    /// Adding the same string multiple times returns the same offset.
+    pub fn add_string(&mut self, string: &str) -> Result<StringOffset, Error<W::Error>> {
+        Ok(self.strings.add(string)?)
+    }
+
+    /// Write the end of both sections, and return the type section and symbol section writers.
+    pub fn finish(self) -> Result<(W, W), Error<W::Error>> {
+        let type_section = self.types.finish()?;
+        let symbol_section = self.symbols.finish(&self.strings)?;
+        Ok((type_section, symbol_section))
+    }
+}
+
+/// Add a leaf with the given function, splitting field lists that are too large for a single
+/// record into multiple records chained by `Field::Index`.
+///
+/// Returns the index of the leaf, or the record containing the first members of a field list.
+pub(crate) fn add_leaf<Reloc, E: std::error::Error + 'static>(
+    leaf: Leaf,
+    mut push_leaf: impl FnMut(Leaf) -> Result<LeafId, Error<E>>,
+) -> Result<LeafId, Error<E>> {
+    let field_list = match leaf {
+        Leaf::FieldList(field_list)
+            if Writable::<Reloc>::size(&field_list) > MAX_FIELD_LIST_SIZE =>
+        {
+            field_list
+        }
+        leaf => return push_leaf(leaf),
+    };
+
+    let index_size = Writable::<Reloc>::size(&leaf::Field::Index(leaf::Index {
+        continuation: LeafId::null(),
+    }));
+
+    let mut segments = vec![vec![]];
+    let mut size = 0;
+    for field in field_list.fields {
+        let field_size = Writable::<Reloc>::size(&field);
+        if size + field_size + index_size > MAX_FIELD_LIST_SIZE && size != 0 {
+            segments.push(vec![]);
+            size = 0;
+        }
+        size += field_size;
+        segments.last_mut().unwrap().push(field);
+    }
+
+    // Continuation records are added first so that each record refers to an earlier one.
+    let mut continuation = None;
+    for mut fields in segments.into_iter().rev() {
+        if let Some(continuation) = continuation {
+            fields.push(leaf::Field::Index(leaf::Index { continuation }));
+        }
+        continuation = Some(push_leaf(Leaf::FieldList(leaf::FieldList { fields }))?);
+    }
+    Ok(continuation.unwrap())
+}
+
+/// Leaf index of the leaf at the given position, counting from 0.
+pub(crate) fn leaf_id<E: std::error::Error + 'static>(index: usize) -> Result<LeafId, Error<E>> {
+    u32::try_from(index)
+        .ok()
+        .and_then(|index| LeafId::try_from(index).ok())
+        .ok_or(Error::LeafIndexOverflow(index))
+}
+
+/// Writes leaves to a type section.
+pub(crate) struct TypeStream<Reloc, W> {
+    writer: W,
+    policy: StringPolicy,
+    section_name: &'static str,
+    next_index: usize,
+    position: usize,
+    end_precomp: Option<leaf::EndPrecomp>,
+    type_server: bool,
+    reloc: PhantomData<Reloc>,
+}
+
+impl<Reloc, W: SectionWrite<Reloc>> TypeStream<Reloc, W> {
+    /// Write the beginning of the section.
+    pub(crate) fn new(
+        writer: W,
+        origin: &TypeOrigin,
+        policy: StringPolicy,
+    ) -> Result<Self, Error<W::Error>> {
+        let mut stream = TypeStream {
+            writer,
+            policy,
+            section_name: origin.section_name(),
+            next_index: origin.first_leaf_index(),
+            position: 0,
+            end_precomp: None,
+            type_server: matches!(origin, TypeOrigin::TypeServer(_)),
+            reloc: PhantomData,
+        };
+        wu(stream.writer.write(&4u32.to_le_bytes()))?;
+        stream.position = 4;
+        match origin {
+            TypeOrigin::TypeServer(type_server) => stream.write_record(type_server)?,
+            TypeOrigin::Precomp(precomp) => stream.write_record(precomp)?,
+            TypeOrigin::EndPrecomp(end_precomp) => stream.end_precomp = Some(end_precomp.clone()),
+            TypeOrigin::Local => (),
+        }
+        Ok(stream)
+    }
+
+    fn write_record<T: WritableRecord<Reloc>>(
+        &mut self,
+        record: &T,
+    ) -> Result<(), Error<W::Error>> {
+        let mut writer = PolicyWriter {
+            inner: &mut self.writer,
+            policy: self.policy,
+        };
+        write_record(record, &mut writer)
+            .map_err(|e| e.at_offset(self.section_name, self.position))?;
+        self.position += record.size() + 4;
+        Ok(())
+    }
+
+    /// Write a leaf as is, and return its leaf index.
+    pub(crate) fn write_leaf(&mut self, leaf: &Leaf) -> Result<LeafId, Error<W::Error>> {
+        if self.type_server {
+            return Err(Error::TypeServerLeaf);
+        }
+        let id = leaf_id(self.next_index)?;
+        let mut writer = PolicyWriter {
+            inner: &mut self.writer,
+            policy: self.policy,
+        };
+        leaf::write(leaf, &mut writer)
+            .map_err(|e| e.in_leaf(id).at_offset(self.section_name, self.position))?;
+        self.position += leaf::size::<Reloc>(leaf);
+        self.next_index += 1;
+        Ok(id)
+    }
+
+    /// Write the end of the section, and return the writer.
+    pub(crate) fn finish(mut self) -> Result<W, Error<W::Error>> {
+        if let Some(end_precomp) = self.end_precomp.take() {
+            self.write_record(&end_precomp)?;
+        }
+        Ok(self.writer)
+    }
+}
+
+/// Writes subsections to a symbol section.
+pub(crate) struct SymbolStream<Reloc, W> {
+    writer: W,
+    policy: StringPolicy,
+    position: usize,
+    subsections: usize,
+    reloc: PhantomData<Reloc>,
+}
+
+impl<Reloc, W: SectionWrite<Reloc>> SymbolStream<Reloc, W> {
+    /// Write the beginning of the section.
+    pub(crate) fn new(mut writer: W, policy: StringPolicy) -> Result<Self, Error<W::Error>> {
+        wu(writer.write(&4u32.to_le_bytes()))?;
+        Ok(SymbolStream {
+            writer,
+            policy,
+            position: 4,
+            subsections: 0,
+            reloc: PhantomData,
+        })
+    }
+
+    pub(crate) fn add_subsection(
+        &mut self,
+        subsection: &Subsection<Reloc>,
+    ) -> Result<(), Error<W::Error>> {
+        let index = self.subsections;
+        let position = self.position;
+        let mut writer = PolicyWriter {
+            inner: &mut self.writer,
+            policy: self.policy,
+        };
+        self.position += write_subsection(index, subsection, &mut writer, position)
+            .map_err(|e| e.in_subsection(index).at_offset(".debug$S", position))?;
+        self.subsections += 1;
+        Ok(())
+    }
+
+    /// Write the string table subsection if it isn't empty, and return the writer.
+    pub(crate) fn finish(mut self, strings: &StringTable) -> Result<W, Error<W::Error>> {
+        if !strings.is_empty() {
+            let len = Writable::<Reloc>::size(strings);
+            let position = self.position;
+            let mut writer = PolicyWriter {
+                inner: &mut self.writer,
+                policy: self.policy,
+            };
+            wu(write_subsection_header(&mut writer, 0xF3, len.try_into()?))?;
+            Writable::<Reloc>::write(strings, &mut writer)
+                .map_err(|e| e.at_offset(".debug$S", position))?;
+            wu(write_padding(&mut writer, len))?;
+        }
+        Ok(self.writer)
+    }
+}
+
+/// Write a subsection at the given section position, and return the size written.
+fn write_subsection<Reloc, W: RecordWrite<Reloc>>(
+    index: usize,
+    subsection: &Subsection<Reloc>,
+    writer: &mut W,
+    position: usize,
+) -> Result<usize, Error<W::Error>> {
+    match subsection {
+        Subsection::Symbols(symbols) => match writer.position() {
+            Some(start) => write_symbols(index, symbols, writer, position, start),
+            None => write_symbols_precomputed(index, symbols, writer, position),
+        },
+        Subsection::FrameData(frame_data) => {
+            let len = 4 + frame_data
+                .entries
+                .iter()
+                .map(Writable::<Reloc>::size)
+                .sum::<usize>();
+            wu(write_subsection_header(writer, 0xF5, len.try_into()?))?;
+            wu(writer.write_rva(&frame_data.address, 0))?;
+            frame_data.entries.write(writer)?;
+            Ok(8 + padded(len))
+        }
+        Subsection::Lines(lines) => {
+            let len = Writable::<Reloc>::size(lines);
+            wu(write_subsection_header(writer, 0xF2, len.try_into()?))?;
+            lines.write(writer)?;
+            Ok(8 + padded(len))
+        }
+        Subsection::FileChecksums(checksums) => {
+            let len = Writable::<Reloc>::size(checksums);
+            wu(write_subsection_header(writer, 0xF4, len.try_into()?))?;
+            checksums.write(writer)?;
+            Ok(8 + padded(len))
+        }
+    }
+}
+
+/// Write a symbol subsection, patching lengths and scope offsets after the symbols are written.
+///
+/// `position` is the section offset and `start` is the writer position of the subsection.
+fn write_symbols<Reloc, W: RecordWrite<Reloc>>(
+    index: usize,
+    symbols: &[Symbol<Reloc>],
+    writer: &mut W,
+    position: usize,
+    start: usize,
+) -> Result<usize, Error<W::Error>> {
+    let offset = |at: usize| u32::try_from(position + at - start);
+    wu(write_subsection_header(writer, 0xF1, 0))?;
+
+    let mut scopes: Vec<usize> = vec![];
+    for (i, symbol) in symbols.iter().enumerate() {
+        let at = writer.position().unwrap_or_default();
+        symbol::write(symbol, writer).map_err(|e| {
+            e.in_symbol(index, i)
+                .at_offset(".debug$S", position + at - start)
+        })?;
+        if symbol::is_scope_end(symbol) {
+            if let Some(begin) = scopes.pop() {
+                wu(writer.patch(begin + 8, &offset(at)?.to_le_bytes()))?;
+            }
+        }
+        if symbol::is_scope_begin(symbol) {
+            if let Some(parent) = scopes.last() {
+                wu(writer.patch(at + 4, &offset(*parent)?.to_le_bytes()))?;
+            }
+            scopes.push(at);
+        }
+    }
+
+    let len = writer.position().unwrap_or_default() - start - 8;
+    wu(writer.patch(start + 4, &u32::try_from(len)?.to_le_bytes()))?;
+    wu(write_padding(writer, len))?;
+    Ok(8 + padded(len))
+}
+
+/// Write a symbol subsection to a writer that can't patch data, computing lengths and scope
+/// offsets in advance.
+fn write_symbols_precomputed<Reloc, W: RecordWrite<Reloc>>(
+    index: usize,
+    symbols: &[Symbol<Reloc>],
+    writer: &mut W,
+    position: usize,
+) -> Result<usize, Error<W::Error>> {
+    let len = symbols.iter().map(symbol::size::<Reloc>).sum::<usize>();
+    wu(write_subsection_header(writer, 0xF1, len.try_into()?))?;
+    let patches = scope_patches(symbols, position + 8)?;
+    let mut patch_writer = PatchWriter {
+        inner: &mut *writer,
+        position: position + 8,
+        patches: &patches,
+    };
+    for (i, symbol) in symbols.iter().enumerate() {
+        let offset = patch_writer.position;
+        symbol::write(symbol, &mut patch_writer)
+            .map_err(|e| e.in_symbol(index, i).at_offset(".debug$S", offset))?;
+    }
+    wu(write_padding(writer, len))?;
+    Ok(8 + padded(len))
+}
+
+fn write_subsection_header<Reloc, W: SectionWrite<Reloc>>(
+    writer: &mut W,
+    subsection_type: u32,
+    len: u32,
+) -> Result<(), W::Error> {
+    writer.write(&subsection_type.to_le_bytes())?;
+    writer.write(&len.to_le_bytes())?;
+    Ok(())
+}
+
+/// Compute the offsets of enclosing scopes and closing symbols for scope symbols.
+///
+/// Returns pairs of section position to patch and the value to write there, ordered by position.
+fn scope_patches<Reloc, E: std::error::Error + 'static>(
+    symbols: &[Symbol<Reloc>],
+    mut position: usize,
+) -> Result<Vec<(usize, u32)>, Error<E>> {
+    let mut patches = vec![];
+    let mut scopes: Vec<usize> = vec![];
+    for symbol in symbols {
+        if symbol::is_scope_end(symbol) {
+            if let Some(begin) = scopes.pop() {
+                patches.push((begin + 8, position.try_into()?));
+            }
+        }
+        if symbol::is_scope_begin(symbol) {
+            let parent = scopes.last().copied().unwrap_or(0);
+            patches.push((position + 4, parent.try_into()?));
+            scopes.push(position);
+        }
+        position += symbol::size::<Reloc>(symbol);
+    }
+    patches.sort_by_key(|(position, _)| *position);
+    Ok(patches)
+}
+
+/// A writer that replaces 4-byte values at given section positions as data passes through.
+struct PatchWriter<'a, W> {
+    inner: &'a mut W,
+    position: usize,
+    patches: &'a [(usize, u32)],
+}
+
+impl<'a, Reloc, W: SectionWrite<Reloc>> SectionWrite<Reloc> for PatchWriter<'a, W> {
+    type Error = W::Error;
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        let start = self.position;
+        let end = start + data.len();
+        self.position = end;
+
+        while let Some((at, _)) = self.patches.first() {
+            if at + 4 > start {
+                break;
+            }
+            self.patches = &self.patches[1..];
+        }
+
+        let overlapping = self.patches.iter().take_while(|(at, _)| *at < end);
+        if overlapping.clone().next().is_none() {
+            return self.inner.write(data);
+        }
+
+        let mut data = data.to_vec();
+        for (at, value) in overlapping {
+            for (i, byte) in value.to_le_bytes().iter().enumerate() {
+                if (start..end).contains(&(at + i)) {
+                    data[at + i - start] = *byte;
+                }
+            }
+        }
+        self.inner.write(&data)
+    }
+
+    fn write_reloc(
+        &mut self,
+        kind: RelocKind,
+        reloc: &Reloc,
+        addend: i64,
+    ) -> Result<(), Self::Error> {
+        self.position += kind.size();
+        self.inner.write_reloc(kind, reloc, addend)
+    }
+}
+
+impl<'a, Reloc, W: RecordWrite<Reloc>> RecordWrite<Reloc> for PatchWriter<'a, W> {
+    fn string_policy(&self) -> StringPolicy {
+        self.inner.string_policy()
+    }
+}
+
+fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn write_padding<Reloc, W: SectionWrite<Reloc>>(
+    writer: &mut W,
+    len: usize,
+) -> Result<(), W::Error> {
+    writer.write(&[0; 3][0..(4 - len % 4) % 4])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file::{ChecksumKind, FileChecksum, FileChecksums};
+    use crate::line::{Block, Line, Lines};
+    use crate::reloc::{SecRel, SectionIndex};
+    use crate::section_write::SectionSink;
+
+    #[test]
+    fn scope_offsets() {
+        let block = || {
+            Symbol::Block32(symbol::Block32 {
+                length: 0,
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                name: "b".to_string(),
+            })
+        };
+        let end = || Symbol::End(symbol::End {});
+        let symbols = vec![block(), block(), end(), end()];
+
+        let patches = scope_patches::<_, std::convert::Infallible>(&symbols, 0x10).unwrap();
+        assert_eq!(
+            patches,
+            vec![(0x14, 0), (0x18, 0x44), (0x2C, 0x10), (0x30, 0x40)]
+        );
+
+        let mut sink = SectionSink::new();
+        let mut writer = PatchWriter {
+            inner: &mut sink,
+            position: 0x10,
+            patches: &patches,
+        };
+        for symbol in &symbols {
+            symbol::write(symbol, &mut writer).unwrap();
+        }
+        assert_eq!(&sink.data[0x08..0x0C], &[0x44, 0, 0, 0]);
+        assert_eq!(&sink.data[0x1C..0x24], &[0x10, 0, 0, 0, 0x40, 0, 0, 0]);
+    }
+
+    /// A writer that can't patch data.
+    struct Unpatchable(SectionSink<()>);
+
+    impl SectionWrite<()> for Unpatchable {
+        type Error = std::convert::Infallible;
+
+        fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+            self.0.write(data)
+        }
+
+        fn write_reloc(
+            &mut self,
+            kind: RelocKind,
+            reloc: &(),
+            addend: i64,
+        ) -> Result<(), Self::Error> {
+            self.0.write_reloc(kind, reloc, addend)
+        }
+    }
+
+    #[test]
+    fn single_pass_symbols() {
+        let block = || {
+            Symbol::Block32(symbol::Block32 {
+                length: 0,
+                offset: SecRel(()),
+                section: SectionIndex(()),
+                name: "b".to_string(),
+            })
+        };
+        let end = || Symbol::End(symbol::End {});
+        let label = Symbol::Label32(symbol::Label32 {
+            offset: SecRel(()),
+            section: SectionIndex(()),
+            flags: 0,
+            name: "l".to_string(),
+        });
+        let symbols = Subsection::Symbols(vec![block(), label, block(), end(), end()]);
+
+        let policy = StringPolicy::default();
+        let mut patched = SymbolStream::new(SectionSink::new(), policy).unwrap();
+        let mut computed = SymbolStream::new(Unpatchable(SectionSink::new()), policy).unwrap();
+        for _ in 0..2 {
+            patched.add_subsection(&symbols).unwrap();
+            computed.add_subsection(&symbols).unwrap();
+        }
+        let strings = StringTable::default();
+        let patched = patched.finish(&strings).unwrap().data;
+        let computed = computed.finish(&strings).unwrap().0.data;
+        assert_eq!(patched, computed);
+
+        // Length of the first subsection, and the parents of the inner blocks.
+        assert_eq!(&patched[0x08..0x0C], &[0x45, 0, 0, 0]);
+        assert_eq!(&patched[0x35..0x39], &[0x0C, 0, 0, 0]);
+        assert_eq!(&patched[0x85..0x89], &[0x5C, 0, 0, 0]);
+    }
+
+    #[test]
+    fn streaming() {
+        let mut writer = CodeviewWriter::new(SectionSink::<()>::new(), SectionSink::new()).unwrap();
+        let id = writer
+            .add_leaf(Leaf::StringId(leaf::StringId {
+                substr: LeafId::null(),
+                content: "a".into(),
+            }))
+            .unwrap();
+        assert_eq!(id, LeafId(0x1000));
+        writer
+            .add_subsection(Subsection::Symbols(vec![Symbol::BuildInfo(
+                symbol::BuildInfo { leaf: id },
+            )]))
+            .unwrap();
+        assert_eq!(writer.add_string("x").unwrap().0, 1);
+
+        let (types, symbols) = writer.finish().unwrap();
+        assert_eq!(
+            types.data,
+            &[4, 0, 0, 0, 8, 0, 0x05, 0x16, 0, 0, 0, 0, b'a', 0]
+        );
+        assert_eq!(
+            symbols.data,
+            &[
+                4, 0, 0, 0, 0xF1, 0, 0, 0, 8, 0, 0, 0, 6, 0, 0x4C, 0x11, 0x00, 0x10, 0, 0, 0xF3, 0,
+                0, 0, 3, 0, 0, 0, 0, b'x', 0, 0,
+            ]
+        );
+    }
+
+    #[test]
+    fn lines() {
+        let mut writer = CodeviewWriter::new(SectionSink::<()>::new(), SectionSink::new()).unwrap();
+        let mut checksums = FileChecksums::default();
+        let a = checksums
+            .add(FileChecksum {
+                name: writer.add_string("a.c").unwrap(),
+                kind: ChecksumKind::None,
+                checksum: vec![],
+            })
+            .unwrap();
+        let b = checksums
+            .add(FileChecksum {
+                name: writer.add_string("b.c").unwrap(),
+                kind: ChecksumKind::Md5,
+                checksum: vec![0x11; 16],
+            })
+            .unwrap();
+        assert_eq!((a.index(), b.index()), (0, 8));
+        writer
+            .add_subsection(Subsection::FileChecksums(checksums))
+            .unwrap();
+        writer
+            .add_subsection(Subsection::Lines(Lines {
+                address: (),
+                code_length: 0x10,
+                blocks: vec![Block {
+                    file: b,
+                    lines: vec![Line {
+                        offset: 4,
+                        line_start: 3,
+                        line_delta: None,
+                        is_statement: true,
+                    }],
+                }],
+            }))
+            .unwrap();
+
+        let (_, symbols) = writer.finish().unwrap();
+        let mut checksum = [0; 24];
+        checksum[0..6].copy_from_slice(&[5, 0, 0, 0, 16, 1]);
+        checksum[6..22].copy_from_slice(&[0x11; 16]);
+        assert_eq!(&symbols.data[0..4], &[4, 0, 0, 0]);
+        assert_eq!(
+            &symbols.data[4..20],
+            &[0xF4, 0, 0, 0, 32, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(&symbols.data[20..44], &checksum);
+        assert_eq!(
+            &symbols.data[44..84],
+            &[
+                0xF2, 0, 0, 0, 32, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0, 0, 0, 8, 0, 0, 0, 1,
+                0, 0, 0, 20, 0, 0, 0, 4, 0, 0, 0, 3, 0, 0, 0x80,
+            ]
+        );
+        assert_eq!(&symbols.data[84..88], &[0xF3, 0, 0, 0]);
+        let relocations: Vec<_> = symbols
+            .relocations
+            .iter()
+            .map(|relocation| (relocation.offset, relocation.kind))
+            .collect();
+        assert_eq!(
+            relocations,
+            [(52, RelocKind::SecRel), (56, RelocKind::Section)]
+        );
+    }
+}